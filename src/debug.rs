@@ -1,11 +1,19 @@
-use std::time::Instant;
+use std::{sync::Arc, time::Instant};
 
 use chrono::Duration;
-use hrdf_parser::Hrdf;
+use hrdf_parser::{CoordinateSystem, Coordinates, Hrdf, Journey, Model};
+use rustc_hash::FxHashSet;
 
 use crate::{
-    routing::{find_reachable_stops_within_time_limit, plan_journey},
-    utils::create_date_time,
+    routing::{
+        find_reachable_stops_within_time_limit, get_exchange_time, is_through_service,
+        plan_earlier_journey, plan_journey, plan_journey_arriving_by, plan_later_journey,
+        plan_next_journeys, AccessibilityProfile, GeneralizedCost, LevelExploration, Raptor,
+        ReliabilityCost, Route, RoutingAlgorithm, RoutingOptions, Scenario, SearchEvent,
+        SearchTrace, StopLocator, TimetableOverlay,
+    },
+    travel_time_matrix::compute_travel_time_matrix,
+    utils::{create_date, create_date_time, create_time, haversine_distance},
 };
 
 const N: u32 = 20;
@@ -16,13 +24,31 @@ pub fn run_debug(hrdf: Hrdf) {
     println!("--- Debug");
     println!("------------------------------------------------------------------------------------------------");
 
-    test_plan_journey(&hrdf);
-    // test_find_reachable_stops_within_time_limit(&hrdf);
+    let algorithm = LevelExploration::new(hrdf.data_storage());
+    // let algorithm = crate::routing::Raptor::new(hrdf.data_storage());
+    // let algorithm = crate::routing::ConnectionScan::new(hrdf.data_storage(), crate::utils::create_date(2024, 6, 1));
+
+    let options = RoutingOptions::default();
+
+    test_plan_journey(&hrdf, &algorithm, &options);
+    // test_find_reachable_stops_within_time_limit(&hrdf, &algorithm, &options);
+    // test_get_exchange_time(&hrdf);
+    // test_compute_travel_time_matrix(&hrdf, &options);
+    // test_search_trace(&hrdf, &algorithm);
+    // test_scenario(&hrdf, &algorithm);
+    // test_timetable_overlay(&hrdf, &algorithm);
+    // test_journey_cost(&hrdf, &algorithm);
+    // test_reliability(&hrdf, &algorithm);
+    // test_paging(&hrdf, &algorithm);
+    // test_accessibility_profile(&hrdf, &algorithm);
+    // test_same_terminus(&hrdf, &algorithm);
+    // test_later_arrival_preferred(&hrdf, &algorithm);
+    // test_stop_locator(&hrdf);
 }
 
 #[allow(dead_code)]
 #[rustfmt::skip]
-fn test_plan_journey(hrdf: &Hrdf) {
+fn test_plan_journey(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm, options: &RoutingOptions) {
     // ------------------------------------------------------------------------------------------------
     // --- 2.0.5
     // ------------------------------------------------------------------------------------------------
@@ -34,34 +60,34 @@ fn test_plan_journey(hrdf: &Hrdf) {
         let verbose = i == 0;
 
         // 1. Petit-Lancy, Les Esserts => Onex, Bandol
-        // plan_journey(hrdf, 8587418, 8593027, create_date_time(2024, 6, 1, 12, 30), verbose);
+        // plan_journey(hrdf, algorithm, options, 8587418, 8593027, create_date_time(2024, 6, 1, 12, 30), verbose).unwrap();
 
         // 2. Petit-Lancy, Les Esserts => Genève-Aéroport
-        // plan_journey(hrdf, 8587418, 8501026, create_date_time(2024, 2, 9, 14, 2), verbose);
+        // plan_journey(hrdf, algorithm, options, 8587418, 8501026, create_date_time(2024, 2, 9, 14, 2), verbose).unwrap();
 
         // 3. Avully, village => Pont-Céard, gare
-        // plan_journey(hrdf, 8587031, 8593189, create_date_time(2024, 7, 13, 16, 43), verbose);
+        // plan_journey(hrdf, algorithm, options, 8587031, 8593189, create_date_time(2024, 7, 13, 16, 43), verbose).unwrap();
 
         // 4. Petit-Lancy, Les Esserts => Vevey, Palud
-        // plan_journey(hrdf, 8587418, 8595120, create_date_time(2024, 9, 17, 5, 59), verbose);
+        // plan_journey(hrdf, algorithm, options, 8587418, 8595120, create_date_time(2024, 9, 17, 5, 59), verbose).unwrap();
 
         // 5. Genève, gare Cornavin => Avusy, village
-        // plan_journey(hrdf, 8587057, 8587032, create_date_time(2024, 10, 18, 20, 10), verbose);
+        // plan_journey(hrdf, algorithm, options, 8587057, 8587032, create_date_time(2024, 10, 18, 20, 10), verbose).unwrap();
 
         // 6. Genève => Bern, Bierhübeli
-        // plan_journey(hrdf, 8501008, 8590028, create_date_time(2024, 11, 22, 6, 59), verbose);
+        // plan_journey(hrdf, algorithm, options, 8501008, 8590028, create_date_time(2024, 11, 22, 6, 59), verbose).unwrap();
 
         // 7. Genève => Zürich HB
-        // plan_journey(hrdf, 8501008, 8503000, create_date_time(2024, 4, 9, 8, 4), verbose);
+        // plan_journey(hrdf, algorithm, options, 8501008, 8503000, create_date_time(2024, 4, 9, 8, 4), verbose).unwrap();
 
         // 8. Zürich HB => Lugano, Genzana
-        // plan_journey(hrdf, 8503000, 8575310, create_date_time(2024, 6, 15, 12, 10), verbose);
+        // plan_journey(hrdf, algorithm, options, 8503000, 8575310, create_date_time(2024, 6, 15, 12, 10), verbose).unwrap();
 
         // 9. Chancy, Douane => Campocologno
-        // plan_journey(hrdf, 8587477, 8509368, create_date_time(2024, 5, 29, 17, 29), verbose);
+        // plan_journey(hrdf, algorithm, options, 8587477, 8509368, create_date_time(2024, 5, 29, 17, 29), verbose).unwrap();
 
         // 10. Chancy, Douane => Sevelen, Post
-        plan_journey(hrdf, 8587477, 8588197, create_date_time(2024, 9, 10, 13, 37), verbose);
+        plan_journey(hrdf, algorithm, options, 8587477, 8588197, create_date_time(2024, 9, 10, 13, 37), verbose).unwrap();
     }
 
     println!("\n{:.2?}", start_time.elapsed() / N);
@@ -69,7 +95,7 @@ fn test_plan_journey(hrdf: &Hrdf) {
 
 #[allow(dead_code)]
 #[rustfmt::skip]
-fn test_find_reachable_stops_within_time_limit(hrdf: &Hrdf) {
+fn test_find_reachable_stops_within_time_limit(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm, options: &RoutingOptions) {
     // 1. Petit-Lancy, Les Esserts (8587418)
     let departure_stop_id = 8587418;
     let departure_at = create_date_time(2024, 6, 1, 12, 30);
@@ -114,7 +140,7 @@ fn test_find_reachable_stops_within_time_limit(hrdf: &Hrdf) {
         let start_time = Instant::now();
 
         for i in 0..N {
-            let routes = find_reachable_stops_within_time_limit(hrdf, departure_stop_id, departure_at, Duration::minutes(time_limit), false);
+            let routes = find_reachable_stops_within_time_limit(hrdf, algorithm, options, departure_stop_id, departure_at, Duration::minutes(time_limit), false).unwrap();
 
             if i == 0 {
                 println!("\n{}", routes.len());
@@ -124,3 +150,474 @@ fn test_find_reachable_stops_within_time_limit(hrdf: &Hrdf) {
         println!("{:.2?}", start_time.elapsed() / N);
     }
 }
+
+/// Checks that the travel time matrix matches the journeys planned one by one.
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_compute_travel_time_matrix(hrdf: &Hrdf, options: &RoutingOptions) {
+    // Petit-Lancy, Les Esserts, Genève, gare Cornavin, Bern, Bierhübeli, Zürich HB, Lugano, Genzana.
+    let stop_ids = [8587418, 8587057, 8590028, 8503000, 8575310];
+    let departure_at = create_date_time(2024, 6, 1, 12, 30);
+
+    let raptor = Raptor::new(hrdf.data_storage());
+    let start_time = Instant::now();
+    let matrix = compute_travel_time_matrix(hrdf, &raptor, &stop_ids, &stop_ids, departure_at, options).unwrap();
+    println!("\n{:.2?}", start_time.elapsed());
+
+    matrix.write_csv(&mut std::io::stdout()).unwrap();
+
+    let algorithm = LevelExploration::new(hrdf.data_storage());
+
+    for (i, &origin) in stop_ids.iter().enumerate() {
+        for (j, &destination) in stop_ids.iter().enumerate() {
+            if origin == destination {
+                continue;
+            }
+
+            let route = plan_journey(hrdf, &algorithm, options, origin, destination, departure_at, false).unwrap();
+            let duration = route.map(|route| route.arrival_at() - departure_at);
+
+            if duration != matrix.duration(i, j) {
+                println!("{} => {}: {:?} != {:?}", origin, destination, duration, matrix.duration(i, j));
+            }
+        }
+    }
+}
+
+/// Checks that the exchange times take precedence in the right order: the line-related ones at the stop, the administration-related
+/// ones at the stop, the stop-related ones, the global line-related ones, the global administration-related ones and finally the default one.
+/// The expected exchange time is worked out from the serde representation of the timetable, without the routing index.
+/// The journey pairs having a journey pair-related exchange time or a through service are skipped.
+#[allow(dead_code)]
+fn test_get_exchange_time(hrdf: &Hrdf) {
+    let data_storage = hrdf.data_storage();
+    let algorithm = LevelExploration::new(data_storage);
+    let options = RoutingOptions::default();
+    let departure_at = create_date_time(2024, 6, 3, 8, 0);
+    let bit_fields = data_storage
+        .bit_fields_by_day()
+        .get(&departure_at.date())
+        .unwrap();
+
+    let exchange_times_line: Vec<serde_json::Value> = data_storage
+        .exchange_times_line()
+        .data()
+        .values()
+        .map(|exchange_time| serde_json::to_value(exchange_time).unwrap())
+        .collect();
+
+    // The stops having line-related exchange times, then Zürich HB, Bern, Genève, Lausanne, Basel SBB.
+    let mut stop_ids: Vec<i32> = exchange_times_line
+        .iter()
+        .filter_map(|exchange_time| exchange_time["stop_id"].as_i64())
+        .map(|stop_id| stop_id as i32)
+        .collect();
+    stop_ids.sort();
+    stop_ids.dedup();
+    stop_ids.truncate(20);
+    stop_ids.extend([8503000, 8507000, 8501008, 8501120, 8500010]);
+
+    let mut counts = [0; 6];
+
+    for stop_id in stop_ids {
+        let Some(stop) = data_storage.stops().find(stop_id) else {
+            continue;
+        };
+        let journeys: Vec<_> = bit_fields
+            .iter()
+            .filter_map(|&bit_field_id| {
+                data_storage
+                    .journeys_by_stop_id_and_bit_field_id()
+                    .get(&(stop_id, bit_field_id))
+            })
+            .flatten()
+            .take(100)
+            .map(|&journey_id| data_storage.journeys().find(journey_id).unwrap())
+            .collect();
+
+        for journey_1 in &journeys {
+            for journey_2 in &journeys {
+                if journey_1.id() == journey_2.id()
+                    || data_storage.exchange_times_journey_map().contains_key(&(
+                        stop_id,
+                        journey_1.id(),
+                        journey_2.id(),
+                    ))
+                    || is_through_service(
+                        data_storage,
+                        algorithm.index(),
+                        stop_id,
+                        journey_1.id(),
+                        journey_2.id(),
+                        departure_at,
+                    )
+                {
+                    continue;
+                }
+
+                let exchange_time = get_exchange_time(
+                    data_storage,
+                    &options,
+                    algorithm.index(),
+                    stop_id,
+                    journey_1.id(),
+                    journey_2.id(),
+                    departure_at,
+                );
+
+                let is_ic = journey_1.transport_type(data_storage).designation() == "IC"
+                    && journey_2.transport_type(data_storage).designation() == "IC";
+                let exchange_time_at_stop =
+                    |(ic, other): (i16, i16)| if is_ic { ic } else { other };
+                let exchange_time_administration = |stop_id: Option<i32>| {
+                    data_storage
+                        .exchange_times_administration_map()
+                        .get(&(
+                            stop_id,
+                            journey_1.administration().into(),
+                            journey_2.administration().into(),
+                        ))
+                        .map(|&id| {
+                            vec![data_storage
+                                .exchange_times_administration()
+                                .find(id)
+                                .unwrap()
+                                .duration()]
+                        })
+                };
+                let exchange_time_line = |is_global: bool| {
+                    let durations = expected_exchange_times_line(
+                        &exchange_times_line,
+                        (!is_global).then_some(stop_id),
+                        journey_1,
+                        journey_2,
+                    );
+                    (!durations.is_empty()).then_some(durations)
+                };
+
+                // The first level defining an exchange time applies.
+                let (level, expected) = [
+                    (0, exchange_time_line(false)),
+                    (1, exchange_time_administration(Some(stop_id))),
+                    (
+                        2,
+                        stop.exchange_time()
+                            .map(|value| vec![exchange_time_at_stop(value)]),
+                    ),
+                    (3, exchange_time_line(true)),
+                    (4, exchange_time_administration(None)),
+                ]
+                .into_iter()
+                .find_map(|(level, expected)| Some((level, expected?)))
+                .unwrap_or_else(|| {
+                    (
+                        5,
+                        vec![exchange_time_at_stop(data_storage.default_exchange_time())],
+                    )
+                });
+
+                assert!(
+                    expected.contains(&exchange_time),
+                    "Stop {stop_id}, journeys {} and {}: {exchange_time} instead of {expected:?} (level {level}).",
+                    journey_1.id(),
+                    journey_2.id(),
+                );
+                counts[level] += 1;
+            }
+        }
+    }
+
+    println!("\nLine at stop, administration at stop, stop, global line, global administration, default: {counts:?}");
+}
+
+/// Returns the durations of the most specific line-related exchange times matching the journeys, several if they are equally specific.
+/// The stop id is None for the global exchange times.
+fn expected_exchange_times_line(
+    exchange_times_line: &[serde_json::Value],
+    stop_id: Option<i32>,
+    journey_1: &Journey,
+    journey_2: &Journey,
+) -> Vec<i16> {
+    let at_stop = stop_id.unwrap_or_default();
+
+    let matches = |line: &serde_json::Value, journey: &Journey| {
+        let line_metadata = journey_metadata_at(journey, "Line", at_stop);
+        let direction = journey_metadata_at(journey, "Direction", at_stop)
+            .and_then(|entry| entry["extra_field_1"].as_str().map(str::to_string))
+            .map(|direction| {
+                if direction == "H" {
+                    "Return"
+                } else {
+                    "Outbound"
+                }
+            });
+
+        let is_line_matching = match line["line_id"].as_str() {
+            None => true,
+            Some(line_id) => line_metadata.is_some_and(|entry| match line_id.strip_prefix('#') {
+                Some(id) => {
+                    entry["resource_id"].as_i64().map(|id| id.to_string()) == Some(id.to_string())
+                }
+                None => entry["extra_field_1"].as_str() == Some(line_id),
+            }),
+        };
+        let is_direction_matching = line["direction"]
+            .as_str()
+            .is_none_or(|expected| direction == Some(expected));
+
+        line["administration"].as_str() == Some(journey.administration())
+            && line["transport_type_id"].as_i64() == Some(journey.transport_type_id().into())
+            && is_line_matching
+            && is_direction_matching
+    };
+
+    let specificity = |line: &serde_json::Value| {
+        usize::from(!line["line_id"].is_null()) + usize::from(!line["direction"].is_null())
+    };
+
+    let candidates: Vec<(usize, i16)> = exchange_times_line
+        .iter()
+        .filter(|exchange_time| exchange_time["stop_id"].as_i64().map(|id| id as i32) == stop_id)
+        .filter(|exchange_time| {
+            matches(&exchange_time["line_1"], journey_1)
+                && matches(&exchange_time["line_2"], journey_2)
+        })
+        .map(|exchange_time| {
+            (
+                specificity(&exchange_time["line_1"]) + specificity(&exchange_time["line_2"]),
+                exchange_time["duration"].as_i64().unwrap() as i16,
+            )
+        })
+        .collect();
+
+    let max_specificity = candidates.iter().map(|&(specificity, _)| specificity).max();
+    candidates
+        .into_iter()
+        .filter(|&(specificity, _)| Some(specificity) == max_specificity)
+        .map(|(_, duration)| duration)
+        .collect()
+}
+
+/// Returns the first metadata entry of the kind (e.g. "Line") that applies to the journey at the stop.
+fn journey_metadata_at(journey: &Journey, kind: &str, stop_id: i32) -> Option<serde_json::Value> {
+    let position_of = |id: &serde_json::Value| {
+        id.as_i64().and_then(|id| {
+            journey
+                .route()
+                .iter()
+                .position(|route_entry| i64::from(route_entry.stop_id()) == id)
+        })
+    };
+    let position = position_of(&stop_id.into())?;
+    let value = serde_json::to_value(journey).unwrap();
+
+    value["metadata"][kind]
+        .as_array()?
+        .iter()
+        .find(|entry| {
+            position_of(&entry["from_stop_id"]).is_none_or(|from| from <= position)
+                && position_of(&entry["until_stop_id"]).is_none_or(|until| position <= until)
+        })
+        .cloned()
+}
+
+/// Dumps the trace of a search, to see why the journeys departing from the departure stop were not taken.
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_search_trace(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    let trace = Arc::new(SearchTrace::new());
+    let mut options = RoutingOptions::default();
+    options.set_event_sink(Some(trace.clone()));
+
+    // Petit-Lancy, Les Esserts => Genève, gare Cornavin.
+    let departure_stop_id = 8587418;
+    plan_journey(hrdf, algorithm, &options, departure_stop_id, 8587057, create_date_time(2024, 6, 1, 12, 30), true).unwrap();
+
+    println!();
+    trace.write_json(&mut std::io::stdout()).unwrap();
+    println!();
+
+    let skipped_departures = trace
+        .events()
+        .into_iter()
+        .filter(|event| matches!(event, SearchEvent::JourneySameTerminus { stop_id, .. } if *stop_id == departure_stop_id))
+        .count();
+    println!("{} departures skipped at the departure stop", skipped_departures);
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_scenario(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    // Olten is closed from 10:00 to 14:00.
+    let mut scenario = Scenario::new();
+    scenario.close_stop(8500218, create_date_time(2024, 6, 1, 10, 0), create_date_time(2024, 6, 1, 14, 0));
+
+    let baseline_options = RoutingOptions::default();
+    let mut disrupted_options = RoutingOptions::default();
+    disrupted_options.set_scenario(Some(Arc::new(scenario)));
+
+    // Bern => Basel SBB.
+    for options in [&baseline_options, &disrupted_options] {
+        plan_journey(hrdf, algorithm, options, 8507000, 8500010, create_date_time(2024, 6, 1, 11, 0), true).unwrap();
+    }
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_timetable_overlay(hrdf: &Hrdf, algorithm: &LevelExploration) {
+    // A hypothetical express bus from Petit-Lancy, Les Esserts to Genève, gare Cornavin.
+    let mut overlay = TimetableOverlay::new();
+    let route = vec![
+        (8587418, None, Some(create_time(12, 35))),
+        (8587057, Some(create_time(12, 47)), None),
+    ];
+    overlay.add_journey(hrdf.data_storage(), "B", Some("X1".to_string()), route, vec![create_date(2024, 6, 1)]).unwrap();
+
+    let baseline_options = RoutingOptions::default();
+    let mut proposal_options = RoutingOptions::default();
+    proposal_options.set_timetable_overlay(Some(Arc::new(overlay)));
+
+    for options in [&baseline_options, &proposal_options] {
+        plan_journey(hrdf, algorithm, options, 8587418, 8587057, create_date_time(2024, 6, 1, 12, 30), true).unwrap();
+        plan_journey_arriving_by(hrdf, algorithm, options, 8587418, 8587057, create_date_time(2024, 6, 1, 12, 50), true).unwrap();
+    }
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_journey_cost(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    // Walking counts twice, the trains are preferred to the buses and each transfer costs 5 minutes.
+    let mut journey_cost = GeneralizedCost::new(2.0, 5.0);
+    journey_cost.set_transport_type_weight("B", 1.2);
+
+    let baseline_options = RoutingOptions::default();
+    let mut weighted_options = RoutingOptions::default();
+    weighted_options.set_journey_cost(Some(Arc::new(journey_cost)));
+
+    // Petit-Lancy, Les Esserts => Genève, gare Cornavin.
+    for options in [&baseline_options, &weighted_options] {
+        plan_journey(hrdf, algorithm, options, 8587418, 8587057, create_date_time(2024, 6, 1, 12, 30), true).unwrap();
+    }
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_reliability(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    // At least 3 minutes must be left when changing.
+    let mut strict_options = RoutingOptions::default();
+    strict_options.set_minimum_transfer_slack(Duration::minutes(3));
+
+    // A missed change costs an hour, a change is safe from 5 minutes of slack, or 10 minutes when arriving by bus.
+    let mut reliability_cost = ReliabilityCost::new(60.0, 5);
+    reliability_cost.set_transport_type_tolerance("B", 10);
+    let mut robust_options = RoutingOptions::default();
+    robust_options.set_journey_cost(Some(Arc::new(reliability_cost.clone())));
+
+    // Bern => Basel SBB.
+    for options in [&RoutingOptions::default(), &strict_options, &robust_options] {
+        if let Some(route) = plan_journey(hrdf, algorithm, options, 8507000, 8500010, create_date_time(2024, 6, 1, 11, 0), true).unwrap() {
+            println!("Risk score: {:.2}", reliability_cost.risk_score(hrdf.data_storage(), options, &route));
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_paging(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    let options = RoutingOptions::default();
+
+    // Petit-Lancy, Les Esserts => Genève, gare Cornavin.
+    let routes = plan_next_journeys(hrdf, algorithm, &options, 8587418, 8587057, create_date_time(2024, 6, 1, 12, 30), 3, true).unwrap();
+
+    // The route after the last one and the route before the first one.
+    plan_later_journey(hrdf, algorithm, &options, routes.last().unwrap(), true).unwrap();
+    plan_earlier_journey(hrdf, algorithm, &options, routes.first().unwrap(), true).unwrap();
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_accessibility_profile(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    // The journeys must have the attribute "NF" along the ridden sections and each change takes 5 minutes more.
+    let required_attributes: FxHashSet<String> = ["NF".to_string()].into_iter().collect();
+    let mut options = RoutingOptions::default();
+    options.set_accessibility_profile(Some(AccessibilityProfile::new(required_attributes.clone(), 5, FxHashSet::default())));
+
+    // Petit-Lancy, Les Esserts => Genève, gare Cornavin and Bern => Basel SBB.
+    for (departure_stop_id, arrival_stop_id) in [(8587418, 8587057), (8507000, 8500010)] {
+        let Some(route) = plan_journey(hrdf, algorithm, &options, departure_stop_id, arrival_stop_id, create_date_time(2024, 6, 1, 12, 30), true).unwrap() else {
+            continue;
+        };
+
+        // The attributes of a section are the ones its journey has from its departure stop to its arrival stop.
+        for section in route.sections().iter().filter(|section| !section.is_walking_trip()) {
+            let attributes: FxHashSet<String> = section.accessibility_attributes().iter().cloned().collect();
+            assert_eq!(attributes, required_attributes, "{} -> {}", section.departure_stop_id(), section.arrival_stop_id());
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_same_terminus(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    // A later journey serving the same stops may overtake an earlier one, RAPTOR does not ignore it either.
+    let raptor = Raptor::new(hrdf.data_storage());
+    let options = RoutingOptions::default();
+
+    // Bern => Basel SBB, Lausanne => Genève and Petit-Lancy, Les Esserts => Genève, gare Cornavin.
+    for (departure_stop_id, arrival_stop_id) in [(8507000, 8500010), (8501120, 8501008), (8587418, 8587057)] {
+        for hour in [7, 13, 18] {
+            let departure_at = create_date_time(2024, 6, 1, hour, 58);
+            let route_1 = plan_journey(hrdf, algorithm, &options, departure_stop_id, arrival_stop_id, departure_at, false).unwrap();
+            let route_2 = plan_journey(hrdf, &raptor, &options, departure_stop_id, arrival_stop_id, departure_at, false).unwrap();
+            assert_eq!(route_1.map(|route| route.arrival_at()), route_2.map(|route| route.arrival_at()), "{} -> {} at {}", departure_stop_id, arrival_stop_id, departure_at);
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_later_arrival_preferred(hrdf: &Hrdf, algorithm: &dyn RoutingAlgorithm) {
+    // Walking counts three times, a route reaching a stop later with less walking must still be explored from it.
+    let mut weighted_options = RoutingOptions::default();
+    weighted_options.set_journey_cost(Some(Arc::new(GeneralizedCost::new(3.0, 0.0))));
+
+    let walking_duration = |route: &Route| -> i64 {
+        route.sections().iter().filter(|section| section.is_walking_trip()).map(|section| i64::from(section.duration().unwrap_or(0))).sum()
+    };
+
+    // Petit-Lancy, Les Esserts => Genève, gare Cornavin and Bern => Basel SBB.
+    for (departure_stop_id, arrival_stop_id) in [(8587418, 8587057), (8507000, 8500010)] {
+        let departure_at = create_date_time(2024, 6, 1, 12, 30);
+        let Some(baseline_route) = plan_journey(hrdf, algorithm, &RoutingOptions::default(), departure_stop_id, arrival_stop_id, departure_at, false).unwrap() else {
+            continue;
+        };
+        let weighted_route = plan_journey(hrdf, algorithm, &weighted_options, departure_stop_id, arrival_stop_id, departure_at, false).unwrap().unwrap();
+
+        // The earliest arrival cannot be beaten, so the cheapest route walks no more.
+        assert!(walking_duration(&weighted_route) <= walking_duration(&baseline_route), "{} -> {}", departure_stop_id, arrival_stop_id);
+    }
+}
+
+#[allow(dead_code)]
+#[rustfmt::skip]
+fn test_stop_locator(hrdf: &Hrdf) {
+    // The stops found in the kd-tree must be the ones found by comparing the distances to all the stops.
+    let stop_locator = StopLocator::new(hrdf.data_storage());
+    let options = RoutingOptions::default();
+
+    // Bern, Genève, Zürich and a point in the Alps far from any stop.
+    for (latitude, longitude) in [(46.94883, 7.43913), (46.21022, 6.14245), (47.37818, 8.54021), (46.53740, 8.12573)] {
+        let point = Coordinates::new(CoordinateSystem::WGS84, latitude, longitude);
+
+        let mut expected: Vec<(i32, f64)> = hrdf.data_storage().stops().entries().into_iter().filter_map(|stop| {
+            let coord = stop.wgs84_coordinates()?;
+            Some((stop.id(), haversine_distance(latitude, longitude, coord.latitude(), coord.longitude()) * 1000.0))
+        }).collect();
+        expected.sort_by(|(_, distance_1), (_, distance_2)| distance_1.total_cmp(distance_2));
+        let count = expected.iter().take_while(|(_, distance)| *distance <= options.maximum_walking_distance()).count();
+        expected.truncate(count.max(1));
+
+        let stops = stop_locator.find_stops_within_distance(hrdf.data_storage(), &options, point, options.maximum_walking_distance());
+        assert_eq!(stops, expected, "({}, {})", latitude, longitude);
+    }
+}
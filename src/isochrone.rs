@@ -4,11 +4,15 @@ mod contour_line;
 mod models;
 mod utils;
 
-use crate::isochrone::utils::haversine_distance;
-use crate::routing::find_reachable_stops_within_time_limit;
+use crate::routing::find_reachable_stops_from_departure_stops;
+use crate::routing::from_search_result;
+use crate::routing::to_search_result;
 use crate::routing::Route;
 use crate::routing::RouteSection;
-use constants::WALKING_SPEED_IN_KILOMETERS_PER_HOUR;
+use crate::routing::RoutingAlgorithm;
+use crate::routing::RoutingOptions;
+use crate::routing::SearchResult;
+use crate::utils::haversine_distance;
 use hrdf_parser::CoordinateSystem;
 use hrdf_parser::Coordinates;
 use hrdf_parser::DataStorage;
@@ -24,13 +28,18 @@ use models::Isochrone;
 use utils::distance_to_time;
 use utils::lv95_to_wgs84;
 use utils::time_to_distance;
-use utils::wgs84_to_lv95;
+pub(crate) use utils::wgs84_to_lv95;
 
 /// Computes the isochrones.
-/// The point of origin is used to find the departure stop (the nearest stop).
+/// The point of origin is used to find the departure stops (the stops within the maximum walking distance, or the nearest stop if there are none).
 /// The departure date and time must be within the timetable period.
+/// A ConnectionScan built for the departure date is well suited as the routing algorithm, unless the options require the level exploration (see RoutingOptions).
+/// If the search is aborted, the error holds the isochrones of the stops reached until then.
+#[allow(clippy::too_many_arguments)]
 pub fn compute_isochrones(
     hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
     origin_point_latitude: f64,
     origin_point_longitude: f64,
     departure_at: NaiveDateTime,
@@ -38,7 +47,7 @@ pub fn compute_isochrones(
     isochrone_interval: Duration,
     display_mode: models::DisplayMode,
     verbose: bool,
-) -> IsochroneMap {
+) -> SearchResult<IsochroneMap> {
     let departure_stop = find_nearest_stop(
         hrdf.data_storage(),
         origin_point_latitude,
@@ -46,29 +55,32 @@ pub fn compute_isochrones(
     );
     let departure_stop_coord = departure_stop.wgs84_coordinates().unwrap();
 
-    // The departure time is calculated according to the time it takes to walk to the departure stop.
-    let (adjusted_departure_at, adjusted_time_limit) = adjust_departure_at(
-        departure_at,
-        time_limit,
+    let departure_stops = find_departure_stops(
+        hrdf.data_storage(),
+        options,
         origin_point_latitude,
         origin_point_longitude,
-        &departure_stop,
+        departure_stop,
+        departure_at,
     );
 
-    let mut routes: Vec<_> = find_reachable_stops_within_time_limit(
+    let (routes, abort_reason) = from_search_result(find_reachable_stops_from_departure_stops(
         hrdf,
-        departure_stop.id(),
-        adjusted_departure_at,
-        adjusted_time_limit,
+        algorithm,
+        options,
+        &departure_stops,
+        departure_at.checked_add_signed(time_limit).unwrap(),
         verbose,
-    )
-    .into_iter()
-    .filter(|route| {
-        // Keeps only stops in Switzerland.
-        let stop_id = route.sections().last().unwrap().arrival_stop_id();
-        stop_id.to_string().starts_with("85")
-    })
-    .collect();
+    ));
+
+    let mut routes: Vec<_> = routes
+        .into_iter()
+        .filter(|route| {
+            // Keeps only stops in Switzerland.
+            let stop_id = route.sections().last().unwrap().arrival_stop_id();
+            stop_id.to_string().starts_with("85")
+        })
+        .collect();
 
     // A false route is created to represent the point of origin in the results.
     let (easting, northing) = wgs84_to_lv95(origin_point_latitude, origin_point_longitude);
@@ -91,10 +103,15 @@ pub fn compute_isochrones(
     routes.push(route);
 
     let data = get_data(routes, departure_at);
-    let bounding_box = get_bounding_box(&data, time_limit);
+    let bounding_box = get_bounding_box(&data, time_limit, options.walking_speed());
 
     let grid = if display_mode == models::DisplayMode::ContourLine {
-        Some(contour_line::create_grid(&data, bounding_box, time_limit))
+        Some(contour_line::create_grid(
+            &data,
+            bounding_box,
+            time_limit,
+            options.walking_speed(),
+        ))
     } else {
         None
     };
@@ -106,11 +123,13 @@ pub fn compute_isochrones(
         let time_limit = Duration::minutes(isochrone_interval.num_minutes() * (i + 1));
 
         let polygons = match display_mode {
-            IsochroneDisplayMode::Circles => circles::get_polygons(&data, time_limit),
+            IsochroneDisplayMode::Circles => {
+                circles::get_polygons(&data, time_limit, options.walking_speed())
+            }
             IsochroneDisplayMode::ContourLine => {
                 let (grid, num_points_x, num_points_y) = grid.as_ref().unwrap();
                 contour_line::get_polygons(
-                    &grid,
+                    grid,
                     *num_points_x,
                     *num_points_y,
                     bounding_box.0,
@@ -122,11 +141,12 @@ pub fn compute_isochrones(
         isochrones.push(Isochrone::new(polygons, time_limit.num_minutes() as u32));
     }
 
-    IsochroneMap::new(
+    let isochrone_map = IsochroneMap::new(
         isochrones,
         departure_stop_coord,
         convert_bounding_box_to_wgs84(bounding_box),
-    )
+    );
+    to_search_result(isochrone_map, abort_reason)
 }
 
 fn find_nearest_stop(
@@ -164,30 +184,64 @@ fn find_nearest_stop(
         .unwrap()
 }
 
-fn adjust_departure_at(
-    departure_at: NaiveDateTime,
-    time_limit: Duration,
+/// Returns the stops within the maximum walking distance of the point of origin, or the nearest stop if there are none.
+/// The departure time of each stop is calculated according to the time it takes to walk to it.
+fn find_departure_stops(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
     origin_point_latitude: f64,
     origin_point_longitude: f64,
-    departure_stop: &Stop,
-) -> (NaiveDateTime, Duration) {
-    let distance = {
-        let coord = departure_stop.wgs84_coordinates().unwrap();
-
-        haversine_distance(
-            origin_point_latitude,
-            origin_point_longitude,
-            coord.latitude(),
-            coord.longitude(),
-        ) * 1000.0
+    nearest_stop: &Stop,
+    departure_at: NaiveDateTime,
+) -> Vec<(i32, NaiveDateTime)> {
+    let to_departure_stop = |stop: &Stop, distance: f64| {
+        let duration = distance_to_time(distance, options.walking_speed());
+        (
+            stop.id(),
+            departure_at.checked_add_signed(duration).unwrap(),
+        )
     };
 
-    let duration = distance_to_time(distance, WALKING_SPEED_IN_KILOMETERS_PER_HOUR);
+    let departure_stops: Vec<_> = data_storage
+        .stops()
+        .entries()
+        .into_iter()
+        // Only considers stops in Switzerland.
+        .filter(|stop| stop.id().to_string().starts_with("85"))
+        // The stops of the timetable overlay can also be departed from.
+        .chain(
+            options
+                .timetable_overlay()
+                .into_iter()
+                .flat_map(|overlay| overlay.stops()),
+        )
+        .filter(|stop| stop.wgs84_coordinates().is_some())
+        .filter_map(|stop| {
+            let distance = distance_to_stop(origin_point_latitude, origin_point_longitude, stop);
+            (distance <= options.maximum_walking_distance())
+                .then(|| to_departure_stop(stop, distance))
+        })
+        .collect();
 
-    let adjusted_departure_at = departure_at.checked_add_signed(duration).unwrap();
-    let adjusted_time_limit = time_limit - duration;
+    if departure_stops.is_empty() {
+        let distance =
+            distance_to_stop(origin_point_latitude, origin_point_longitude, nearest_stop);
+        vec![to_departure_stop(nearest_stop, distance)]
+    } else {
+        departure_stops
+    }
+}
 
-    (adjusted_departure_at, adjusted_time_limit)
+/// Returns the distance between the point of origin and the stop, in meters.
+fn distance_to_stop(origin_point_latitude: f64, origin_point_longitude: f64, stop: &Stop) -> f64 {
+    let coord = stop.wgs84_coordinates().unwrap();
+
+    haversine_distance(
+        origin_point_latitude,
+        origin_point_longitude,
+        coord.latitude(),
+        coord.longitude(),
+    ) * 1000.0
 }
 
 fn get_data(routes: Vec<Route>, departure_at: NaiveDateTime) -> Vec<(Coordinates, Duration)> {
@@ -207,38 +261,39 @@ fn get_data(routes: Vec<Route>, departure_at: NaiveDateTime) -> Vec<(Coordinates
 }
 
 fn get_bounding_box(
-    data: &Vec<(Coordinates, Duration)>,
+    data: &[(Coordinates, Duration)],
     time_limit: Duration,
+    walking_speed: f64,
 ) -> ((f64, f64), (f64, f64)) {
     let min_x = data
         .iter()
         .fold(f64::INFINITY, |result, &(coord, duration)| {
-            let candidate = coord.easting()
-                - time_to_distance(time_limit - duration, WALKING_SPEED_IN_KILOMETERS_PER_HOUR);
+            let candidate =
+                coord.easting() - time_to_distance(time_limit - duration, walking_speed);
             f64::min(result, candidate)
         });
 
     let max_x = data
         .iter()
         .fold(f64::NEG_INFINITY, |result, &(coord, duration)| {
-            let candidate = coord.easting()
-                + time_to_distance(time_limit - duration, WALKING_SPEED_IN_KILOMETERS_PER_HOUR);
+            let candidate =
+                coord.easting() + time_to_distance(time_limit - duration, walking_speed);
             f64::max(result, candidate)
         });
 
     let min_y = data
         .iter()
         .fold(f64::INFINITY, |result, &(coord, duration)| {
-            let candidate = coord.northing()
-                - time_to_distance(time_limit - duration, WALKING_SPEED_IN_KILOMETERS_PER_HOUR);
+            let candidate =
+                coord.northing() - time_to_distance(time_limit - duration, walking_speed);
             f64::min(result, candidate)
         });
 
     let max_y = data
         .iter()
         .fold(f64::NEG_INFINITY, |result, &(coord, duration)| {
-            let candidate = coord.northing()
-                + time_to_distance(time_limit - duration, WALKING_SPEED_IN_KILOMETERS_PER_HOUR);
+            let candidate =
+                coord.northing() + time_to_distance(time_limit - duration, walking_speed);
             f64::max(result, candidate)
         });
 
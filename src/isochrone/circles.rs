@@ -3,20 +3,17 @@ use std::f64::consts::PI;
 use chrono::Duration;
 use hrdf_parser::{CoordinateSystem, Coordinates};
 
-use super::{
-    constants::WALKING_SPEED_IN_KILOMETERS_PER_HOUR,
-    utils::{lv95_to_wgs84, time_to_distance},
-};
+use super::utils::{lv95_to_wgs84, time_to_distance};
 
 pub fn get_polygons(
-    data: &Vec<(Coordinates, Duration)>,
+    data: &[(Coordinates, Duration)],
     time_limit: Duration,
+    walking_speed: f64,
 ) -> Vec<Vec<Coordinates>> {
     data.iter()
         .filter(|(_, duration)| *duration <= time_limit)
         .map(|(center_lv95, duration)| {
-            let distance =
-                time_to_distance(time_limit - *duration, WALKING_SPEED_IN_KILOMETERS_PER_HOUR);
+            let distance = time_to_distance(time_limit - *duration, walking_speed);
 
             generate_lv95_circle_points(center_lv95.easting(), center_lv95.northing(), distance, 18)
                 .into_iter()
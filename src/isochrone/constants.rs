@@ -1,2 +1 @@
-pub const WALKING_SPEED_IN_KILOMETERS_PER_HOUR: f64 = 4.0;
 pub const GRID_SPACING_IN_METERS: f64 = 100.0;
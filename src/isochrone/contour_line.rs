@@ -4,16 +4,17 @@ use hrdf_parser::{CoordinateSystem, Coordinates};
 use kd_tree::{KdPoint, KdTree};
 
 use super::{
-    constants::{GRID_SPACING_IN_METERS, WALKING_SPEED_IN_KILOMETERS_PER_HOUR},
+    constants::GRID_SPACING_IN_METERS,
     utils::{distance_between_2_points, distance_to_time, lv95_to_wgs84, time_to_distance},
 };
 
 use rayon::prelude::*;
 
 pub fn create_grid(
-    data: &Vec<(Coordinates, Duration)>,
+    data: &[(Coordinates, Duration)],
     bounding_box: ((f64, f64), (f64, f64)),
     time_limit: Duration,
+    walking_speed: f64,
 ) -> (Vec<(Coordinates, Duration)>, usize, usize) {
     let num_points_x =
         ((bounding_box.1 .0 - bounding_box.0 .0) / GRID_SPACING_IN_METERS).ceil() as usize;
@@ -41,9 +42,9 @@ pub fn create_grid(
 
                 let coord = Coordinates::new(CoordinateSystem::LV95, x, y);
 
-                let points = tree.within_radius(&[coord.easting(), coord.northing()], time_to_distance(time_limit, WALKING_SPEED_IN_KILOMETERS_PER_HOUR));
+                let points = tree.within_radius(&[coord.easting(), coord.northing()], time_to_distance(time_limit, walking_speed));
 
-                if points.len() == 0 {
+                if points.is_empty() {
                     result.push((coord, time_limit * 2));
                     continue;
                 }
@@ -54,7 +55,7 @@ pub fn create_grid(
                         let distance = distance_between_2_points(coord, point.coord());
 
                         point.duration()
-                            + distance_to_time(distance, WALKING_SPEED_IN_KILOMETERS_PER_HOUR)
+                            + distance_to_time(distance, walking_speed)
                     })
                     .min()
                     .unwrap();
@@ -71,7 +72,7 @@ pub fn create_grid(
 }
 
 pub fn get_polygons(
-    grid: &Vec<(Coordinates, Duration)>,
+    grid: &[(Coordinates, Duration)],
     num_points_x: usize,
     num_points_y: usize,
     min_point: (f64, f64),
@@ -2,13 +2,51 @@ mod debug;
 mod isochrone;
 mod routing;
 mod service;
+mod travel_time_matrix;
 mod utils;
 
 pub use isochrone::compute_isochrones;
+pub use routing::find_reachable_stops_from_departure_stops;
 pub use routing::find_reachable_stops_within_time_limit;
+pub use routing::plan_door_to_door_journey;
+pub use routing::plan_earlier_journey;
 pub use routing::plan_journey;
+pub use routing::plan_journey_arriving_by;
+pub use routing::plan_journey_via;
+pub use routing::plan_journeys_within_departure_window;
+pub use routing::plan_later_journey;
+pub use routing::plan_next_journeys;
+pub use routing::plan_pareto_optimal_journeys;
+pub use routing::AbortReason;
+pub use routing::AccessibilityProfile;
+pub use routing::CancellationToken;
+pub use routing::ConnectionScan;
+pub use routing::EarliestArrivalCost;
+pub use routing::GeneralizedCost;
+pub use routing::JourneyCost;
+pub use routing::LevelExploration;
+pub use routing::PartialRoute;
+pub use routing::Raptor;
+pub use routing::RealTimeFeed;
+pub use routing::RealTimeUpdates;
+pub use routing::ReliabilityCost;
 pub use routing::Route;
 pub use routing::RouteSection;
+pub use routing::RoutingAlgorithm;
+pub use routing::RoutingFilter;
+pub use routing::RoutingOptions;
+pub use routing::Scenario;
+pub use routing::SearchAborted;
+pub use routing::SearchEvent;
+pub use routing::SearchEventSink;
+pub use routing::SearchResult;
+pub use routing::SearchTrace;
+pub use routing::StopLocator;
+pub use routing::TimetableOverlay;
+pub use routing::TimetableOverlayError;
+pub use routing::ViaStop;
+pub use travel_time_matrix::compute_travel_time_matrix;
+pub use travel_time_matrix::TravelTimeMatrix;
 
 use std::{env, error::Error};
 
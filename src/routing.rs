@@ -1,43 +1,347 @@
+mod accessibility;
+mod algorithm;
+mod connection_scan;
 mod connections;
 mod constants;
 mod core;
+mod cost;
 mod display;
+mod door_to_door;
+mod exchange_times_line;
 mod exploration;
+mod field_serializer;
 mod models;
+mod overlay;
+mod paging;
+mod priority_queue;
+mod raptor;
+mod real_time;
+mod reliability;
 mod route_impl;
+mod routing_index;
+mod scenario;
+mod stop_locator;
+mod through_services;
+mod trace;
 mod utils;
 
+pub use algorithm::{LevelExploration, RoutingAlgorithm};
+pub use connection_scan::ConnectionScan;
+pub(crate) use connections::get_exchange_time;
+pub use cost::{EarliestArrivalCost, GeneralizedCost, JourneyCost, PartialRoute};
 use hrdf_parser::Hrdf;
+pub use models::AbortReason;
+pub use models::AccessibilityProfile;
+pub use models::CancellationToken;
 pub use models::RouteResult as Route;
 pub use models::RouteSectionResult as RouteSection;
+pub use models::RoutingFilter;
+pub use models::RoutingOptions;
+pub use models::SearchAborted;
+pub use models::SearchResult;
+pub use models::ViaStop;
+pub use overlay::{TimetableOverlay, TimetableOverlayError};
+pub use raptor::Raptor;
+pub use real_time::{RealTimeFeed, RealTimeUpdates};
+pub use reliability::ReliabilityCost;
+pub use scenario::Scenario;
+pub use stop_locator::StopLocator;
+pub(crate) use through_services::is_through_service;
+pub use trace::{SearchEvent, SearchEventSink, SearchTrace};
+pub(crate) use utils::{from_search_result, map_search_result, to_search_result};
 
-use core::compute_routing;
+use core::{
+    compute_pareto_optimal_routing, compute_profile_routing, compute_routing_backward,
+    compute_routing_via,
+};
 
 use chrono::{Duration, NaiveDateTime};
+use door_to_door::compute_door_to_door_routing;
 use models::RoutingAlgorithmArgs;
+use paging::{compute_earlier_routing, compute_later_routing, compute_next_routings};
 
 /// Finds the fastest route from the departure stop to the arrival stop.
 /// The departure date and time must be within the timetable period.
+/// If the search is aborted (see RoutingOptions), the error holds the best result found until then.
 pub fn plan_journey(
     hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
     departure_stop_id: i32,
     arrival_stop_id: i32,
     departure_at: NaiveDateTime,
     verbose: bool,
-) -> Option<Route> {
-    let result = compute_routing(
+) -> SearchResult<Option<Route>> {
+    let result = map_search_result(
+        algorithm.compute_routing(
+            hrdf.data_storage(),
+            options,
+            departure_stop_id,
+            departure_at,
+            verbose,
+            RoutingAlgorithmArgs::solve_from_departure_stop_to_arrival_stop(arrival_stop_id),
+        ),
+        |mut routes| routes.remove(&arrival_stop_id),
+    );
+
+    if verbose {
+        if let Ok(Some(rou)) = &result {
+            println!();
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds the fastest route from the departure stop to the arrival stop going through the via stops in the given order.
+/// The route stays at each via stop at least its dwell duration, the sections arriving at the via stops give the time spent there.
+/// The departure date and time must be within the timetable period.
+/// The legs departing later than the day after the departure are not found by a ConnectionScan built for the departure date.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_journey_via(
+    hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    via_stops: &[ViaStop],
+    arrival_stop_id: i32,
+    departure_at: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Option<Route>> {
+    let result = compute_routing_via(
+        hrdf.data_storage(),
+        algorithm,
+        options,
+        departure_stop_id,
+        via_stops,
+        arrival_stop_id,
+        departure_at,
+        verbose,
+    );
+
+    if verbose {
+        if let Ok(Some(rou)) = &result {
+            println!();
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds the fastest route from the point of origin to the point of destination (WGS84 coordinates), walking at both ends.
+/// All the stops within the maximum walking distance of a point are considered, or the nearest stop if there are none.
+/// The first and last sections are the walks from and to the points, their stop id is 0.
+/// The stops near the points are found with the stop locator, built once for the timetable.
+/// The departure date and time must be within the timetable period.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_door_to_door_journey(
+    hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    stop_locator: &StopLocator,
+    options: &RoutingOptions,
+    origin_point_latitude: f64,
+    origin_point_longitude: f64,
+    destination_point_latitude: f64,
+    destination_point_longitude: f64,
+    departure_at: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Option<Route>> {
+    let result = compute_door_to_door_routing(
+        hrdf.data_storage(),
+        algorithm,
+        stop_locator,
+        options,
+        origin_point_latitude,
+        origin_point_longitude,
+        destination_point_latitude,
+        destination_point_longitude,
+        departure_at,
+    );
+
+    if verbose {
+        if let Ok(Some(rou)) = &result {
+            println!();
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds the first route departing strictly later than a route returned by plan_journey, between the same stops.
+/// If the search is aborted (see RoutingOptions), the error holds the best result found until then.
+pub fn plan_later_journey(
+    hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    route: &Route,
+    verbose: bool,
+) -> SearchResult<Option<Route>> {
+    let result = compute_later_routing(hrdf.data_storage(), algorithm, options, route, verbose);
+
+    if verbose {
+        if let Ok(Some(rou)) = &result {
+            println!();
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds the route arriving strictly earlier than a route returned by plan_journey, between the same stops, and departing as late as possible.
+/// Several searches are needed, the departures up to a day earlier are considered. They share the deadline and the maximum number of explored routes.
+/// If the search is aborted (see RoutingOptions), the error holds the best result found until then.
+pub fn plan_earlier_journey(
+    hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    route: &Route,
+    verbose: bool,
+) -> SearchResult<Option<Route>> {
+    let result = compute_earlier_routing(hrdf.data_storage(), algorithm, options, route, verbose);
+
+    if verbose {
+        if let Ok(Some(rou)) = &result {
+            println!();
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds up to count routes from the departure stop to the arrival stop, each departing strictly later than the previous one.
+/// The routes are sorted by ascending departure time.
+/// The departure date and time must be within the timetable period.
+/// If the search is aborted (see RoutingOptions), the error holds the routes found until then.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_next_journeys(
+    hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    departure_at: NaiveDateTime,
+    count: usize,
+    verbose: bool,
+) -> SearchResult<Vec<Route>> {
+    let result = compute_next_routings(
+        hrdf.data_storage(),
+        algorithm,
+        options,
+        departure_stop_id,
+        arrival_stop_id,
+        departure_at,
+        count,
+        verbose,
+    );
+
+    if verbose {
+        for rou in result.iter().flatten() {
+            println!();
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds the Pareto-optimal routes from the departure stop to the arrival stop, with respect to the arrival time and the number of connections.
+/// Each returned route arrives earlier than the previous one but requires more connections.
+/// The departure date and time must be within the timetable period.
+pub fn plan_pareto_optimal_journeys(
+    hrdf: &Hrdf,
+    algorithm: &LevelExploration,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    departure_at: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Vec<Route>> {
+    let result = compute_pareto_optimal_routing(
+        hrdf.data_storage(),
+        options,
+        algorithm.index(),
+        departure_stop_id,
+        arrival_stop_id,
+        departure_at,
+        verbose,
+    );
+
+    if verbose {
+        for rou in result.iter().flatten() {
+            println!();
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds all the routes from the departure stop to the arrival stop departing within the time window.
+/// A route is only returned if no other route departs later and arrives at the same time or earlier.
+/// The routes are sorted by ascending departure time.
+/// The departure date and time must be within the timetable period.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_journeys_within_departure_window(
+    hrdf: &Hrdf,
+    algorithm: &LevelExploration,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    departure_at: NaiveDateTime,
+    time_window: Duration,
+    verbose: bool,
+) -> SearchResult<Vec<Route>> {
+    let result = compute_profile_routing(
         hrdf.data_storage(),
+        options,
+        algorithm.index(),
         departure_stop_id,
+        arrival_stop_id,
         departure_at,
+        departure_at.checked_add_signed(time_window).unwrap(),
         verbose,
-        RoutingAlgorithmArgs::solve_from_departure_stop_to_arrival_stop(arrival_stop_id),
-    )
-    .remove(&arrival_stop_id);
+    );
 
     if verbose {
-        if let Some(rou) = &result {
+        for rou in result.iter().flatten() {
             println!();
-            rou.print(hrdf.data_storage());
+            rou.print(hrdf.data_storage(), options);
+        }
+    }
+
+    result
+}
+
+/// Finds the route that leaves the departure stop as late as possible while reaching the arrival stop no later than the arrival time.
+/// The arrival date and time must be within the timetable period.
+pub fn plan_journey_arriving_by(
+    hrdf: &Hrdf,
+    algorithm: &LevelExploration,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    arrival_at: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Option<Route>> {
+    let result = compute_routing_backward(
+        hrdf.data_storage(),
+        options,
+        algorithm.index(),
+        departure_stop_id,
+        arrival_stop_id,
+        arrival_at,
+        verbose,
+    );
+
+    if verbose {
+        if let Ok(Some(rou)) = &result {
+            println!();
+            rou.print(hrdf.data_storage(), options);
         }
     }
 
@@ -49,13 +353,16 @@ pub fn plan_journey(
 #[allow(dead_code)]
 pub fn find_reachable_stops_within_time_limit(
     hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
     departure_stop_id: i32,
     departure_at: NaiveDateTime,
     time_limit: Duration,
     verbose: bool,
-) -> Vec<Route> {
-    let routes = compute_routing(
+) -> SearchResult<Vec<Route>> {
+    let routes = algorithm.compute_routing(
         hrdf.data_storage(),
+        options,
         departure_stop_id,
         departure_at,
         verbose,
@@ -63,5 +370,26 @@ pub fn find_reachable_stops_within_time_limit(
             departure_at.checked_add_signed(time_limit).unwrap(),
         ),
     );
-    routes.into_iter().map(|(_, v)| v).collect()
+    map_search_result(routes, |routes| routes.into_values().collect())
+}
+
+/// Finds all stops that can be reached before the time limit from any of the departure stops, in a single search.
+/// Each departure stop comes with the date and time from which it can be departed, the departure stops themselves are not reachable stops.
+/// The departure dates and times must be within the timetable period.
+pub fn find_reachable_stops_from_departure_stops(
+    hrdf: &Hrdf,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    departure_stops: &[(i32, NaiveDateTime)],
+    time_limit: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Vec<Route>> {
+    let routes = algorithm.compute_routing_from_departure_stops(
+        hrdf.data_storage(),
+        options,
+        departure_stops,
+        verbose,
+        RoutingAlgorithmArgs::solve_from_departure_stop_to_reachable_arrival_stops(time_limit),
+    );
+    map_search_result(routes, |routes| routes.into_values().collect())
 }
@@ -0,0 +1,147 @@
+use std::ops::RangeInclusive;
+
+use hrdf_parser::{DataStorage, Journey, JourneyMetadataType};
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use super::{
+    exchange_times_line::{applicable_positions, JourneyMetadataEntryData},
+    field_serializer::serialize_field,
+};
+
+// hrdf-parser does not expose the attributes (ATTRIBUT) nor the attributes of the journeys and stop connections.
+// They are read through their serde representation.
+
+#[derive(Debug, Deserialize)]
+struct Attribute {
+    id: i32,
+    designation: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttributeStorage {
+    data: FxHashMap<i32, Attribute>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopConnection {
+    stop_id_1: i32,
+    stop_id_2: i32,
+    attribute: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopConnectionStorage {
+    data: FxHashMap<i32, StopConnection>,
+}
+
+/// The designations of the attributes indexed by id and the attributes of the stop connections indexed by stop pair.
+#[derive(Debug, Clone, Default)]
+pub struct Attributes {
+    designations: FxHashMap<i32, String>,
+    stop_connection_attributes: FxHashMap<(i32, i32), i32>,
+}
+
+impl Attributes {
+    pub fn new(data_storage: &DataStorage) -> Self {
+        let designations = serialize_field(data_storage, "attributes")
+            .and_then(|value| serde_json::from_value::<AttributeStorage>(value).ok())
+            .map(|storage| {
+                storage
+                    .data
+                    .into_values()
+                    .map(|attribute| (attribute.id, attribute.designation.trim().to_string()))
+                    .collect()
+            })
+            .unwrap_or_else(|| {
+                log::warn!("Attributes could not be read.");
+                FxHashMap::default()
+            });
+
+        let stop_connection_attributes = serde_json::to_value(data_storage.stop_connections())
+            .ok()
+            .and_then(|value| serde_json::from_value::<StopConnectionStorage>(value).ok())
+            .map(|storage| {
+                storage
+                    .data
+                    .into_values()
+                    // The stop connections without attribute have the attribute 0.
+                    .filter(|stop_connection| stop_connection.attribute != 0)
+                    .map(|stop_connection| {
+                        (
+                            (stop_connection.stop_id_1, stop_connection.stop_id_2),
+                            stop_connection.attribute,
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(|| {
+                log::warn!("Stop connection attributes could not be read.");
+                FxHashMap::default()
+            });
+
+        Self {
+            designations,
+            stop_connection_attributes,
+        }
+    }
+
+    // Functions
+
+    /// Returns the designation of the attribute of the stop connection from stop 1 to stop 2, if any.
+    pub fn stop_connection_attribute(&self, stop_id_1: i32, stop_id_2: i32) -> Option<&str> {
+        let attribute_id = self
+            .stop_connection_attributes
+            .get(&(stop_id_1, stop_id_2))?;
+        self.designations.get(attribute_id).map(String::as_str)
+    }
+}
+
+/// The designations of the attributes of a journey, each with the positions in the route of the journey where it applies.
+#[derive(Debug, Clone, Default)]
+pub struct JourneyAttributes {
+    data: Vec<(String, RangeInclusive<usize>)>,
+}
+
+impl JourneyAttributes {
+    /// The attribute entries are taken from the metadata of the journey.
+    pub fn new(
+        journey: &Journey,
+        metadata: &mut FxHashMap<JourneyMetadataType, Vec<JourneyMetadataEntryData>>,
+        attributes: &Attributes,
+    ) -> Self {
+        let data = metadata
+            .remove(&JourneyMetadataType::Attribute)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let designation = attributes.designations.get(&entry.resource_id?)?;
+                Some((designation.clone(), applicable_positions(journey, &entry)))
+            })
+            .collect();
+
+        Self { data }
+    }
+
+    // Functions
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns true if the attribute applies all the way from position 1 to position 2, each stop to the next one.
+    pub fn has_attribute_between(
+        &self,
+        designation: &str,
+        position_1: usize,
+        position_2: usize,
+    ) -> bool {
+        (position_1..position_2).all(|position| {
+            self.data.iter().any(|(attribute, positions)| {
+                attribute == designation
+                    && positions.contains(&position)
+                    && positions.contains(&(position + 1))
+            })
+        })
+    }
+}
@@ -0,0 +1,95 @@
+use chrono::NaiveDateTime;
+use hrdf_parser::DataStorage;
+use rustc_hash::FxHashMap;
+
+use super::{
+    core::compute_routing,
+    models::{RouteResult, RoutingAlgorithmArgs, RoutingOptions, SearchResult},
+    routing_index::RoutingIndex,
+};
+
+/// A routing algorithm finds the best route from the departure stop to one or many arrival stops.
+pub trait RoutingAlgorithm {
+    /// Returns true if a search can depart at the date and time, any date and time within the timetable period by default.
+    fn supports_departure_at(&self, _departure_at: NaiveDateTime) -> bool {
+        true
+    }
+
+    /// Returns true if the search takes all the routing options into account, see RoutingOptions.
+    fn supports_options(&self, _options: &RoutingOptions) -> bool {
+        true
+    }
+
+    /// Returns the best route found for each arrival stop, indexed by stop id.
+    /// If the search is aborted, the routes found until then are returned in the error.
+    fn compute_routing(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        departure_stop_id: i32,
+        departure_at: NaiveDateTime,
+        verbose: bool,
+        args: RoutingAlgorithmArgs,
+    ) -> SearchResult<FxHashMap<i32, RouteResult>> {
+        self.compute_routing_from_departure_stops(
+            data_storage,
+            options,
+            &[(departure_stop_id, departure_at)],
+            verbose,
+            args,
+        )
+    }
+
+    /// Returns the best route found for each arrival stop, indexed by stop id, departing from any of the departure stops.
+    /// Each departure stop comes with the date and time from which it can be departed, all of them are explored in a single search.
+    /// The departure stops are never arrival stops.
+    fn compute_routing_from_departure_stops(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        departure_stops: &[(i32, NaiveDateTime)],
+        verbose: bool,
+        args: RoutingAlgorithmArgs,
+    ) -> SearchResult<FxHashMap<i32, RouteResult>>;
+}
+
+/// The level-by-level exploration, each level corresponding to one more connection.
+/// The stops and the journeys are indexed once, the index is shared by all the searches.
+#[derive(Debug, Clone)]
+pub struct LevelExploration {
+    index: RoutingIndex,
+}
+
+impl LevelExploration {
+    pub fn new(data_storage: &DataStorage) -> Self {
+        Self {
+            index: RoutingIndex::new(data_storage),
+        }
+    }
+
+    // Getters/Setters
+
+    pub(crate) fn index(&self) -> &RoutingIndex {
+        &self.index
+    }
+}
+
+impl RoutingAlgorithm for LevelExploration {
+    fn compute_routing_from_departure_stops(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        departure_stops: &[(i32, NaiveDateTime)],
+        verbose: bool,
+        args: RoutingAlgorithmArgs,
+    ) -> SearchResult<FxHashMap<i32, RouteResult>> {
+        compute_routing(
+            data_storage,
+            options,
+            &self.index,
+            departure_stops,
+            verbose,
+            args,
+        )
+    }
+}
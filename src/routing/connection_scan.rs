@@ -0,0 +1,671 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use hrdf_parser::{DataStorage, Model};
+use rustc_hash::FxHashMap;
+
+use crate::utils::{add_1_day, add_minutes_to_date_time, create_time, sub_1_day};
+
+use super::{
+    algorithm::RoutingAlgorithm,
+    connections::get_exchange_time,
+    is_through_service,
+    models::{
+        AbortReason, ExplorationBudget, RouteResult, RouteSection, RoutingAlgorithmArgs,
+        RoutingAlgorithmMode, RoutingOptions, SearchResult,
+    },
+    route_impl::sections_to_route_result,
+    routing_index::RoutingIndex,
+    utils::{get_stop_connections, to_search_result},
+};
+
+const MINUTES_PER_DAY: i32 = 1440;
+
+/// Connection Scan Algorithm (CSA), the elementary connections of a service day are scanned once by ascending departure time.
+/// The connections include the journeys of the service day and of the next day, and the end of the journeys of the previous day.
+/// Only the departures on the service day or on the next day can be searched.
+/// Only one label is kept per stop, so the maximum number of transfers may discard a route that a label with more journeys has dominated.
+pub struct ConnectionScan {
+    date: NaiveDate,
+    index: RoutingIndex,
+    exchange_points: Vec<bool>,
+    journey_ids: Vec<i32>,
+    // Sorted by ascending departure time.
+    connections: Vec<ElementaryConnection>,
+    footpaths_by_stop: Vec<Vec<(usize, i16)>>,
+}
+
+/// A journey going from one stop to the next one, the times are in minutes since midnight of the service day.
+struct ElementaryConnection {
+    departure_stop: usize,
+    arrival_stop: usize,
+    departure: i32,
+    arrival: i32,
+    trip: usize,
+    // The position of the departure stop in the route of the journey.
+    position: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Label {
+    Origin {
+        arrival: i32,
+    },
+    Trip {
+        arrival: i32,
+        trip: usize,
+        boarding_stop: usize,
+        boarding_kind: BoardingKind,
+        journey_count: usize,
+    },
+    Walk {
+        arrival: i32,
+        departure_stop: usize,
+        duration: i16,
+        journey_count: usize,
+    },
+}
+
+impl Label {
+    fn arrival(&self) -> i32 {
+        match *self {
+            Label::Origin { arrival } => arrival,
+            Label::Trip { arrival, .. } => arrival,
+            Label::Walk { arrival, .. } => arrival,
+        }
+    }
+
+    fn journey_count(&self) -> usize {
+        match *self {
+            Label::Origin { .. } => 0,
+            Label::Trip { journey_count, .. } => journey_count,
+            Label::Walk { journey_count, .. } => journey_count,
+        }
+    }
+}
+
+/// The label from which a trip was boarded.
+#[derive(Debug, Clone, Copy)]
+enum BoardingKind {
+    Trip,
+    Walk,
+    WalkFromOrigin,
+}
+
+struct Labels {
+    by_trip: Vec<Option<Label>>,
+    by_walk: Vec<Option<Label>>,
+    // Walking from the departure stop alone is not a valid solution, these labels are kept apart.
+    by_walk_from_origin: Vec<Option<Label>>,
+}
+
+impl Labels {
+    fn get(&self, stop: usize, kind: BoardingKind) -> Option<Label> {
+        match kind {
+            BoardingKind::Trip => self.by_trip[stop],
+            BoardingKind::Walk => self.by_walk[stop],
+            BoardingKind::WalkFromOrigin => self.by_walk_from_origin[stop],
+        }
+    }
+
+    /// Returns the earliest arrival at the stop, ignoring the walks from the departure stop.
+    fn arrival(&self, stop: usize) -> i32 {
+        [self.by_trip[stop], self.by_walk[stop]]
+            .into_iter()
+            .flatten()
+            .map(|label| label.arrival())
+            .min()
+            .unwrap_or(i32::MAX)
+    }
+}
+
+impl ConnectionScan {
+    pub fn new(data_storage: &DataStorage, date: NaiveDate) -> Self {
+        let index = RoutingIndex::new(data_storage);
+
+        let exchange_points = (0..index.stop_count() as u32)
+            .map(|stop| {
+                let stop_id = index.stop_id(stop);
+                data_storage
+                    .stops()
+                    .find(stop_id)
+                    .unwrap_or_else(|| panic!("Stop {:?} not found.", stop_id))
+                    .can_be_used_as_exchange_point()
+            })
+            .collect();
+
+        let mut journey_ids = Vec::new();
+        let mut connections = Vec::new();
+
+        for (offset, service_date) in [
+            (-MINUTES_PER_DAY, sub_1_day(date)),
+            (0, date),
+            (MINUTES_PER_DAY, add_1_day(date)),
+        ] {
+            let Some(bit_fields) = data_storage.bit_fields_by_day().get(&service_date) else {
+                continue;
+            };
+
+            for journey in data_storage.journeys().entries() {
+                // If the journey has no bit_field_id, the default value is 0. A value of 0 means that the journey operates every day.
+                if !bit_fields.contains(&journey.bit_field_id().unwrap_or(0)) {
+                    continue;
+                }
+
+                // Sometimes certain stop identifiers don't exist for unknown reasons.
+                let Some(stops) = journey
+                    .route()
+                    .iter()
+                    .map(|route_entry| find_stop(&index, route_entry.stop_id()))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    continue;
+                };
+
+                let first_departure_time = journey.route()[0].departure_time().unwrap();
+                let minutes_of = |time: NaiveTime| {
+                    let minutes = to_minutes(time) + offset;
+                    // The time is on the next day if it is earlier than the first departure time.
+                    if time < first_departure_time {
+                        minutes + MINUTES_PER_DAY
+                    } else {
+                        minutes
+                    }
+                };
+
+                let trip = journey_ids.len();
+                let route = journey.route();
+                let count = connections.len();
+
+                connections.extend((0..route.len() - 1).filter_map(|i| {
+                    let departure = minutes_of((*route[i].departure_time())?);
+                    let arrival = minutes_of((*route[i + 1].arrival_time())?);
+
+                    // Only the end of the journeys of the previous day is kept.
+                    (departure >= 0).then_some(ElementaryConnection {
+                        departure_stop: stops[i],
+                        arrival_stop: stops[i + 1],
+                        departure,
+                        arrival,
+                        trip,
+                        position: i,
+                    })
+                }));
+
+                if connections.len() > count {
+                    journey_ids.push(journey.id());
+                }
+            }
+        }
+
+        connections.sort_by_key(|connection| (connection.departure, connection.arrival));
+
+        let footpaths_by_stop = (0..index.stop_count() as u32)
+            .map(|stop| {
+                get_stop_connections(data_storage, index.stop_id(stop))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|stop_connection| {
+                        find_stop(&index, stop_connection.stop_id_2())
+                            .map(|stop| (stop, stop_connection.duration()))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            date,
+            index,
+            exchange_points,
+            journey_ids,
+            connections,
+            footpaths_by_stop,
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    // Functions
+
+    fn stop_id(&self, stop: usize) -> i32 {
+        self.index.stop_id(stop as u32)
+    }
+
+    /// Returns the minutes since midnight of the service day.
+    fn minutes_since_midnight(&self, date_time: NaiveDateTime) -> i32 {
+        to_minutes(date_time.time())
+            + i32::try_from((date_time.date() - self.date).num_days()).unwrap() * MINUTES_PER_DAY
+    }
+
+    /// Each scanned connection counts as an explored route, if the budget is exhausted the labels set until then are returned with the reason.
+    fn scan(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        origins: &[(usize, i32)],
+        target: Option<usize>,
+        time_limit: i32,
+        budget: &mut ExplorationBudget,
+    ) -> (Labels, Option<AbortReason>) {
+        let stop_count = self.index.stop_count();
+        let midnight = NaiveDateTime::new(self.date, create_time(0, 0));
+
+        let mut labels = Labels {
+            by_trip: vec![None; stop_count],
+            by_walk: vec![None; stop_count],
+            by_walk_from_origin: vec![None; stop_count],
+        };
+        // For each trip, the stop where it was boarded, the label from which it was boarded and the number of journeys once boarded.
+        let mut boardings: Vec<Option<(usize, BoardingKind, usize)>> =
+            vec![None; self.journey_ids.len()];
+
+        for &(origin, departure) in origins {
+            labels.by_trip[origin] = Some(Label::Origin { arrival: departure });
+        }
+
+        for &(origin, departure) in origins {
+            for &(stop, duration) in &self.footpaths_by_stop[origin] {
+                if !options.accepts_stop(self.stop_id(stop))
+                    || !options.accepts_stop_connection(
+                        &self.index,
+                        self.stop_id(origin),
+                        self.stop_id(stop),
+                    )
+                {
+                    continue;
+                }
+
+                let arrival = departure + i32::from(duration);
+
+                if labels.by_walk_from_origin[stop].is_some_and(|label| label.arrival() <= arrival)
+                {
+                    continue;
+                }
+
+                labels.by_walk_from_origin[stop] = Some(Label::Walk {
+                    arrival,
+                    departure_stop: origin,
+                    duration,
+                    journey_count: 0,
+                });
+            }
+        }
+
+        let departure = origins
+            .iter()
+            .map(|&(_, departure)| departure)
+            .min()
+            .unwrap();
+        let start = self
+            .connections
+            .partition_point(|connection| connection.departure < departure);
+
+        for connection in &self.connections[start..] {
+            if let Err(reason) = budget.explore() {
+                return (labels, Some(reason));
+            }
+
+            let bound = target.map_or(time_limit, |t| labels.arrival(t));
+
+            if connection.departure > bound {
+                break;
+            }
+
+            // The journey must be left before a segment without the required attributes.
+            if !options.accepts_journey_segment(
+                &self.index,
+                self.journey_ids[connection.trip],
+                connection.position,
+            ) {
+                boardings[connection.trip] = None;
+                continue;
+            }
+
+            if boardings[connection.trip].is_none() {
+                boardings[connection.trip] =
+                    self.boarding(data_storage, options, &labels, connection, midnight);
+            }
+
+            let Some((boarding_stop, boarding_kind, journey_count)) = boardings[connection.trip]
+            else {
+                continue;
+            };
+
+            let stop = connection.arrival_stop;
+
+            if connection.arrival > bound
+                || labels.by_trip[stop].is_some_and(|label| label.arrival() <= connection.arrival)
+            {
+                continue;
+            }
+
+            labels.by_trip[stop] = Some(Label::Trip {
+                arrival: connection.arrival,
+                trip: connection.trip,
+                boarding_stop,
+                boarding_kind,
+                journey_count,
+            });
+
+            if !self.exchange_points[stop] || !options.accepts_stop(self.stop_id(stop)) {
+                continue;
+            }
+
+            // Walking is only possible after a journey and from a stop that can be used for exchange.
+            for &(next_stop, duration) in &self.footpaths_by_stop[stop] {
+                if !options.accepts_stop(self.stop_id(next_stop))
+                    || !options.accepts_stop_connection(
+                        &self.index,
+                        self.stop_id(stop),
+                        self.stop_id(next_stop),
+                    )
+                {
+                    continue;
+                }
+
+                let arrival = connection.arrival + i32::from(duration);
+
+                if arrival <= bound && arrival < labels.arrival(next_stop) {
+                    labels.by_walk[next_stop] = Some(Label::Walk {
+                        arrival,
+                        departure_stop: stop,
+                        duration,
+                        journey_count,
+                    });
+                }
+            }
+        }
+
+        (labels, None)
+    }
+
+    /// Returns the stop where the trip of the connection can be boarded, the label from which it is boarded and the number of journeys once boarded.
+    fn boarding(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        labels: &Labels,
+        connection: &ElementaryConnection,
+        midnight: NaiveDateTime,
+    ) -> Option<(usize, BoardingKind, usize)> {
+        let stop = connection.departure_stop;
+        let minimum_transfer_slack =
+            i32::try_from(options.minimum_transfer_slack().num_minutes()).unwrap();
+
+        // The journeys cannot be boarded at an avoided stop.
+        if !options.accepts_stop(self.stop_id(stop)) {
+            return None;
+        }
+
+        if options.has_journey_filters() {
+            let journey_id = self.journey_ids[connection.trip];
+            let journey = data_storage
+                .journeys()
+                .find(journey_id)
+                .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id));
+
+            if !options.accepts_journey(data_storage, &self.index, journey, self.stop_id(stop)) {
+                return None;
+            }
+        }
+
+        [
+            BoardingKind::Trip,
+            BoardingKind::Walk,
+            BoardingKind::WalkFromOrigin,
+        ]
+        .into_iter()
+        .filter_map(|kind| labels.get(stop, kind).map(|label| (kind, label)))
+        .find_map(|(kind, label)| {
+            let departure_at = add_minutes_to_date_time(midnight, connection.departure.into());
+
+            let is_through_service = match label {
+                Label::Trip { trip, .. } => is_through_service(
+                    data_storage,
+                    &self.index,
+                    self.stop_id(stop),
+                    self.journey_ids[trip],
+                    self.journey_ids[connection.trip],
+                    departure_at,
+                ),
+                _ => false,
+            };
+
+            // A journey continuing as another journey (through service) is not counted.
+            let journey_count = label.journey_count() + usize::from(!is_through_service);
+
+            if journey_count > options.maximum_number_of_journeys() {
+                return None;
+            }
+
+            let can_board = match label {
+                Label::Origin { arrival } => arrival <= connection.departure,
+                _ if !self.exchange_points[stop] => false,
+                Label::Trip { arrival, trip, .. } => {
+                    // It is checked that there is enough time to embark on the journey (exchange time), leaving the minimum transfer slack.
+                    let exchange_time = get_exchange_time(
+                        data_storage,
+                        options,
+                        &self.index,
+                        self.stop_id(stop),
+                        self.journey_ids[trip],
+                        self.journey_ids[connection.trip],
+                        departure_at,
+                    );
+                    let slack = if is_through_service {
+                        0
+                    } else {
+                        minimum_transfer_slack
+                    };
+                    arrival + i32::from(exchange_time) + slack <= connection.departure
+                }
+                // After walking from a journey, the minimum transfer slack must elapse and the accessibility profile may require more time.
+                Label::Walk { arrival, .. } => {
+                    arrival + minimum_transfer_slack + i32::from(options.additional_exchange_time())
+                        <= connection.departure
+                }
+            };
+
+            can_board.then_some((stop, kind, journey_count))
+        })
+    }
+
+    fn reconstruct_route(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        labels: &Labels,
+        midnight: NaiveDateTime,
+        mut stop: usize,
+        mut kind: BoardingKind,
+    ) -> RouteResult {
+        let mut sections = Vec::new();
+
+        loop {
+            match labels.get(stop, kind).unwrap() {
+                Label::Origin { .. } => break,
+                Label::Trip {
+                    arrival,
+                    trip,
+                    boarding_stop,
+                    boarding_kind,
+                    ..
+                } => {
+                    sections.push(RouteSection::new(
+                        Some(self.journey_ids[trip]),
+                        Some(self.index.journey_index(self.journey_ids[trip])),
+                        self.stop_id(boarding_stop),
+                        boarding_stop as u32,
+                        self.stop_id(stop),
+                        stop as u32,
+                        add_minutes_to_date_time(midnight, arrival.into()),
+                        None,
+                    ));
+                    stop = boarding_stop;
+                    kind = boarding_kind;
+                }
+                Label::Walk {
+                    arrival,
+                    departure_stop,
+                    duration,
+                    ..
+                } => {
+                    sections.push(RouteSection::new(
+                        None,
+                        None,
+                        self.stop_id(departure_stop),
+                        departure_stop as u32,
+                        self.stop_id(stop),
+                        stop as u32,
+                        add_minutes_to_date_time(midnight, arrival.into()),
+                        Some(duration),
+                    ));
+                    stop = departure_stop;
+                    kind = BoardingKind::Trip;
+                }
+            }
+        }
+
+        sections.reverse();
+        // The sections are built with the static timetable.
+        sections_to_route_result(data_storage, options, &self.index, &sections)
+    }
+
+    /// Returns the kind of the best label of the stop, if the stop has been reached by a journey.
+    fn best_label(labels: &Labels, stop: usize) -> Option<BoardingKind> {
+        let by_trip = labels.by_trip[stop].filter(|label| matches!(label, Label::Trip { .. }));
+        let by_walk = labels.by_walk[stop];
+
+        match (by_trip, by_walk) {
+            (Some(l1), Some(l2)) if l2.arrival() < l1.arrival() => Some(BoardingKind::Walk),
+            (Some(_), _) => Some(BoardingKind::Trip),
+            (None, Some(_)) => Some(BoardingKind::Walk),
+            (None, None) => None,
+        }
+    }
+}
+
+impl RoutingAlgorithm for ConnectionScan {
+    fn supports_departure_at(&self, departure_at: NaiveDateTime) -> bool {
+        (0..=1).contains(&(departure_at.date() - self.date).num_days())
+    }
+
+    fn supports_options(&self, options: &RoutingOptions) -> bool {
+        !options.has_level_exploration_options()
+    }
+
+    /// The departure stops whose departure is not on the service day or the next day are ignored.
+    fn compute_routing_from_departure_stops(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        departure_stops: &[(i32, NaiveDateTime)],
+        _verbose: bool,
+        args: RoutingAlgorithmArgs,
+    ) -> SearchResult<FxHashMap<i32, RouteResult>> {
+        let mut result = FxHashMap::default();
+
+        if !self.supports_options(options) {
+            return to_search_result(result, Some(AbortReason::UnsupportedOptions));
+        }
+
+        let origins: Vec<(usize, i32)> = departure_stops
+            .iter()
+            .filter(|(_, departure_at)| self.supports_departure_at(*departure_at))
+            .filter_map(|(stop_id, departure_at)| {
+                let origin = find_stop(&self.index, *stop_id)?;
+                Some((origin, self.minutes_since_midnight(*departure_at)))
+            })
+            .collect();
+
+        if origins.is_empty() {
+            return Ok(result);
+        }
+
+        let midnight = NaiveDateTime::new(self.date, create_time(0, 0));
+        let mut budget = ExplorationBudget::new(options);
+
+        let abort_reason = match args.mode() {
+            RoutingAlgorithmMode::SolveFromDepartureStopToArrivalStop => {
+                let Some(target) = find_stop(&self.index, args.arrival_stop_id()) else {
+                    return Ok(result);
+                };
+
+                if origins.iter().any(|&(origin, _)| origin == target) {
+                    return Ok(result);
+                }
+
+                let (labels, abort_reason) = self.scan(
+                    data_storage,
+                    options,
+                    &origins,
+                    Some(target),
+                    i32::MAX,
+                    &mut budget,
+                );
+
+                if let Some(kind) = Self::best_label(&labels, target) {
+                    result.insert(
+                        args.arrival_stop_id(),
+                        self.reconstruct_route(
+                            data_storage,
+                            options,
+                            &labels,
+                            midnight,
+                            target,
+                            kind,
+                        ),
+                    );
+                }
+
+                abort_reason
+            }
+            RoutingAlgorithmMode::SolveFromDepartureStopToReachableArrivalStops => {
+                let time_limit = self.minutes_since_midnight(args.time_limit());
+
+                let (labels, abort_reason) = self.scan(
+                    data_storage,
+                    options,
+                    &origins,
+                    None,
+                    time_limit,
+                    &mut budget,
+                );
+
+                // The journeys can pass through the avoided stops, but they are not reachable.
+                for stop in (0..self.index.stop_count()).filter(|&stop| {
+                    !origins.iter().any(|&(origin, _)| origin == stop)
+                        && options.accepts_stop(self.stop_id(stop))
+                }) {
+                    if let Some(kind) = Self::best_label(&labels, stop) {
+                        result.insert(
+                            self.stop_id(stop),
+                            self.reconstruct_route(
+                                data_storage,
+                                options,
+                                &labels,
+                                midnight,
+                                stop,
+                                kind,
+                            ),
+                        );
+                    }
+                }
+
+                abort_reason
+            }
+        };
+
+        to_search_result(result, abort_reason)
+    }
+}
+
+fn to_minutes(time: NaiveTime) -> i32 {
+    i32::try_from(time.hour() * 60 + time.minute()).unwrap()
+}
+
+fn find_stop(index: &RoutingIndex, stop_id: i32) -> Option<usize> {
+    index.find_stop_index(stop_id).map(|stop| stop as usize)
+}
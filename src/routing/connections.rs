@@ -1,244 +1,793 @@
-use chrono::{Duration, NaiveDate, NaiveDateTime};
+use chrono::{NaiveDate, NaiveDateTime};
 use hrdf_parser::{timetable_end_date, DataStorage, Journey, Model, TransportType};
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::utils::{
-    add_1_day, add_minutes_to_date_time, count_days_between_two_dates, create_time,
+    add_1_day, add_minutes_to_date_time, count_days_between_two_dates, create_time, sub_1_day,
 };
 
-use super::{models::Route, utils::get_routes_to_ignore};
+use super::{
+    exchange_times_line::exchange_time_line,
+    models::{Route, RouteArena, RoutingOptions},
+    overlay::TimetableOverlay,
+    real_time::{delayed_arrivals_after, delayed_departure_at, operating_date_of_departure},
+    reliability::transfer_slack,
+    routing_index::RoutingIndex,
+    through_services::is_through_service,
+    trace::SearchEvent,
+    utils::{
+        find_journey, find_stop, get_routes_to_ignore, get_routes_to_ignore_backward,
+        hash_route_backward,
+    },
+};
 
 pub fn get_connections(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
     route: &Route,
-    journeys_to_ignore: &FxHashSet<i32>,
+    journeys_to_ignore: &[bool],
 ) -> Vec<Route> {
     next_departures(
         data_storage,
+        options,
+        index,
         route.arrival_stop_id(),
-        route.arrival_at(),
-        Some(get_routes_to_ignore(data_storage, &route)),
+        earliest_departure_at(arena, options, route),
+        Some(get_routes_to_ignore(data_storage, options, arena, route)),
         route.last_section().journey_id(),
     )
     .into_iter()
+    // The journey is looked up in the index once, the new route carries its index.
+    .map(|(journey, journey_departure_at)| {
+        (
+            journey,
+            index.journey_index(journey.id()),
+            journey_departure_at,
+        )
+    })
     // A journey is removed if it has already been explored at a lower connection level.
-    .filter(|(journey, _)| !journeys_to_ignore.contains(&journey.id()))
-    .filter_map(|(journey, journey_departure_at)| {
+    .filter(|&(journey, journey_index, journey_departure_at)| {
+        let is_ignored = journeys_to_ignore[journey_index as usize];
+
+        if is_ignored {
+            options.record_event(|| SearchEvent::JourneyAlreadyExplored {
+                stop_id: route.arrival_stop_id(),
+                journey_id: journey.id(),
+                departure_at: journey_departure_at,
+            });
+        }
+
+        !is_ignored
+    })
+    .filter_map(|(journey, journey_index, journey_departure_at)| {
         route.extend(
+            arena,
             data_storage,
-            journey.id(),
-            journey_departure_at.date(),
-            true,
+            options,
+            index,
+            journey,
+            journey_index,
+            operating_date_of_departure(
+                options,
+                journey,
+                route.arrival_stop_id(),
+                journey_departure_at,
+            ),
         )
     })
     .collect()
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn next_departures<'a>(
     data_storage: &'a DataStorage,
+    options: &'a RoutingOptions,
+    index: &RoutingIndex,
     departure_stop_id: i32,
     departure_at: NaiveDateTime,
     routes_to_ignore: Option<FxHashSet<u64>>,
     previous_journey_id: Option<i32>,
 ) -> Vec<(&'a Journey, NaiveDateTime)> {
-    fn get_journeys(
-        data_storage: &DataStorage,
+    fn get_journeys<'a>(
+        data_storage: &'a DataStorage,
+        options: &'a RoutingOptions,
         date: NaiveDate,
         stop_id: i32,
-    ) -> (Vec<(&Journey, NaiveDateTime)>, NaiveDateTime) {
+    ) -> (Vec<(&'a Journey, NaiveDateTime, NaiveDate)>, NaiveDateTime) {
         let mut max_departure_at = NaiveDateTime::new(date, create_time(0, 0));
 
-        let journeys = get_operating_journeys(data_storage, date, stop_id)
-            .into_iter()
-            .filter(|journey| !journey.is_last_stop(stop_id, true))
-            .map(|journey| {
-                let journey_departure_at = journey.departure_at_of(stop_id, date);
-                if journey_departure_at > max_departure_at {
-                    max_departure_at = journey_departure_at;
-                }
-                (journey, journey_departure_at)
-            })
-            .collect();
+        let journeys =
+            get_operating_journeys(data_storage, options.timetable_overlay(), date, stop_id)
+                .into_iter()
+                .filter(|journey| !journey.is_last_stop(stop_id, true))
+                .map(|journey| {
+                    let journey_departure_at =
+                        delayed_departure_at(options, journey, stop_id, date);
+                    if journey_departure_at > max_departure_at {
+                        max_departure_at = journey_departure_at;
+                    }
+                    (journey, journey_departure_at, date)
+                })
+                // A journey cannot be boarded where it does not stop according to the real-time updates and the scenario.
+                .filter(|&(journey, journey_departure_at, _)| {
+                    options.is_stop_served(journey.id(), date, stop_id, journey_departure_at)
+                })
+                .collect();
         (journeys, max_departure_at)
     }
 
-    let (journeys_1, mut max_depearture_at_journeys_1_adjusted) =
-        get_journeys(data_storage, departure_at.date(), departure_stop_id);
+    if !options.accepts_stop(departure_stop_id) {
+        // The journeys cannot be boarded at an avoided stop.
+        return Vec::new();
+    }
+
+    let (journeys_1, mut max_depearture_at_journeys_1_adjusted) = get_journeys(
+        data_storage,
+        options,
+        departure_at.date(),
+        departure_stop_id,
+    );
     max_depearture_at_journeys_1_adjusted = max_depearture_at_journeys_1_adjusted
-        .checked_add_signed(Duration::hours(-4))
+        .checked_sub_signed(options.lookahead_horizon())
         .unwrap();
 
     let (journeys_2, max_departure_at) = if departure_at > max_depearture_at_journeys_1_adjusted {
         // The journeys of the next day are also loaded.
-        // The maximum departure time is 08:00 the next day.
+        // The maximum departure time is the overnight cutoff (08:00 by default) the next day.
         let departure_date = add_1_day(departure_at.date());
-        let (journeys, _) = get_journeys(data_storage, departure_date, departure_stop_id);
-        let max_departure_at = NaiveDateTime::new(departure_date, create_time(8, 0));
+        let (journeys, _) = get_journeys(data_storage, options, departure_date, departure_stop_id);
+        let max_departure_at = NaiveDateTime::new(departure_date, options.overnight_cutoff());
 
         (journeys, max_departure_at)
     } else {
-        let max_departure_at = if departure_at.time() < create_time(8, 0) {
-            // The maximum departure time is 08:00.
-            NaiveDateTime::new(departure_at.date(), create_time(8, 0))
+        let max_departure_at = if departure_at.time() < options.overnight_cutoff() {
+            // The maximum departure time is the overnight cutoff (08:00 by default).
+            NaiveDateTime::new(departure_at.date(), options.overnight_cutoff())
         } else {
-            // The maximum departure time is 4 hours later.
-            departure_at.checked_add_signed(Duration::hours(4)).unwrap()
+            // The maximum departure time is the lookahead horizon (4 hours by default) later.
+            departure_at
+                .checked_add_signed(options.lookahead_horizon())
+                .unwrap()
         };
 
         (Vec::new(), max_departure_at)
     };
 
-    let mut journeys: Vec<(&Journey, NaiveDateTime)> = [journeys_1, journeys_2]
+    let mut journeys: Vec<(&Journey, NaiveDateTime, NaiveDate)> = [journeys_1, journeys_2]
         .concat()
         .into_iter()
-        .filter(|&(_, journey_departure_at)| {
+        .filter(|&(_, journey_departure_at, _)| {
             // Journeys that depart too early or too late are ignored.
             journey_departure_at >= departure_at && journey_departure_at <= max_departure_at
         })
+        .filter(|&(journey, _, _)| {
+            options.accepts_journey(data_storage, index, journey, departure_stop_id)
+        })
         .collect();
 
     // Journeys are sorted by ascending departure time, allowing them to be filtered correctly afterwards.
-    journeys.sort_by_key(|(_, journey_departure_at)| *journey_departure_at);
+    journeys.sort_by_key(|(_, journey_departure_at, _)| *journey_departure_at);
 
-    let mut routes_to_ignore = routes_to_ignore.unwrap_or_else(FxHashSet::default);
+    let mut routes_to_ignore = routes_to_ignore.unwrap_or_default();
+    let minimum_transfer_slack = options.minimum_transfer_slack().num_minutes();
+    let may_prefer_later_departures = options.journey_cost().may_prefer_later_departures();
+    // The arrivals of the journeys kept so far, for each set of stops served, only compared with real-time updates.
+    let mut kept_arrivals: FxHashMap<u64, Vec<Vec<(i32, NaiveDateTime)>>> = FxHashMap::default();
 
     journeys
         .into_iter()
-        .filter(|(journey, _)| {
-            let hash = journey.hash_route(departure_stop_id).unwrap();
+        // Checked before the journeys having the same terminus are ignored, so that a later one leaving enough slack can be taken.
+        .filter(|&(journey, journey_departure_at, _)| {
+            let Some(id) = previous_journey_id.filter(|_| minimum_transfer_slack > 0) else {
+                return true;
+            };
 
-            if !routes_to_ignore.contains(&hash) {
-                // The journey is the first to have this destination (terminus).
-                routes_to_ignore.insert(hash);
-                true
-            } else {
-                // The journey has the same destination as another journey, but arrives later.
-                // It's ignored.
-                false
+            if is_through_service(
+                data_storage,
+                index,
+                departure_stop_id,
+                id,
+                journey.id(),
+                journey_departure_at,
+            ) {
+                return true;
             }
+
+            let transfer_slack = transfer_slack(
+                data_storage,
+                options,
+                index,
+                id,
+                departure_at,
+                None,
+                departure_stop_id,
+                journey.id(),
+                journey_departure_at,
+            );
+            let is_slack_sufficient = i64::from(transfer_slack) >= minimum_transfer_slack;
+
+            if !is_slack_sufficient {
+                options.record_event(|| SearchEvent::TransferSlackNotMet {
+                    stop_id: departure_stop_id,
+                    previous_journey_id: id,
+                    journey_id: journey.id(),
+                    arrival_at: departure_at,
+                    departure_at: journey_departure_at,
+                    transfer_slack,
+                });
+            }
+
+            is_slack_sufficient
         })
-        .filter(|&(journey, journey_departure_at)| {
+        // Also checked before the journeys having the same terminus are ignored, only a journey that can be boarded makes a later one useless.
+        .filter(|&(journey, journey_departure_at, _)| {
             // It is checked that there is enough time to embark on the journey (exchange time).
-            previous_journey_id.map_or(true, |id| {
+            previous_journey_id.is_none_or(|id| {
                 let exchange_time = get_exchange_time(
                     data_storage,
+                    options,
+                    index,
                     departure_stop_id,
                     id,
                     journey.id(),
                     journey_departure_at,
                 );
-                add_minutes_to_date_time(departure_at, exchange_time.into()) <= journey_departure_at
+                let is_exchange_possible =
+                    add_minutes_to_date_time(departure_at, exchange_time.into())
+                        <= journey_departure_at;
+
+                if !is_exchange_possible {
+                    options.record_event(|| SearchEvent::ExchangeTimeNotMet {
+                        stop_id: departure_stop_id,
+                        previous_journey_id: id,
+                        journey_id: journey.id(),
+                        arrival_at: departure_at,
+                        departure_at: journey_departure_at,
+                        exchange_time,
+                    });
+                }
+
+                is_exchange_possible
             })
         })
+        .filter(|&(journey, journey_departure_at, operating_date)| {
+            let hash = journey.hash_route(departure_stop_id).unwrap();
+
+            let is_ignored = if may_prefer_later_departures {
+                // The journey cost may prefer a later journey, only the journeys serving the same stops as the route already did are ignored.
+                routes_to_ignore.contains(&hash)
+            } else if options
+                .real_time_updates()
+                .is_none_or(|updates| updates.is_empty())
+            {
+                // The journey is the first to have this destination (terminus), the later ones are ignored.
+                !routes_to_ignore.insert(hash)
+            } else {
+                routes_to_ignore.contains(&hash) || {
+                    let arrivals =
+                        delayed_arrivals_after(options, journey, departure_stop_id, operating_date);
+                    let kept = kept_arrivals.entry(hash).or_default();
+
+                    // Delayed, an earlier journey may be overtaken by a later one serving the same stops.
+                    // The later journey is only ignored if an earlier one arrives no later at each of them.
+                    let is_dominated = kept
+                        .iter()
+                        .any(|kept_arrivals| is_arriving_no_later(kept_arrivals, &arrivals));
+
+                    if !is_dominated {
+                        kept.push(arrivals);
+                    }
+                    is_dominated
+                }
+            };
+
+            if is_ignored {
+                options.record_event(|| SearchEvent::JourneySameTerminus {
+                    stop_id: departure_stop_id,
+                    journey_id: journey.id(),
+                    departure_at: journey_departure_at,
+                });
+            }
+
+            !is_ignored
+        })
+        .map(|(journey, journey_departure_at, _)| (journey, journey_departure_at))
         .collect()
 }
 
-pub fn get_operating_journeys(
+/// Returns true if the first arrivals are no later than the second ones at each stop, the stops being served in the same order.
+fn is_arriving_no_later(
+    arrivals_1: &[(i32, NaiveDateTime)],
+    arrivals_2: &[(i32, NaiveDateTime)],
+) -> bool {
+    arrivals_1.len() == arrivals_2.len()
+        && arrivals_1.iter().zip(arrivals_2).all(
+            |(&(stop_id_1, arrival_at_1), &(stop_id_2, arrival_at_2))| {
+                stop_id_1 == stop_id_2 && arrival_at_1 <= arrival_at_2
+            },
+        )
+}
+
+/// After walking from a journey to another stop, the minimum transfer slack and the additional exchange time of the accessibility profile must also elapse.
+/// Changing at the same stop, it is checked by next_departures, together with the exchange time.
+fn earliest_departure_at(
+    arena: &RouteArena,
+    options: &RoutingOptions,
+    route: &Route,
+) -> NaiveDateTime {
+    let is_walked_change = route.last_section().journey_id().is_none()
+        && route
+            .sections_rev(arena)
+            .any(|section| section.journey_id().is_some());
+
+    if is_walked_change {
+        let minutes = options.minimum_transfer_slack().num_minutes()
+            + i64::from(options.additional_exchange_time());
+        add_minutes_to_date_time(route.arrival_at(), minutes)
+    } else {
+        route.arrival_at()
+    }
+}
+
+/// The route must have been built backward, its last section being the earliest one.
+pub fn get_previous_connections(
     data_storage: &DataStorage,
-    date: NaiveDate,
-    stop_id: i32,
-) -> Vec<&Journey> {
-    data_storage
-        .bit_fields_by_stop_id()
-        .get(&stop_id)
-        .map_or(Vec::new(), |bit_fields_1| {
-            let bit_fields_2 = data_storage.bit_fields_by_day().get(&date).unwrap();
-            let bit_fields: Vec<_> = bit_fields_1.intersection(&bit_fields_2).collect();
-
-            bit_fields
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    route: &Route,
+    journeys_to_ignore: &[bool],
+) -> Vec<Route> {
+    previous_arrivals(
+        data_storage,
+        options,
+        index,
+        route.last_section().departure_stop_id(),
+        latest_arrival_at(data_storage, arena, options, route),
+        Some(get_routes_to_ignore_backward(
+            data_storage,
+            options,
+            arena,
+            route,
+        )),
+        route.last_section().journey_id(),
+    )
+    .into_iter()
+    // The journey is looked up in the index once, the new route carries its index.
+    .map(|(journey, journey_arrival_at)| {
+        (
+            journey,
+            index.journey_index(journey.id()),
+            journey_arrival_at,
+        )
+    })
+    // A journey is removed if it has already been explored at a lower connection level.
+    .filter(|&(_, journey_index, _)| !journeys_to_ignore[journey_index as usize])
+    .filter_map(|(journey, journey_index, journey_arrival_at)| {
+        route.extend_backward(
+            arena,
+            data_storage,
+            options,
+            index,
+            journey,
+            journey_index,
+            journey_arrival_at,
+        )
+    })
+    .collect()
+}
+
+/// Counterpart of earliest_departure_at, before walking to a journey the minimum transfer slack and the additional exchange time of the accessibility profile must elapse.
+fn latest_arrival_at(
+    data_storage: &DataStorage,
+    arena: &RouteArena,
+    options: &RoutingOptions,
+    route: &Route,
+) -> NaiveDateTime {
+    let departure_at = route.last_section().departure_at(data_storage, options);
+    let is_walked_change = route.last_section().journey_id().is_none()
+        && route
+            .sections_rev(arena)
+            .any(|section| section.journey_id().is_some());
+
+    if is_walked_change {
+        let minutes = options.minimum_transfer_slack().num_minutes()
+            + i64::from(options.additional_exchange_time());
+        add_minutes_to_date_time(departure_at, -minutes)
+    } else {
+        departure_at
+    }
+}
+
+/// Counterpart of next_departures, returns the journeys arriving at the stop before the given time.
+#[allow(clippy::too_many_arguments)]
+pub fn previous_arrivals<'a>(
+    data_storage: &'a DataStorage,
+    options: &'a RoutingOptions,
+    index: &RoutingIndex,
+    arrival_stop_id: i32,
+    arrival_at: NaiveDateTime,
+    routes_to_ignore: Option<FxHashSet<u64>>,
+    next_journey_id: Option<i32>,
+) -> Vec<(&'a Journey, NaiveDateTime)> {
+    fn get_journeys<'a>(
+        data_storage: &'a DataStorage,
+        options: &'a RoutingOptions,
+        date: NaiveDate,
+        stop_id: i32,
+    ) -> (Vec<(&'a Journey, NaiveDateTime)>, NaiveDateTime) {
+        let mut min_arrival_at = NaiveDateTime::new(add_1_day(date), create_time(0, 0));
+
+        let journeys =
+            get_operating_journeys(data_storage, options.timetable_overlay(), date, stop_id)
                 .into_iter()
-                .map(|&bit_field_id| {
-                    data_storage
-                        .journeys_by_stop_id_and_bit_field_id()
-                        .get(&(stop_id, bit_field_id))
-                        .unwrap()
+                .filter(|journey| {
+                    journey.first_stop_id() != stop_id || journey.is_last_stop(stop_id, false)
                 })
-                .flatten()
-                .map(|&journey_id| {
-                    data_storage
-                        .journeys()
-                        .find(journey_id)
-                        .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id))
+                .map(|journey| {
+                    let journey_arrival_at = journey.arrival_at_of_with_origin(
+                        stop_id,
+                        date,
+                        true,
+                        journey.first_stop_id(),
+                    );
+                    if journey_arrival_at < min_arrival_at {
+                        min_arrival_at = journey_arrival_at;
+                    }
+                    (journey, journey_arrival_at)
                 })
-                .collect()
+                .collect();
+        (journeys, min_arrival_at)
+    }
+
+    if !options.accepts_stop(arrival_stop_id) {
+        // The journeys cannot be left at an avoided stop.
+        return Vec::new();
+    }
+
+    let (journeys_1, mut min_arrival_at_journeys_1_adjusted) =
+        get_journeys(data_storage, options, arrival_at.date(), arrival_stop_id);
+    min_arrival_at_journeys_1_adjusted = min_arrival_at_journeys_1_adjusted
+        .checked_add_signed(options.lookahead_horizon())
+        .unwrap();
+
+    let arrival_date = sub_1_day(arrival_at.date());
+
+    let (journeys_2, min_arrival_at) = if arrival_at < min_arrival_at_journeys_1_adjusted
+        && data_storage.bit_fields_by_day().contains_key(&arrival_date)
+    {
+        // The journeys of the previous day are also loaded.
+        // The minimum arrival time is the evening cutoff (20:00 by default) the previous day.
+        let (journeys, _) = get_journeys(data_storage, options, arrival_date, arrival_stop_id);
+        let min_arrival_at = NaiveDateTime::new(arrival_date, options.evening_cutoff());
+
+        (journeys, min_arrival_at)
+    } else {
+        let min_arrival_at = if arrival_at.time() > options.evening_cutoff() {
+            // The minimum arrival time is the evening cutoff (20:00 by default).
+            NaiveDateTime::new(arrival_at.date(), options.evening_cutoff())
+        } else {
+            // The minimum arrival time is the lookahead horizon (4 hours by default) earlier.
+            arrival_at
+                .checked_sub_signed(options.lookahead_horizon())
+                .unwrap()
+        };
+
+        (Vec::new(), min_arrival_at)
+    };
+
+    let mut journeys: Vec<(&Journey, NaiveDateTime)> = [journeys_1, journeys_2]
+        .concat()
+        .into_iter()
+        .filter(|&(_, journey_arrival_at)| {
+            // Journeys that arrive too early or too late are ignored.
+            journey_arrival_at <= arrival_at && journey_arrival_at >= min_arrival_at
         })
+        .filter(|&(journey, _)| {
+            options.accepts_journey_arriving(data_storage, index, journey, arrival_stop_id)
+        })
+        .collect();
+
+    // Journeys are sorted by descending arrival time, allowing them to be filtered correctly afterwards.
+    journeys.sort_by_key(|(_, journey_arrival_at)| std::cmp::Reverse(*journey_arrival_at));
+
+    let mut routes_to_ignore = routes_to_ignore.unwrap_or_default();
+    let minimum_transfer_slack = options.minimum_transfer_slack().num_minutes();
+
+    journeys
+        .into_iter()
+        // Checked before the journeys having the same origin are ignored, so that an earlier one leaving enough slack can be taken.
+        .filter(|&(journey, journey_arrival_at)| {
+            let Some(id) = next_journey_id.filter(|_| minimum_transfer_slack > 0) else {
+                return true;
+            };
+
+            if is_through_service(
+                data_storage,
+                index,
+                arrival_stop_id,
+                journey.id(),
+                id,
+                arrival_at,
+            ) {
+                return true;
+            }
+
+            let exchange_time = get_exchange_time(
+                data_storage,
+                options,
+                index,
+                arrival_stop_id,
+                journey.id(),
+                id,
+                arrival_at,
+            );
+            let minutes = i64::from(exchange_time) + minimum_transfer_slack;
+            add_minutes_to_date_time(journey_arrival_at, minutes) <= arrival_at
+        })
+        // Also checked before the journeys having the same origin are ignored, only a journey from which the change can be made makes an earlier one useless.
+        .filter(|&(journey, journey_arrival_at)| {
+            // It is checked that there is enough time to embark on the next journey (exchange time).
+            next_journey_id.is_none_or(|id| {
+                if is_through_service(
+                    data_storage,
+                    index,
+                    arrival_stop_id,
+                    journey.id(),
+                    id,
+                    arrival_at,
+                ) {
+                    return true;
+                }
+
+                let exchange_time = get_exchange_time(
+                    data_storage,
+                    options,
+                    index,
+                    arrival_stop_id,
+                    journey.id(),
+                    id,
+                    arrival_at,
+                );
+                add_minutes_to_date_time(journey_arrival_at, exchange_time.into()) <= arrival_at
+            })
+        })
+        .filter(|(journey, _)| {
+            let hash = hash_route_backward(journey, arrival_stop_id).unwrap();
+
+            if !routes_to_ignore.contains(&hash) {
+                // The journey is the last to come from this origin.
+                routes_to_ignore.insert(hash);
+                true
+            } else {
+                // The journey has the same origin as another journey, but departs earlier.
+                // It's ignored.
+                false
+            }
+        })
+        .collect()
+}
+
+/// Returns the distinct departure times from the stop between the two date times, sorted in ascending order.
+pub fn departure_times_between(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    departure_at_from: NaiveDateTime,
+    departure_at_to: NaiveDateTime,
+) -> Vec<NaiveDateTime> {
+    let mut departure_times = Vec::new();
+    let mut date = departure_at_from.date();
+
+    while date <= departure_at_to.date() && data_storage.bit_fields_by_day().contains_key(&date) {
+        departure_times.extend(
+            get_operating_journeys(
+                data_storage,
+                options.timetable_overlay(),
+                date,
+                departure_stop_id,
+            )
+            .into_iter()
+            .filter(|journey| !journey.is_last_stop(departure_stop_id, true))
+            .map(|journey| journey.departure_at_of(departure_stop_id, date))
+            .filter(|&journey_departure_at| {
+                journey_departure_at >= departure_at_from && journey_departure_at <= departure_at_to
+            }),
+        );
+        date = add_1_day(date);
+    }
+
+    departure_times.sort();
+    departure_times.dedup();
+    departure_times
+}
+
+/// The journeys of the timetable overlay operating on the date are added.
+pub fn get_operating_journeys<'a>(
+    data_storage: &'a DataStorage,
+    timetable_overlay: Option<&'a TimetableOverlay>,
+    date: NaiveDate,
+    stop_id: i32,
+) -> Vec<&'a Journey> {
+    let mut journeys =
+        data_storage
+            .bit_fields_by_stop_id()
+            .get(&stop_id)
+            .map_or(Vec::new(), |bit_fields_1| {
+                let bit_fields_2 = data_storage.bit_fields_by_day().get(&date).unwrap();
+                let bit_fields: Vec<_> = bit_fields_1.intersection(bit_fields_2).collect();
+
+                bit_fields
+                    .into_iter()
+                    .flat_map(|&bit_field_id| {
+                        data_storage
+                            .journeys_by_stop_id_and_bit_field_id()
+                            .get(&(stop_id, bit_field_id))
+                            .unwrap()
+                    })
+                    .map(|&journey_id| {
+                        data_storage
+                            .journeys()
+                            .find(journey_id)
+                            .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id))
+                    })
+                    .collect()
+            });
+
+    if let Some(overlay) = timetable_overlay {
+        journeys.extend(overlay.operating_journeys(date, stop_id));
+    }
+    journeys
 }
 
 pub fn get_exchange_time(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
     stop_id: i32,
     journey_id_1: i32,
     journey_id_2: i32,
     departure_at: NaiveDateTime,
 ) -> i16 {
-    let stop = data_storage
-        .stops()
-        .find(stop_id)
-        .unwrap_or_else(|| panic!("Stop {:?} not found.", stop_id));
-    let journey_1 = data_storage
-        .journeys()
-        .find(journey_id_1)
-        .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id_1));
-    let journey_2 = data_storage
-        .journeys()
-        .find(journey_id_2)
-        .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id_2));
-
-    // Fahrtpaarbezogene Umsteigezeiten /-\ Journey pair-related exchange times.
-    if let Some(exchange_time) = exchange_time_journey_pair(
+    // Durchbindungen /-\ Through services, the passengers stay seated.
+    if is_through_service(
         data_storage,
+        index,
         stop_id,
         journey_id_1,
         journey_id_2,
         departure_at,
     ) {
-        return exchange_time;
+        return 0;
     }
 
-    // Linienbezogene Umsteigezeiten an Haltestellen /-\ Line-related exchange times at stops.
+    // The accessibility profile may require more time.
+    exchange_time(
+        data_storage,
+        options,
+        index,
+        stop_id,
+        journey_id_1,
+        journey_id_2,
+        departure_at,
+    ) + options.additional_exchange_time()
+}
+
+fn exchange_time(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    stop_id: i32,
+    journey_id_1: i32,
+    journey_id_2: i32,
+    departure_at: NaiveDateTime,
+) -> i16 {
+    let stop = find_stop(data_storage, options, stop_id)
+        .unwrap_or_else(|| panic!("Stop {:?} not found.", stop_id));
+    let journey_1 = find_journey(data_storage, options, journey_id_1)
+        .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id_1));
+    let journey_2 = find_journey(data_storage, options, journey_id_2)
+        .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id_2));
 
-    // Verwaltungsbezogene Umsteigezeiten an Haltestellen /-\ Administration-related exchange times at stops.
-    if let Some(&id) = data_storage.exchange_times_administration_map().get(&(
-        Some(stop_id),
-        journey_1.administration().into(),
-        journey_2.administration().into(),
-    )) {
-        return data_storage
-            .exchange_times_administration()
-            .find(id)
-            .unwrap_or_else(|| panic!("Exchange time administration {:?} not found.", id))
-            .duration();
-    }
+    exchange_time_by_precedence(
+        |level| match level {
+            // Fahrtpaarbezogene Umsteigezeiten /-\ Journey pair-related exchange times.
+            ExchangeTimeLevel::JourneyPair => exchange_time_journey_pair(
+                data_storage,
+                stop_id,
+                journey_id_1,
+                journey_id_2,
+                departure_at,
+            ),
+            // Linienbezogene Umsteigezeiten an Haltestellen /-\ Line-related exchange times at stops.
+            ExchangeTimeLevel::LineAtStop => {
+                exchange_time_line(index, stop_id, journey_1, journey_2, false)
+            }
+            // Verwaltungsbezogene Umsteigezeiten an Haltestellen /-\ Administration-related exchange times at stops.
+            ExchangeTimeLevel::AdministrationAtStop => {
+                exchange_time_administration(data_storage, Some(stop_id), journey_1, journey_2)
+            }
+            // Haltestellenbezogene Umsteigezeiten /-\ Stop-related exchange times.
+            ExchangeTimeLevel::Stop => stop.exchange_time().map(|exchange_time| {
+                exchange_time_at_stop(
+                    exchange_time,
+                    journey_1.transport_type(data_storage),
+                    journey_2.transport_type(data_storage),
+                )
+            }),
+            // Linienbezogene Umsteigezeiten (global) /-\ Line-related exchange times (global).
+            ExchangeTimeLevel::GlobalLine => {
+                exchange_time_line(index, stop_id, journey_1, journey_2, true)
+            }
+            // Verwaltungsbezogene Umsteigezeiten (global) /-\ Administration-related exchange times (global).
+            ExchangeTimeLevel::GlobalAdministration => {
+                exchange_time_administration(data_storage, None, journey_1, journey_2)
+            }
+        },
+        // Standardumsteigezeit /-\ Standard exchange time.
+        || {
+            exchange_time_at_stop(
+                data_storage.default_exchange_time(),
+                journey_1.transport_type(data_storage),
+                journey_2.transport_type(data_storage),
+            )
+        },
+    )
+}
 
-    // Haltestellenbezogene Umsteigezeiten /-\ Stop-related exchange times.
-    if let Some(exchange_time) = stop.exchange_time() {
-        return exchange_time_at_stop(
-            exchange_time,
-            journey_1.transport_type(data_storage),
-            journey_2.transport_type(data_storage),
-        );
-    }
+/// The levels of the exchange times, from the highest precedence to the lowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExchangeTimeLevel {
+    JourneyPair,
+    LineAtStop,
+    AdministrationAtStop,
+    Stop,
+    GlobalLine,
+    GlobalAdministration,
+}
 
-    // Linienbezogene Umsteigezeiten (global) /-\ Line-related exchange times (global).
+const EXCHANGE_TIME_LEVELS: [ExchangeTimeLevel; 6] = [
+    ExchangeTimeLevel::JourneyPair,
+    ExchangeTimeLevel::LineAtStop,
+    ExchangeTimeLevel::AdministrationAtStop,
+    ExchangeTimeLevel::Stop,
+    ExchangeTimeLevel::GlobalLine,
+    ExchangeTimeLevel::GlobalAdministration,
+];
 
-    // Verwaltungsbezogene Umsteigezeiten (global) /-\ Administration-related exchange times (global).
-    if let Some(&id) = data_storage.exchange_times_administration_map().get(&(
-        None,
+/// Returns the exchange time of the first level defining one, the standard exchange time otherwise.
+/// The levels are only looked up until one of them applies.
+fn exchange_time_by_precedence(
+    exchange_time_of: impl FnMut(ExchangeTimeLevel) -> Option<i16>,
+    standard_exchange_time: impl FnOnce() -> i16,
+) -> i16 {
+    EXCHANGE_TIME_LEVELS
+        .into_iter()
+        .find_map(exchange_time_of)
+        .unwrap_or_else(standard_exchange_time)
+}
+
+/// The stop id is None for the global exchange times.
+fn exchange_time_administration(
+    data_storage: &DataStorage,
+    stop_id: Option<i32>,
+    journey_1: &Journey,
+    journey_2: &Journey,
+) -> Option<i16> {
+    let &id = data_storage.exchange_times_administration_map().get(&(
+        stop_id,
         journey_1.administration().into(),
         journey_2.administration().into(),
-    )) {
-        return data_storage
+    ))?;
+
+    Some(
+        data_storage
             .exchange_times_administration()
             .find(id)
             .unwrap_or_else(|| panic!("Exchange time administration {:?} not found.", id))
-            .duration();
-    }
-
-    // Standardumsteigezeit /-\ Standard exchange time.
-    exchange_time_at_stop(
-        data_storage.default_exchange_time(),
-        journey_1.transport_type(data_storage),
-        journey_2.transport_type(data_storage),
+            .duration(),
     )
 }
 
@@ -249,13 +798,10 @@ fn exchange_time_journey_pair(
     journey_id_2: i32,
     departure_at: NaiveDateTime,
 ) -> Option<i16> {
-    let Some(exchange_times) =
+    let exchange_times =
         data_storage
             .exchange_times_journey_map()
-            .get(&(stop_id, journey_id_1, journey_id_2))
-    else {
-        return None;
-    };
+            .get(&(stop_id, journey_id_1, journey_id_2))?;
 
     // "2 +" because a 2-bit offset is mandatory.
     // "- 1" to obtain an index.
@@ -298,3 +844,49 @@ fn exchange_time_at_stop(
         exchange_time.1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{exchange_time_by_precedence, ExchangeTimeLevel, EXCHANGE_TIME_LEVELS};
+
+    const STANDARD_EXCHANGE_TIME: i16 = 99;
+
+    /// Each level and all the ones below it define an exchange time, the level itself must apply.
+    #[test]
+    fn each_level_overrides_the_next_ones() {
+        for (i, &level) in EXCHANGE_TIME_LEVELS.iter().enumerate() {
+            let mut looked_up_levels = Vec::new();
+
+            let exchange_time = exchange_time_by_precedence(
+                |other_level| {
+                    looked_up_levels.push(other_level);
+                    let j = EXCHANGE_TIME_LEVELS
+                        .iter()
+                        .position(|&l| l == other_level)
+                        .unwrap();
+                    (j >= i).then_some(j as i16)
+                },
+                || STANDARD_EXCHANGE_TIME,
+            );
+
+            assert_eq!(exchange_time, i as i16, "{:?}", level);
+            // The lower levels are not looked up.
+            assert_eq!(looked_up_levels, EXCHANGE_TIME_LEVELS[..=i]);
+        }
+    }
+
+    #[test]
+    fn standard_exchange_time_applies_last() {
+        assert_eq!(
+            exchange_time_by_precedence(|_| None, || STANDARD_EXCHANGE_TIME),
+            STANDARD_EXCHANGE_TIME
+        );
+        assert_eq!(
+            exchange_time_by_precedence(
+                |level| (level == ExchangeTimeLevel::GlobalAdministration).then_some(1),
+                || STANDARD_EXCHANGE_TIME,
+            ),
+            1
+        );
+    }
+}
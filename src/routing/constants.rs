@@ -1 +1,8 @@
-pub const MAXIMUM_NUMBER_OF_EXPLORABLE_CONNECTIONS: i32 = 10;
+pub const DEFAULT_MAXIMUM_NUMBER_OF_TRANSFERS: usize = 9;
+pub const DEFAULT_LOOKAHEAD_HORIZON_IN_MINUTES: i64 = 240;
+pub const DEFAULT_OVERNIGHT_CUTOFF_HOUR: u32 = 8;
+pub const DEFAULT_EVENING_CUTOFF_HOUR: u32 = 20;
+pub const DEFAULT_MAXIMUM_WALKING_DISTANCE_IN_METERS: f64 = 500.0;
+pub const DEFAULT_WALKING_SPEED_IN_KILOMETERS_PER_HOUR: f64 = 4.0;
+pub const EXPLORATION_CHECK_INTERVAL: usize = 64;
+pub const MAXIMUM_EARLIER_DEPARTURE_LOOKBEHIND_IN_HOURS: i64 = 24;
@@ -1,51 +1,447 @@
 use chrono::NaiveDateTime;
-use hrdf_parser::DataStorage;
-use rustc_hash::{FxHashMap, FxHashSet};
+use hrdf_parser::{DataStorage, Model};
+use rustc_hash::FxHashMap;
+
+use crate::utils::add_minutes_to_date_time;
 
 use super::{
-    connections::next_departures,
-    constants::MAXIMUM_NUMBER_OF_EXPLORABLE_CONNECTIONS,
-    exploration::explore_routes,
-    models::{Route, RouteResult, RouteSection, RoutingAlgorithmArgs, RoutingAlgorithmMode},
-    utils::{get_stop_connections, sort_routes},
+    algorithm::RoutingAlgorithm,
+    connections::{departure_times_between, next_departures, previous_arrivals},
+    cost::PartialRoute,
+    exploration::{explore_routes, explore_routes_backward},
+    models::{
+        AbortReason, ExplorationBudget, Route, RouteArena, RouteResult, RouteSection,
+        RoutingAlgorithmArgs, RoutingAlgorithmMode, RoutingOptions, SearchResult, ViaStop,
+    },
+    real_time::{delayed_arrival_at, operating_date_of_arrival, operating_date_of_departure},
+    routing_index::RoutingIndex,
+    utils::{
+        get_stop_connections_from, get_stop_connections_to, sort_routes, sort_routes_backward,
+        to_search_result,
+    },
 };
 
 pub fn compute_routing(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    departure_stops: &[(i32, NaiveDateTime)],
+    verbose: bool,
+    args: RoutingAlgorithmArgs,
+) -> SearchResult<FxHashMap<i32, RouteResult>> {
+    // The stops and the journeys of the timetable overlay are indexed for this search only.
+    let index = &*index.with_timetable_overlay(options.timetable_overlay());
+    let mut arena = RouteArena::new();
+
+    let (solutions, abort_reason) = explore_connection_levels(
+        data_storage,
+        options,
+        index,
+        &mut arena,
+        departure_stops,
+        verbose,
+        &args,
+        None,
+        &mut ExplorationBudget::new(options),
+        |_| {},
+    );
+
+    let result = solutions
+        .into_iter()
+        .enumerate()
+        .filter_map(|(stop_index, route)| Some((index.stop_id(stop_index as u32), route?)))
+        // A departure stop can be reached from another departure stop, but it is not an arrival stop.
+        .filter(|(stop_id, _)| !departure_stops.iter().any(|&(id, _)| id == *stop_id))
+        .map(|(k, v)| (k, v.to_route_result(&arena, data_storage, options, index)))
+        .collect();
+    to_search_result(result, abort_reason)
+}
+
+/// Returns one route for each non-dominated (arrival time, number of connections) pair, sorted by ascending number of connections.
+pub fn compute_pareto_optimal_routing(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
     departure_stop_id: i32,
+    arrival_stop_id: i32,
     departure_at: NaiveDateTime,
     verbose: bool,
-    args: RoutingAlgorithmArgs,
-) -> FxHashMap<i32, RouteResult> {
-    let mut routes = create_initial_routes(data_storage, departure_stop_id, departure_at);
-    let mut journeys_to_ignore = FxHashSet::default();
-    let mut earliest_arrival_by_stop_id = FxHashMap::default();
-    let mut solutions = FxHashMap::default();
+) -> SearchResult<Vec<RouteResult>> {
+    // The stops and the journeys of the timetable overlay are indexed for this search only.
+    let index = &*index.with_timetable_overlay(options.timetable_overlay());
+    let Some(arrival_stop_index) = index.find_stop_index(arrival_stop_id) else {
+        return Ok(Vec::new());
+    };
+
+    let args = RoutingAlgorithmArgs::solve_from_departure_stop_to_arrival_stop(arrival_stop_id);
+    let mut arena = RouteArena::new();
+    let mut candidates: Vec<Route> = Vec::new();
+
+    let (_, abort_reason) = explore_connection_levels(
+        data_storage,
+        options,
+        index,
+        &mut arena,
+        &[(departure_stop_id, departure_at)],
+        verbose,
+        &args,
+        None,
+        &mut ExplorationBudget::new(options),
+        |solutions| {
+            // The best solution found at each connection level is a candidate.
+            if let Some(solution) = &solutions[arrival_stop_index as usize] {
+                candidates.push(solution.clone());
+            }
+        },
+    );
+
+    candidates.sort_by_key(|route| (route.connection_count(), route.arrival_at()));
+    candidates.dedup_by_key(|route| (route.connection_count(), route.arrival_at()));
+
+    let result = candidates
+        .iter()
+        .filter(|candidate| {
+            !candidates
+                .iter()
+                .any(|other| is_dominating_solution(other, candidate))
+        })
+        .map(|route| route.to_route_result(&arena, data_storage, options, index))
+        .collect();
+    to_search_result(result, abort_reason)
+}
+
+/// Returns the routes departing within the time window that are not dominated by a route departing later and arriving no later, sorted by ascending departure time.
+/// The departure times are explored from the latest to the earliest, the earliest arrival times found for later departures are kept to prune the exploration of earlier departures.
+/// They are kept for each connection level (as in rRAPTOR), a route is only pruned by a later departure that reached the stop no later with no more connections.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_profile_routing(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    departure_at_from: NaiveDateTime,
+    departure_at_to: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Vec<RouteResult>> {
+    // The stops and the journeys of the timetable overlay are indexed for this search only.
+    let index = &*index.with_timetable_overlay(options.timetable_overlay());
+    let Some(arrival_stop_index) = index.find_stop_index(arrival_stop_id) else {
+        return Ok(Vec::new());
+    };
+
+    let args = RoutingAlgorithmArgs::solve_from_departure_stop_to_arrival_stop(arrival_stop_id);
+    let mut earliest_arrival_by_level = Vec::new();
+    // The budget is shared by the searches of all the departure times.
+    let mut budget = ExplorationBudget::new(options);
+    let mut result: Vec<RouteResult> = Vec::new();
+    let mut abort_reason = None;
+
+    let departure_times = departure_times_between(
+        data_storage,
+        options,
+        departure_stop_id,
+        departure_at_from,
+        departure_at_to,
+    );
+
+    for departure_at in departure_times.into_iter().rev() {
+        let mut arena = RouteArena::new();
+
+        let (mut solutions, reason) = explore_connection_levels(
+            data_storage,
+            options,
+            index,
+            &mut arena,
+            &[(departure_stop_id, departure_at)],
+            verbose,
+            &args,
+            Some(&mut earliest_arrival_by_level),
+            &mut budget,
+            |_| {},
+        );
+
+        let solution = solutions
+            .swap_remove(arrival_stop_index as usize)
+            .map(|solution| solution.to_route_result(&arena, data_storage, options, index))
+            .filter(|solution| solution.departure_at() <= departure_at_to);
+
+        // The route must arrive earlier than the route departing after it, otherwise it is dominated.
+        if let Some(solution) = solution.filter(|solution| {
+            result
+                .last()
+                .is_none_or(|route| solution.arrival_at() < route.arrival_at())
+        }) {
+            result.push(solution);
+        }
+
+        if reason.is_some() {
+            abort_reason = reason;
+            break;
+        }
+    }
+
+    result.reverse();
+    to_search_result(result, abort_reason)
+}
+
+/// Chains one search per leg, each leg departing from the previous via stop once its dwell duration has elapsed.
+/// The last section of each leg but the last one records the time spent at the via stop.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_routing_via(
+    data_storage: &DataStorage,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    via_stops: &[ViaStop],
+    arrival_stop_id: i32,
+    departure_at: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Option<RouteResult>> {
+    let stop_ids: Vec<i32> = via_stops
+        .iter()
+        .map(|via_stop| via_stop.stop_id())
+        .chain([arrival_stop_id])
+        .collect();
+
+    let mut legs: Vec<RouteResult> = Vec::new();
+    let mut leg_departure_stop_id = departure_stop_id;
+    let mut leg_departure_at = departure_at;
+
+    for (i, &leg_arrival_stop_id) in stop_ids.iter().enumerate() {
+        let leg = match algorithm.compute_routing(
+            data_storage,
+            options,
+            leg_departure_stop_id,
+            leg_departure_at,
+            verbose,
+            RoutingAlgorithmArgs::solve_from_departure_stop_to_arrival_stop(leg_arrival_stop_id),
+        ) {
+            Ok(mut routes) => routes.remove(&leg_arrival_stop_id),
+            // The legs found until then do not reach the arrival stop.
+            Err(aborted) => return Err(aborted.map(|_| None)),
+        };
+
+        let Some(leg) = leg else {
+            return Ok(None);
+        };
+
+        leg_departure_stop_id = leg_arrival_stop_id;
+        leg_departure_at = via_stops
+            .get(i)
+            .and_then(|via_stop| via_stop.dwell_duration())
+            .map_or(leg.arrival_at(), |dwell_duration| {
+                add_minutes_to_date_time(leg.arrival_at(), dwell_duration.num_minutes())
+            });
+
+        legs.push(leg);
+    }
+
+    let departure_at = legs.first().unwrap().departure_at();
+    let arrival_at = legs.last().unwrap().arrival_at();
+    let stopover_durations: Vec<_> = legs
+        .windows(2)
+        .map(|pair| (pair[1].departure_at() - pair[0].arrival_at()).num_minutes())
+        .collect();
+
+    let mut sections = Vec::new();
+
+    for (i, leg) in legs.into_iter().enumerate() {
+        let mut leg_sections = leg.into_sections();
+
+        if let Some(&stopover_duration) = stopover_durations.get(i) {
+            leg_sections
+                .last_mut()
+                .unwrap()
+                .set_stopover_duration(i16::try_from(stopover_duration).ok());
+        }
+
+        sections.extend(leg_sections);
+    }
+
+    Ok(Some(RouteResult::new(departure_at, arrival_at, sections)))
+}
+
+/// Counterpart of compute_routing, the route is built backward from the arrival stop so that the departure is as late as possible.
+pub fn compute_routing_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    arrival_at: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Option<RouteResult>> {
+    if options.has_forward_search_options() {
+        return to_search_result(None, Some(AbortReason::UnsupportedOptions));
+    }
+
+    // The stops and the journeys of the timetable overlay are indexed for this search only.
+    let index = &*index.with_timetable_overlay(options.timetable_overlay());
+    let Some(departure_stop_index) = index.find_stop_index(departure_stop_id) else {
+        return Ok(None);
+    };
+
+    let mut arena = RouteArena::new();
+    let mut routes = create_initial_routes_backward(
+        data_storage,
+        options,
+        index,
+        &mut arena,
+        arrival_stop_id,
+        arrival_at,
+    );
+    let mut journeys_to_ignore = vec![false; index.journey_count()];
+    let mut latest_departure_by_stop = vec![None; index.stop_count()];
+    let mut budget = ExplorationBudget::new(options);
+    let mut solution = None;
+    let mut abort_reason = None;
 
     routes.iter().for_each(|route| {
-        if let Some(journey_id) = route.last_section().journey_id() {
-            journeys_to_ignore.insert(journey_id);
+        if let Some(journey_index) = route.last_section().journey_index() {
+            journeys_to_ignore[journey_index as usize] = true;
         }
     });
 
-    for _ in 0..MAXIMUM_NUMBER_OF_EXPLORABLE_CONNECTIONS {
+    // Each level adds a journey, a journey continuing as another journey (through service) is not counted.
+    while !routes.is_empty() {
         if verbose {
             log::info!("{}", routes.len());
         }
 
-        let can_continue_exploration: Box<dyn FnMut(&Route) -> bool> = match args.mode() {
-            RoutingAlgorithmMode::SolveFromDepartureStopToArrivalStop => Box::new(|route| {
-                can_continue_exploration_one_to_one(
+        let new_routes = explore_routes_backward(
+            data_storage,
+            options,
+            index,
+            &mut arena,
+            routes,
+            &mut journeys_to_ignore,
+            &mut latest_departure_by_stop,
+            &mut budget,
+            |arena, route| {
+                can_continue_exploration_backward(
                     data_storage,
+                    options,
+                    arena,
                     route,
-                    &mut solutions,
-                    args.arrival_stop_id(),
+                    &mut solution,
+                    departure_stop_id,
+                    departure_stop_index,
                 )
-            }),
+            },
+        );
+
+        let new_routes = match new_routes {
+            Ok(new_routes) => new_routes,
+            Err(reason) => {
+                abort_reason = Some(reason);
+                break;
+            }
+        };
+
+        routes = within_maximum_number_of_journeys(options, new_routes);
+    }
+
+    let result =
+        solution.map(|route| route.to_route_result_backward(&arena, data_storage, options, index));
+    to_search_result(result, abort_reason)
+}
+
+/// Keeps the routes that do not exceed the maximum number of journeys.
+fn within_maximum_number_of_journeys(
+    options: &RoutingOptions,
+    mut routes: Vec<Route>,
+) -> Vec<Route> {
+    routes.retain(|route| route.connection_count() <= options.maximum_number_of_journeys());
+    routes
+}
+
+type CanContinueExploration<'a> = Box<dyn FnMut(&RouteArena, &Route) -> bool + 'a>;
+
+/// The earliest arrivals and the returned solutions are indexed by stop index.
+/// The earliest arrivals found by the previous searches at each connection level prune the routes of the same or a higher level, they are updated with the ones of this search.
+/// If the exploration budget is exhausted, the solutions found until then are returned with the reason.
+#[allow(clippy::too_many_arguments)]
+fn explore_connection_levels<F>(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    departure_stops: &[(i32, NaiveDateTime)],
+    verbose: bool,
+    args: &RoutingAlgorithmArgs,
+    mut earliest_arrival_by_level: Option<&mut Vec<Vec<Option<NaiveDateTime>>>>,
+    budget: &mut ExplorationBudget,
+    mut on_level_explored: F,
+) -> (Vec<Option<Route>>, Option<AbortReason>)
+where
+    F: FnMut(&[Option<Route>]),
+{
+    let mut routes = create_initial_routes(data_storage, options, index, arena, departure_stops);
+    let mut journeys_to_ignore = vec![false; index.journey_count()];
+    let mut explored_routes_by_stop = FxHashMap::default();
+    let mut solutions = vec![None; index.stop_count()];
+    let mut earliest_arrival_by_stop: Vec<Option<NaiveDateTime>> = vec![None; index.stop_count()];
+    let mut level = 0;
+
+    let arrival_stop_index = match args.mode() {
+        RoutingAlgorithmMode::SolveFromDepartureStopToArrivalStop => {
+            index.find_stop_index(args.arrival_stop_id())
+        }
+        RoutingAlgorithmMode::SolveFromDepartureStopToReachableArrivalStops => None,
+    };
+
+    // A departure stop is reached as soon as it can be departed, a route arriving later there is not explored further.
+    for &(departure_stop_id, departure_at) in departure_stops {
+        let Some(departure_stop_index) = index.find_stop_index(departure_stop_id) else {
+            continue;
+        };
+
+        let earliest_arrival = &mut earliest_arrival_by_stop[departure_stop_index as usize];
+        *earliest_arrival = Some(earliest_arrival.map_or(departure_at, |t| t.min(departure_at)));
+    }
+
+    routes.iter().for_each(|route| {
+        if let Some(journey_index) = route.last_section().journey_index() {
+            journeys_to_ignore[journey_index as usize] = true;
+        }
+    });
+
+    // Each level adds a journey, a journey continuing as another journey (through service) is not counted.
+    while !routes.is_empty() {
+        if verbose {
+            log::info!("{}", routes.len());
+        }
+
+        // The routes of this level are pruned by the earliest arrivals of the previous searches at the same or a lower level.
+        if let Some(previous_earliest_arrivals) = earliest_arrival_by_level
+            .as_deref()
+            .and_then(|earliest_arrival_by_level| earliest_arrival_by_level.get(level))
+        {
+            keep_earliest_arrivals(&mut earliest_arrival_by_stop, previous_earliest_arrivals);
+        }
+
+        let can_continue_exploration: CanContinueExploration = match args.mode() {
+            RoutingAlgorithmMode::SolveFromDepartureStopToArrivalStop => {
+                Box::new(|arena, route| {
+                    can_continue_exploration_one_to_one(
+                        data_storage,
+                        options,
+                        arena,
+                        route,
+                        &mut solutions,
+                        args.arrival_stop_id(),
+                        arrival_stop_index,
+                    )
+                })
+            }
             RoutingAlgorithmMode::SolveFromDepartureStopToReachableArrivalStops => {
-                Box::new(|route| {
+                Box::new(|arena, route| {
                     can_continue_exploration_one_to_many(
                         data_storage,
+                        options,
+                        index,
+                        arena,
                         route,
                         &mut solutions,
                         args.time_limit(),
@@ -56,90 +452,270 @@ pub fn compute_routing(
 
         let new_routes = explore_routes(
             data_storage,
+            options,
+            index,
+            arena,
             routes,
             &mut journeys_to_ignore,
-            &mut earliest_arrival_by_stop_id,
+            &mut earliest_arrival_by_stop,
+            &mut explored_routes_by_stop,
+            budget,
             can_continue_exploration,
         );
 
-        if new_routes.is_empty() {
-            break;
+        on_level_explored(&solutions);
+
+        if let Some(earliest_arrival_by_level) = earliest_arrival_by_level.as_deref_mut() {
+            match earliest_arrival_by_level.get_mut(level) {
+                Some(earliest_arrivals) => {
+                    keep_earliest_arrivals(earliest_arrivals, &earliest_arrival_by_stop)
+                }
+                None => earliest_arrival_by_level.push(earliest_arrival_by_stop.clone()),
+            }
         }
+        level += 1;
 
-        routes = new_routes;
+        let new_routes = match new_routes {
+            Ok(new_routes) => new_routes,
+            Err(reason) => return (solutions, Some(reason)),
+        };
+
+        routes = within_maximum_number_of_journeys(options, new_routes);
     }
 
-    solutions
-        .into_iter()
-        .map(|(k, v)| (k, v.to_route_result(data_storage)))
-        .collect()
+    (solutions, None)
 }
 
+/// Keeps the earliest of the two arrivals at each stop.
+fn keep_earliest_arrivals(
+    earliest_arrival_by_stop: &mut [Option<NaiveDateTime>],
+    other_earliest_arrival_by_stop: &[Option<NaiveDateTime>],
+) {
+    earliest_arrival_by_stop
+        .iter_mut()
+        .zip(other_earliest_arrival_by_stop)
+        .for_each(|(earliest_arrival, &other_earliest_arrival)| {
+            *earliest_arrival = match (*earliest_arrival, other_earliest_arrival) {
+                (Some(arrival_at), Some(other_arrival_at)) => {
+                    Some(arrival_at.min(other_arrival_at))
+                }
+                (arrival_at, other_arrival_at) => arrival_at.or(other_arrival_at),
+            };
+        });
+}
+
+/// Creates the routes departing from each departure stop, either by boarding a journey or by walking to a nearby stop.
 pub fn create_initial_routes(
     data_storage: &DataStorage,
-    departure_stop_id: i32,
-    departure_at: NaiveDateTime,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    departure_stops: &[(i32, NaiveDateTime)],
 ) -> Vec<Route> {
-    let mut routes: Vec<Route> =
-        next_departures(data_storage, departure_stop_id, departure_at, None, None)
-            .into_iter()
-            .filter_map(|(journey, journey_departure_at)| {
-                RouteSection::find_next(
-                    data_storage,
+    let mut routes = Vec::new();
+
+    for &(departure_stop_id, departure_at) in departure_stops {
+        // A stop that does not exist cannot be departed from.
+        let Some(departure_stop_index) = index.find_stop_index(departure_stop_id) else {
+            continue;
+        };
+
+        let departures = next_departures(
+            data_storage,
+            options,
+            index,
+            departure_stop_id,
+            departure_at,
+            None,
+            None,
+        );
+
+        for (journey, journey_departure_at) in departures {
+            let visited_stops_start = arena.visited_stops().len();
+
+            if let Some(mut section) = RouteSection::find_next(
+                data_storage,
+                options,
+                index,
+                journey,
+                index.journey_index(journey.id()),
+                departure_stop_id,
+                operating_date_of_departure(
+                    options,
                     journey,
                     departure_stop_id,
-                    journey_departure_at.date(),
-                    true,
-                )
-                .map(|(section, mut visited_stops)| {
-                    visited_stops.insert(departure_stop_id);
-                    Route::new(vec![section], visited_stops)
-                })
+                    journey_departure_at,
+                ),
+                arena.visited_stops_mut(),
+            ) {
+                section.set_journey_departure_at(Some(journey_departure_at));
+                arena.visited_stops_mut().push(departure_stop_index);
+                routes.push(Route::new(arena, section, visited_stops_start));
+            }
+        }
+
+        let stop_connections = get_stop_connections_from(data_storage, options, departure_stop_id)
+            .into_iter()
+            // Sometimes certain stop identifiers don't exist for unknown reasons.
+            .filter_map(|stop_connection| {
+                let stop_index_2 = index.find_stop_index(stop_connection.stop_id_2())?;
+                Some((stop_connection, stop_index_2))
             })
-            .collect();
+            .filter(|(stop_connection, _)| {
+                options.accepts_stop(stop_connection.stop_id_2())
+                    && options.accepts_stop_connection(
+                        index,
+                        stop_connection.stop_id_1(),
+                        stop_connection.stop_id_2(),
+                    )
+                    && options.is_stop_connection_open(
+                        stop_connection.stop_id_1(),
+                        stop_connection.stop_id_2(),
+                        departure_at,
+                        add_minutes_to_date_time(departure_at, stop_connection.duration().into()),
+                    )
+            });
 
-    if let Some(stop_connections) = get_stop_connections(data_storage, departure_stop_id) {
-        routes.extend(stop_connections.iter().map(|stop_connection| {
-            let mut visited_stops = FxHashSet::default();
-            visited_stops.insert(stop_connection.stop_id_1());
-            visited_stops.insert(stop_connection.stop_id_2());
+        for (stop_connection, stop_index_2) in stop_connections {
+            let visited_stops_start = arena.visited_stops().len();
+            arena.visited_stops_mut().push(departure_stop_index);
+            arena.visited_stops_mut().push(stop_index_2);
 
             let section = RouteSection::new(
+                None,
                 None,
                 stop_connection.stop_id_1(),
+                departure_stop_index,
                 stop_connection.stop_id_2(),
+                stop_index_2,
                 departure_at,
                 Some(stop_connection.duration()),
             );
-
-            Route::new(vec![section], visited_stops)
-        }));
+            routes.push(Route::new(arena, section, visited_stops_start));
+        }
     }
 
     sort_routes(&mut routes);
     routes
 }
 
+/// Counterpart of create_initial_routes, the routes end at the arrival stop.
+pub fn create_initial_routes_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    arrival_stop_id: i32,
+    arrival_at: NaiveDateTime,
+) -> Vec<Route> {
+    let mut routes = Vec::new();
+
+    // A stop that does not exist cannot be arrived at.
+    let Some(arrival_stop_index) = index.find_stop_index(arrival_stop_id) else {
+        return routes;
+    };
+
+    let arrivals = previous_arrivals(
+        data_storage,
+        options,
+        index,
+        arrival_stop_id,
+        arrival_at,
+        None,
+        None,
+    );
+
+    for (journey, journey_arrival_at) in arrivals {
+        let visited_stops_start = arena.visited_stops().len();
+
+        if let Some(section) = RouteSection::find_previous(
+            data_storage,
+            options,
+            index,
+            journey,
+            index.journey_index(journey.id()),
+            arrival_stop_id,
+            journey_arrival_at,
+            arena.visited_stops_mut(),
+        ) {
+            arena.visited_stops_mut().push(arrival_stop_index);
+            routes.push(Route::new(arena, section, visited_stops_start));
+        }
+    }
+
+    let stop_connections = get_stop_connections_to(data_storage, options, arrival_stop_id)
+        .into_iter()
+        .filter(|stop_connection| {
+            options.accepts_stop(stop_connection.stop_id_1())
+                && options.accepts_stop_connection(
+                    index,
+                    stop_connection.stop_id_1(),
+                    stop_connection.stop_id_2(),
+                )
+        });
+
+    for stop_connection in stop_connections {
+        let stop_index_1 = index.stop_index(stop_connection.stop_id_1());
+        let visited_stops_start = arena.visited_stops().len();
+        arena.visited_stops_mut().push(stop_index_1);
+        arena.visited_stops_mut().push(arrival_stop_index);
+
+        let section = RouteSection::new(
+            None,
+            None,
+            stop_connection.stop_id_1(),
+            stop_index_1,
+            stop_connection.stop_id_2(),
+            arrival_stop_index,
+            arrival_at,
+            Some(stop_connection.duration()),
+        );
+        routes.push(Route::new(arena, section, visited_stops_start));
+    }
+
+    sort_routes_backward(data_storage, options, &mut routes);
+    routes
+}
+
+#[allow(clippy::too_many_arguments)]
 fn can_continue_exploration_one_to_one(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    arena: &RouteArena,
     route: &Route,
-    solutions: &mut FxHashMap<i32, Route>,
+    solutions: &mut [Option<Route>],
     arrival_stop_id: i32,
+    arrival_stop_index: Option<u32>,
 ) -> bool {
-    let solution = solutions.get(&arrival_stop_id);
-
-    if !route.visited_stops().contains(&arrival_stop_id) {
-        return can_improve_solution(route, &solution);
-    }
+    let solution =
+        arrival_stop_index.and_then(|stop_index| solutions[stop_index as usize].as_ref());
+
+    // The arrival stop cannot have been visited if it does not exist.
+    let Some(arrival_stop_index) =
+        arrival_stop_index.filter(|&stop_index| route.has_visited_stop(arena, stop_index))
+    else {
+        return can_improve_solution(data_storage, options, arena, route, &solution);
+    };
 
-    let candidate = if route.last_section().journey_id().is_none() {
-        route.clone()
-    } else {
-        update_arrival_stop(data_storage, route.clone(), arrival_stop_id)
+    let candidate = match route.last_section().journey_id() {
+        None => route.clone(),
+        Some(_) => {
+            let Some(candidate) = update_arrival_stop(
+                data_storage,
+                options,
+                route.clone(),
+                arrival_stop_id,
+                arrival_stop_index,
+            ) else {
+                // The journey passes through the arrival stop without stopping there.
+                return can_improve_solution(data_storage, options, arena, route, &solution);
+            };
+            candidate
+        }
     };
 
-    if is_improving_solution(data_storage, &candidate, &solution) {
-        solutions.insert(arrival_stop_id, candidate);
+    if is_improving_solution(arena, data_storage, options, &candidate, &solution) {
+        solutions[arrival_stop_index as usize] = Some(candidate);
     }
 
     false
@@ -147,88 +723,186 @@ fn can_continue_exploration_one_to_one(
 
 fn can_continue_exploration_one_to_many(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &RouteArena,
     route: &Route,
-    solutions: &mut FxHashMap<i32, Route>,
+    solutions: &mut [Option<Route>],
     time_limit: NaiveDateTime,
 ) -> bool {
     fn evaluate_candidate(
         data_storage: &DataStorage,
+        options: &RoutingOptions,
+        arena: &RouteArena,
         candidate: Route,
-        solutions: &mut FxHashMap<i32, Route>,
+        solutions: &mut [Option<Route>],
         time_limit: NaiveDateTime,
     ) {
-        if candidate.arrival_at() > time_limit {
+        // The journeys can pass through the avoided stops, but they are not reachable.
+        if candidate.arrival_at() > time_limit || !options.accepts_stop(candidate.arrival_stop_id())
+        {
             return;
         }
 
-        let arrival_stop_id = candidate.arrival_stop_id();
-        let solution = solutions.get(&arrival_stop_id);
+        let arrival_stop_index = candidate.arrival_stop_index() as usize;
+        let solution = solutions[arrival_stop_index].as_ref();
 
-        if is_improving_solution(data_storage, &candidate, &solution) {
-            solutions.insert(arrival_stop_id, candidate);
+        if is_improving_solution(arena, data_storage, options, &candidate, &solution) {
+            solutions[arrival_stop_index] = Some(candidate);
         }
     }
 
     if route.last_section().journey_id().is_none() {
-        evaluate_candidate(data_storage, route.clone(), solutions, time_limit);
+        evaluate_candidate(
+            data_storage,
+            options,
+            arena,
+            route.clone(),
+            solutions,
+            time_limit,
+        );
     } else {
         let last_section = route.last_section();
-        let journey = last_section.journey(data_storage).unwrap();
+        let journey = last_section.journey(data_storage, options).unwrap();
+        let stop_indices = index.journey_stop_indices(last_section.journey_index().unwrap());
+
+        // The stops after the departure stop, up to the arrival stop (as in Journey::route_section).
+        let mut route_iter = journey.route().iter().zip(stop_indices);
+        route_iter
+            .by_ref()
+            .find(|(route_entry, _)| route_entry.stop_id() == last_section.departure_stop_id());
+
+        for (route_entry, &stop_index) in route_iter {
+            if let Some(candidate) = update_arrival_stop(
+                data_storage,
+                options,
+                route.clone(),
+                route_entry.stop_id(),
+                stop_index,
+            ) {
+                evaluate_candidate(
+                    data_storage,
+                    options,
+                    arena,
+                    candidate,
+                    solutions,
+                    time_limit,
+                );
+            }
 
-        for route_entry in journey.route_section(
-            last_section.departure_stop_id(),
-            last_section.arrival_stop_id(),
-        ) {
-            let candidate = update_arrival_stop(data_storage, route.clone(), route_entry.stop_id());
-            evaluate_candidate(data_storage, candidate, solutions, time_limit);
+            if route_entry.stop_id() == last_section.arrival_stop_id() {
+                break;
+            }
         }
     }
 
     route.arrival_at() < time_limit
 }
 
+#[allow(clippy::too_many_arguments)]
+fn can_continue_exploration_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    arena: &RouteArena,
+    route: &Route,
+    solution: &mut Option<Route>,
+    departure_stop_id: i32,
+    departure_stop_index: u32,
+) -> bool {
+    if !route.has_visited_stop(arena, departure_stop_index) {
+        return can_improve_solution_backward(data_storage, options, route, &solution.as_ref());
+    }
+
+    let mut candidate = route.clone();
+
+    if let Some(journey) = candidate.last_section().journey(data_storage, options) {
+        let stop_count = journey.count_stops(
+            departure_stop_id,
+            candidate.last_section().arrival_stop_id(),
+        );
+        let last_section = candidate.last_section_mut();
+        last_section.set_departure_stop_id(departure_stop_id);
+        last_section.set_departure_stop_index(departure_stop_index);
+        last_section.set_stop_count(stop_count);
+    }
+
+    if is_improving_solution_backward(data_storage, options, &candidate, &solution.as_ref()) {
+        *solution = Some(candidate);
+    }
+
+    false
+}
+
+/// Returns None if the journey does not stop at the arrival stop according to the real-time updates and the scenario.
 /// Do not call this function if route.last_section().journey_id() is None.
 fn update_arrival_stop(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
     mut route: Route,
     arrival_stop_id: i32,
-) -> Route {
+    arrival_stop_index: u32,
+) -> Option<Route> {
     let last_section = route.last_section();
 
-    let journey = last_section.journey(data_storage).unwrap();
-    let arrival_at = journey.arrival_at_of_with_origin(
-        arrival_stop_id,
-        last_section.arrival_at().date(),
-        false,
+    let journey = last_section.journey(data_storage, options).unwrap();
+    let operating_date = operating_date_of_arrival(
+        options,
+        journey,
         last_section.arrival_stop_id(),
+        last_section.arrival_at(),
     );
+    let arrival_at = delayed_arrival_at(options, journey, arrival_stop_id, operating_date);
+
+    if !options.is_stop_served(journey.id(), operating_date, arrival_stop_id, arrival_at) {
+        return None;
+    }
 
+    let stop_count = journey.count_stops(last_section.departure_stop_id(), arrival_stop_id);
     let last_section = route.last_section_mut();
     last_section.set_arrival_stop_id(arrival_stop_id);
+    last_section.set_arrival_stop_index(arrival_stop_index);
     last_section.set_arrival_at(arrival_at);
+    last_section.set_stop_count(stop_count);
 
-    route
+    Some(route)
+}
+
+fn can_improve_solution(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    arena: &RouteArena,
+    route: &Route,
+    solution: &Option<&Route>,
+) -> bool {
+    solution.as_ref().is_none_or(|sol| {
+        options.journey_cost().can_improve_solution(
+            &PartialRoute::new(data_storage, options, arena, route),
+            &PartialRoute::new(data_storage, options, arena, sol),
+        )
+    })
 }
 
-fn can_improve_solution(route: &Route, solution: &Option<&Route>) -> bool {
-    solution
-        .as_ref()
-        .map_or(true, |sol| route.arrival_at() <= sol.arrival_at())
+/// The route must have been built backward, its last section being the earliest one.
+fn can_improve_solution_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    route: &Route,
+    solution: &Option<&Route>,
+) -> bool {
+    solution.as_ref().is_none_or(|sol| {
+        route.last_section().departure_at(data_storage, options)
+            >= sol.last_section().departure_at(data_storage, options)
+    })
 }
 
 fn is_improving_solution(
+    arena: &RouteArena,
     data_storage: &DataStorage,
+    options: &RoutingOptions,
     candidate: &Route,
     solution: &Option<&Route>,
 ) -> bool {
-    fn count_stops(data_storage: &DataStorage, section: &RouteSection) -> usize {
-        section
-            .journey(data_storage)
-            .unwrap()
-            .count_stops(section.departure_stop_id(), section.arrival_stop_id())
-    }
-
-    if candidate.sections().len() == 1 && candidate.last_section().journey_id().is_none() {
+    if candidate.section_count() == 1 && candidate.last_section().journey_id().is_none() {
         // If the candidate contains only a walking trip, it is not a valid solution.
         return false;
     }
@@ -238,39 +912,49 @@ fn is_improving_solution(
         return true;
     }
 
-    let solution = solution.unwrap();
-
-    // A variable suffixed with 1 will always correspond to the candiate, suffixed with 2 will correspond to the solution.
-    let t1 = candidate.arrival_at();
-    let t2 = solution.arrival_at();
+    options.journey_cost().is_improving_solution(
+        &PartialRoute::new(data_storage, options, arena, candidate),
+        &PartialRoute::new(data_storage, options, arena, solution.unwrap()),
+    )
+}
 
-    if t1 != t2 {
-        // If the candidate arrives earlier than the solution, then it is a better solution.
-        return t1 < t2;
+/// The candidate and the solution must have been built backward, their last section being the earliest one.
+fn is_improving_solution_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    candidate: &Route,
+    solution: &Option<&Route>,
+) -> bool {
+    if candidate.section_count() == 1 && candidate.last_section().journey_id().is_none() {
+        // If the candidate contains only a walking trip, it is not a valid solution.
+        return false;
     }
 
-    let connection_count_1 = candidate.count_connections();
-    let connection_count_2 = solution.count_connections();
+    let Some(solution) = solution else {
+        // If this is the first solution found, then we keep the candidate as the solution.
+        return true;
+    };
+
+    let t1 = candidate.last_section().departure_at(data_storage, options);
+    let t2 = solution.last_section().departure_at(data_storage, options);
 
-    if connection_count_1 != connection_count_2 {
-        // If the candidate requires fewer connections, then it is a better solution.
-        return connection_count_1 < connection_count_2;
+    if t1 != t2 {
+        // If the candidate departs later than the solution, then it is a better solution.
+        return t1 > t2;
     }
 
-    let sections_1 = candidate.sections_having_journey();
-    let sections_2 = solution.sections_having_journey();
+    // If the candidate requires fewer connections, then it is a better solution.
+    candidate.connection_count() < solution.connection_count()
+}
 
-    // Compare each connection.
-    for i in 0..connection_count_1 {
-        let stop_count_1 = count_stops(data_storage, sections_1[i]);
-        let stop_count_2 = count_stops(data_storage, sections_2[i]);
+fn is_dominating_solution(solution: &Route, candidate: &Route) -> bool {
+    let t1 = solution.arrival_at();
+    let t2 = candidate.arrival_at();
+    let connection_count_1 = solution.connection_count();
+    let connection_count_2 = candidate.connection_count();
 
-        if stop_count_1 != stop_count_2 {
-            // If the candidate crosses more stops than the solution, then it is a better solution.
-            return stop_count_1 > stop_count_2;
-        }
-    }
-
-    // The current solution is better than the candidate.
-    false
+    // The solution is at least as good on both criteria and strictly better on one of them.
+    t1 <= t2
+        && connection_count_1 <= connection_count_2
+        && (t1 < t2 || connection_count_1 < connection_count_2)
 }
@@ -0,0 +1,315 @@
+use std::{cmp::Ordering, fmt::Debug};
+
+use chrono::NaiveDateTime;
+use hrdf_parser::{DataStorage, Journey};
+use rustc_hash::FxHashMap;
+
+use super::{
+    models::{Route, RouteArena, RouteSection, RoutingOptions},
+    utils::find_journey,
+};
+
+/// Decides which of two routes reaching the same stop is kept as the solution, see RoutingOptions::set_journey_cost.
+pub trait JourneyCost: Debug + Send + Sync {
+    /// Returns true if the candidate is a better solution than the current one.
+    fn is_improving_solution(&self, candidate: &PartialRoute, solution: &PartialRoute) -> bool;
+
+    /// Returns true if the route, which is still being explored, may still lead to a better solution than the current one.
+    /// Returning false stops the exploration of the route, so it must never underestimate the route.
+    fn can_improve_solution(&self, route: &PartialRoute, solution: &PartialRoute) -> bool;
+
+    /// Returns true if a departure may lead to a better solution than an earlier departure serving the same stops and arriving no later at each of them.
+    /// The level exploration then explores all the departures serving the same stops, which makes it slower.
+    fn may_prefer_later_departures(&self) -> bool {
+        false
+    }
+
+    /// Returns true if a route reaching a stop later than another route may lead to a better solution.
+    /// The level exploration then keeps exploring from a stop the routes that improve on each route explored from it and arriving no later (see is_improving_solution), which makes it slower.
+    fn may_prefer_later_arrivals(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the cost uses PartialRoute::transfer_slacks, which are only computed for such a cost.
+    fn uses_transfer_slacks(&self) -> bool {
+        false
+    }
+}
+
+/// A route as seen by a journey cost, its properties are only computed when asked for.
+pub struct PartialRoute<'a> {
+    data_storage: &'a DataStorage,
+    options: &'a RoutingOptions,
+    arena: &'a RouteArena,
+    route: &'a Route,
+}
+
+impl<'a> PartialRoute<'a> {
+    pub(crate) fn new(
+        data_storage: &'a DataStorage,
+        options: &'a RoutingOptions,
+        arena: &'a RouteArena,
+        route: &'a Route,
+    ) -> Self {
+        Self {
+            data_storage,
+            options,
+            arena,
+            route,
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn data_storage(&self) -> &'a DataStorage {
+        self.data_storage
+    }
+
+    pub fn arrival_at(&self) -> NaiveDateTime {
+        self.route.arrival_at()
+    }
+
+    // Functions
+
+    /// A journey continuing as another journey (through service) is not counted as a connection.
+    pub fn count_connections(&self) -> usize {
+        self.route.connection_count()
+    }
+
+    /// The number of stops crossed by each journey, in the order of the route.
+    pub fn stop_counts(&self) -> Vec<usize> {
+        let mut stop_counts: Vec<_> = self.stop_counts_rev().collect();
+        stop_counts.reverse();
+        stop_counts
+    }
+
+    /// The total walking duration, in minutes.
+    pub fn walking_duration(&self) -> i64 {
+        self.route.walking_duration()
+    }
+
+    /// Each journey with the duration spent on board in minutes, from the last journey to the first one.
+    pub fn in_vehicle_durations(&self) -> impl Iterator<Item = (&'a Journey, i64)> + '_ {
+        self.journey_sections_rev().map(|section| {
+            let departure_at = section.journey_departure_at().unwrap();
+            let duration = (section.arrival_at() - departure_at).num_minutes();
+            (self.journey(section), duration)
+        })
+    }
+
+    /// The journey arrived with and the time left in minutes for each change, in the order of the route.
+    /// A through service is not a change.
+    /// Only known if the journey cost uses them (see JourneyCost::uses_transfer_slacks), they are computed as the route is extended.
+    pub fn transfer_slacks(&self) -> Vec<(&'a Journey, i16)> {
+        let mut transfer_slacks = Vec::new();
+        // The sections are walked from the last one, the slack of a change is known before the journey arrived with.
+        let mut transfer_slack = None;
+
+        for section in self.route.sections_rev(self.arena) {
+            if section.journey_id().is_none() {
+                continue;
+            }
+
+            if let Some(transfer_slack) = transfer_slack {
+                transfer_slacks.push((self.journey(section), transfer_slack));
+            }
+            transfer_slack = section.transfer_slack();
+        }
+
+        transfer_slacks.reverse();
+        transfer_slacks
+    }
+
+    fn journey_sections_rev(&self) -> impl Iterator<Item = &'a RouteSection> {
+        self.route
+            .sections_rev(self.arena)
+            .filter(|section| section.journey_id().is_some())
+    }
+
+    fn stop_counts_rev(&self) -> impl Iterator<Item = usize> + 'a {
+        self.journey_sections_rev()
+            .map(|section| section.stop_count())
+    }
+
+    /// Do not call this function if section.journey_id() is None.
+    fn journey(&self, section: &RouteSection) -> &'a Journey {
+        let journey_id = section.journey_id().unwrap();
+        find_journey(self.data_storage, self.options, journey_id)
+            .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id))
+    }
+}
+
+/// The earliest arrival first, then the fewest connections, then the most stops crossed by each journey in turn.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EarliestArrivalCost;
+
+impl JourneyCost for EarliestArrivalCost {
+    fn is_improving_solution(&self, candidate: &PartialRoute, solution: &PartialRoute) -> bool {
+        is_improving_by_arrival(candidate, solution).unwrap_or(false)
+    }
+
+    fn can_improve_solution(&self, route: &PartialRoute, solution: &PartialRoute) -> bool {
+        route.arrival_at() <= solution.arrival_at()
+    }
+}
+
+/// The arrival time in minutes increased by weighted penalties, the lowest cost wins.
+/// The walking and in-vehicle minutes are multiplied by their weight (1.0 counts them once, as any other minute),
+/// and each transfer adds a fixed penalty in minutes. The ties are broken as by EarliestArrivalCost.
+/// The weights and the penalty must not be negative, so that the cost of a route never decreases as it is extended.
+#[derive(Debug, Clone)]
+pub struct GeneralizedCost {
+    walking_weight: f64,
+    transfer_penalty: f64,
+    transport_type_weights: FxHashMap<String, f64>,
+}
+
+impl Default for GeneralizedCost {
+    fn default() -> Self {
+        Self::new(1.0, 0.0)
+    }
+}
+
+impl GeneralizedCost {
+    pub fn new(walking_weight: f64, transfer_penalty: f64) -> Self {
+        Self {
+            walking_weight,
+            transfer_penalty,
+            transport_type_weights: FxHashMap::default(),
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn walking_weight(&self) -> f64 {
+        self.walking_weight
+    }
+
+    pub fn set_walking_weight(&mut self, value: f64) {
+        self.walking_weight = value;
+    }
+
+    /// The penalty of each transfer, in minutes.
+    pub fn transfer_penalty(&self) -> f64 {
+        self.transfer_penalty
+    }
+
+    pub fn set_transfer_penalty(&mut self, value: f64) {
+        self.transfer_penalty = value;
+    }
+
+    /// The weight of the minutes on board a transport type (e.g. "IC"), 1.0 if it has none.
+    pub fn transport_type_weight(&self, designation: &str) -> f64 {
+        self.transport_type_weights
+            .get(designation)
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    pub fn set_transport_type_weight(&mut self, designation: &str, value: f64) {
+        self.transport_type_weights
+            .insert(designation.to_string(), value);
+    }
+
+    // Functions
+
+    /// The penalties added to the arrival time of the route, in minutes.
+    pub fn penalty(&self, route: &PartialRoute) -> f64 {
+        let data_storage = route.data_storage();
+
+        let walking_penalty = (self.walking_weight - 1.0) * route.walking_duration() as f64;
+        let transfer_penalty =
+            self.transfer_penalty * route.count_connections().saturating_sub(1) as f64;
+        let in_vehicle_penalty: f64 = route
+            .in_vehicle_durations()
+            .map(|(journey, duration)| {
+                let designation = journey.transport_type(data_storage).designation();
+                (self.transport_type_weight(designation) - 1.0) * duration as f64
+            })
+            .sum();
+
+        walking_penalty + transfer_penalty + in_vehicle_penalty
+    }
+
+    /// Compares the costs of the two routes.
+    fn compare(&self, route_1: &PartialRoute, route_2: &PartialRoute) -> Ordering {
+        let difference = (route_1.arrival_at() - route_2.arrival_at()).num_minutes() as f64
+            + self.penalty(route_1)
+            - self.penalty(route_2);
+        difference.total_cmp(&0.0)
+    }
+}
+
+impl JourneyCost for GeneralizedCost {
+    fn is_improving_solution(&self, candidate: &PartialRoute, solution: &PartialRoute) -> bool {
+        match self.compare(candidate, solution) {
+            Ordering::Equal => is_improving_by_connections(candidate, solution).unwrap_or(false),
+            ordering => ordering == Ordering::Less,
+        }
+    }
+
+    fn can_improve_solution(&self, route: &PartialRoute, solution: &PartialRoute) -> bool {
+        self.compare(route, solution) != Ordering::Greater
+    }
+
+    // A later departure may be faster, spending less time on board a weighted transport type.
+    fn may_prefer_later_departures(&self) -> bool {
+        self.transport_type_weights
+            .values()
+            .any(|&weight| weight != 1.0)
+    }
+
+    // A later arrival may come with less walking, fewer transfers or less time on board a weighted transport type.
+    fn may_prefer_later_arrivals(&self) -> bool {
+        self.walking_weight != 1.0
+            || self.transfer_penalty != 0.0
+            || self.may_prefer_later_departures()
+    }
+}
+
+/// Returns None if the two routes are equivalent.
+fn is_improving_by_arrival(candidate: &PartialRoute, solution: &PartialRoute) -> Option<bool> {
+    // A variable suffixed with 1 will always correspond to the candiate, suffixed with 2 will correspond to the solution.
+    let t1 = candidate.arrival_at();
+    let t2 = solution.arrival_at();
+
+    if t1 != t2 {
+        // If the candidate arrives earlier than the solution, then it is a better solution.
+        return Some(t1 < t2);
+    }
+
+    is_improving_by_connections(candidate, solution)
+}
+
+/// Returns None if the two routes are equivalent.
+pub(super) fn is_improving_by_connections(
+    candidate: &PartialRoute,
+    solution: &PartialRoute,
+) -> Option<bool> {
+    let connection_count_1 = candidate.count_connections();
+    let connection_count_2 = solution.count_connections();
+
+    if connection_count_1 != connection_count_2 {
+        // If the candidate requires fewer connections, then it is a better solution.
+        return Some(connection_count_1 < connection_count_2);
+    }
+
+    // Compare each connection, from the first one.
+    // The journeys are walked from the last one, the first difference is the last one found.
+    let journey_count_1 = candidate.stop_counts_rev().count();
+    let journey_count_2 = solution.stop_counts_rev().count();
+    let compared_count = connection_count_1.min(journey_count_1).min(journey_count_2);
+
+    candidate
+        .stop_counts_rev()
+        .skip(journey_count_1 - compared_count)
+        .zip(
+            solution
+                .stop_counts_rev()
+                .skip(journey_count_2 - compared_count),
+        )
+        .filter(|(stop_count_1, stop_count_2)| stop_count_1 != stop_count_2)
+        .last()
+        // If the candidate crosses more stops than the solution, then it is a better solution.
+        .map(|(stop_count_1, stop_count_2)| stop_count_1 > stop_count_2)
+}
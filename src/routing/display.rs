@@ -1,23 +1,44 @@
 use hrdf_parser::{DataStorage, Model};
 
-use super::models::RouteResult;
+use super::{
+    models::{RouteResult, RoutingOptions},
+    utils::find_stop,
+};
 
 impl RouteResult {
     #[rustfmt::skip]
-    pub fn print(&self, data_storage: &DataStorage) {
+    pub fn print(&self, data_storage: &DataStorage, options: &RoutingOptions) {
         for section in self.sections() {
-            let journey = section.journey(data_storage);
+            let journey = section.journey(data_storage, options);
 
             if journey.is_none() {
-                let stop = data_storage.stops().find(section.arrival_stop_id()).unwrap_or_else(|| panic!("Stop {:?} not found.", section.arrival_stop_id()));
-                println!("Approx. {}-minute walk to {}", section.duration().unwrap(), stop.name());
+                // The walk to the point of destination has no arrival stop.
+                let name = find_stop(data_storage, options, section.arrival_stop_id()).map_or("the destination", |stop| stop.name());
+                println!("Approx. {}-minute walk to {}", section.duration().unwrap(), name);
+
+                if let Some(stopover_duration) = section.stopover_duration() {
+                    println!("Stopover of {} minutes", stopover_duration);
+                }
+
                 continue;
             }
 
             let journey = journey.unwrap();
-            println!("Journey #{}", journey.id());
+            if section.is_through_service() {
+                println!("Journey #{} (through service, stay seated)", journey.id());
+            } else {
+                println!("Journey #{}", journey.id());
+            }
+
+            if let Some(transfer_slack) = section.transfer_slack() {
+                println!("  Transfer slack: {} minutes", transfer_slack);
+            }
+
+            if !section.accessibility_attributes().is_empty() {
+                println!("  Accessibility: {}", section.accessibility_attributes().join(", "));
+            }
 
-            let mut route_iter = journey.route().into_iter().peekable();
+            let mut route_iter = journey.route().iter().peekable();
 
             while route_iter.peek().unwrap().stop_id() != section.departure_stop_id() {
                 route_iter.next();
@@ -48,7 +69,7 @@ impl RouteResult {
                     format!("{}", route_entry.departure_time().as_ref().unwrap().format("%H:%M"))
                 };
 
-                let stop = route_entry.stop(data_storage);
+                let stop = find_stop(data_storage, options, route_entry.stop_id()).unwrap_or_else(|| panic!("Stop {:?} not found.", route_entry.stop_id()));
 
                 println!(
                     "    {:0>7} {: <36} {} - {}",
@@ -60,6 +81,10 @@ impl RouteResult {
             }
 
             println!("  Arrival at: {}", section.arrival_at().unwrap().format("%Y-%m-%d %H:%M"));
+
+            if let Some(stopover_duration) = section.stopover_duration() {
+                println!("Stopover of {} minutes", stopover_duration);
+            }
         }
     }
 }
@@ -0,0 +1,229 @@
+use chrono::NaiveDateTime;
+use hrdf_parser::{CoordinateSystem, Coordinates, DataStorage};
+
+use crate::utils::add_minutes_to_date_time;
+
+use super::{
+    algorithm::RoutingAlgorithm,
+    models::{RouteResult, RouteSectionResult, RoutingAlgorithmArgs, RoutingOptions, SearchResult},
+    stop_locator::StopLocator,
+    utils::{from_search_result, to_search_result, with_shared_budget},
+};
+
+/// The walking sections from the point of origin and to the point of destination have this stop id.
+const POINT_STOP_ID: i32 = 0;
+
+#[allow(clippy::too_many_arguments)]
+pub fn compute_door_to_door_routing(
+    data_storage: &DataStorage,
+    algorithm: &dyn RoutingAlgorithm,
+    stop_locator: &StopLocator,
+    options: &RoutingOptions,
+    origin_point_latitude: f64,
+    origin_point_longitude: f64,
+    destination_point_latitude: f64,
+    destination_point_longitude: f64,
+    departure_at: NaiveDateTime,
+) -> SearchResult<Option<RouteResult>> {
+    let origin = Coordinates::new(
+        CoordinateSystem::WGS84,
+        origin_point_latitude,
+        origin_point_longitude,
+    );
+    let destination = Coordinates::new(
+        CoordinateSystem::WGS84,
+        destination_point_latitude,
+        destination_point_longitude,
+    );
+
+    let options = &with_shared_budget(options);
+
+    let access_stops =
+        find_stops_within_walking_distance(data_storage, stop_locator, options, origin);
+    let egress_stops =
+        find_stops_within_walking_distance(data_storage, stop_locator, options, destination);
+
+    if access_stops.is_empty() || egress_stops.is_empty() {
+        return Ok(None);
+    }
+
+    // All the access stops are explored in a single search, each one departed once it has been walked to.
+    let departure_stops: Vec<(i32, NaiveDateTime)> = access_stops
+        .iter()
+        .map(|&(stop_id, duration)| {
+            (
+                stop_id,
+                add_minutes_to_date_time(departure_at, duration.into()),
+            )
+        })
+        .collect();
+
+    let to_solution = |route: RouteResult, egress_duration: i16| {
+        let access_stop_id = route.sections().first().unwrap().departure_stop_id();
+        let access_duration = access_stops
+            .iter()
+            .find(|&&(stop_id, _)| stop_id == access_stop_id)
+            .map(|&(_, duration)| duration)
+            .unwrap();
+
+        to_door_to_door_route(route, origin, access_duration, destination, egress_duration)
+    };
+
+    // The route to the nearest reachable egress stop bounds the arrival time at the other egress stops, the farther ones being walked from for longer.
+    // The egress stops nearer than it cannot be reached, whatever the arrival time.
+    let mut time_limit = None;
+    let mut solution = None;
+
+    for &(egress_stop_id, egress_duration) in &egress_stops {
+        let route = match algorithm.compute_routing_from_departure_stops(
+            data_storage,
+            options,
+            &departure_stops,
+            false,
+            RoutingAlgorithmArgs::solve_from_departure_stop_to_arrival_stop(egress_stop_id),
+        ) {
+            Ok(mut routes) => routes.remove(&egress_stop_id),
+            Err(aborted) => {
+                return Err(aborted.map(|mut routes| {
+                    let route = routes.remove(&egress_stop_id)?;
+                    Some(to_solution(route, egress_duration))
+                }))
+            }
+        };
+
+        if let Some(route) = route {
+            time_limit = Some(route.arrival_at());
+            solution = Some(to_solution(route, egress_duration));
+            break;
+        }
+    }
+
+    let Some(time_limit) = time_limit else {
+        return Ok(None);
+    };
+
+    // If the search is aborted, the routes found until then can still improve the solution.
+    let (mut routes, abort_reason) =
+        from_search_result(algorithm.compute_routing_from_departure_stops(
+            data_storage,
+            options,
+            &departure_stops,
+            false,
+            RoutingAlgorithmArgs::solve_from_departure_stop_to_reachable_arrival_stops(time_limit),
+        ));
+
+    for &(egress_stop_id, egress_duration) in &egress_stops {
+        let Some(route) = routes.remove(&egress_stop_id) else {
+            continue;
+        };
+
+        let candidate = to_solution(route, egress_duration);
+
+        if solution
+            .as_ref()
+            .is_none_or(|solution| is_improving_solution(&candidate, solution))
+        {
+            solution = Some(candidate);
+        }
+    }
+
+    to_search_result(solution, abort_reason)
+}
+
+/// Returns the stops within the maximum walking distance of the point, the nearest first, with the walking duration in minutes.
+/// If there are none, the nearest stop is returned.
+fn find_stops_within_walking_distance(
+    data_storage: &DataStorage,
+    stop_locator: &StopLocator,
+    options: &RoutingOptions,
+    point: Coordinates,
+) -> Vec<(i32, i16)> {
+    stop_locator
+        .find_stops_within_distance(
+            data_storage,
+            options,
+            point,
+            options.maximum_walking_distance(),
+        )
+        .into_iter()
+        .map(|(stop_id, distance)| (stop_id, walking_duration(distance, options.walking_speed())))
+        .collect()
+}
+
+/// Returns the walking duration in minutes, rounded up.
+fn walking_duration(distance: f64, walking_speed: f64) -> i16 {
+    let speed_in_meters_per_minute = walking_speed * 1000.0 / 60.0;
+    (distance / speed_in_meters_per_minute).ceil() as i16
+}
+
+/// Adds the walking sections from the point of origin and to the point of destination.
+/// The departure and arrival times include all the walking before the first journey and after the last journey.
+fn to_door_to_door_route(
+    route: RouteResult,
+    origin: Coordinates,
+    access_duration: i16,
+    destination: Coordinates,
+    egress_duration: i16,
+) -> RouteResult {
+    let mut sections = route.into_sections();
+
+    let first_section = sections.first().unwrap();
+    let access_section = RouteSectionResult::new(
+        None,
+        POINT_STOP_ID,
+        None,
+        Some(origin),
+        first_section.departure_stop_id(),
+        first_section.departure_stop_lv95_coordinates(),
+        first_section.departure_stop_wgs84_coordinates(),
+        None,
+        None,
+        Some(access_duration),
+    );
+
+    let last_section = sections.last().unwrap();
+    let egress_section = RouteSectionResult::new(
+        None,
+        last_section.arrival_stop_id(),
+        last_section.arrival_stop_lv95_coordinates(),
+        last_section.arrival_stop_wgs84_coordinates(),
+        POINT_STOP_ID,
+        None,
+        Some(destination),
+        None,
+        None,
+        Some(egress_duration),
+    );
+
+    sections.insert(0, access_section);
+    sections.push(egress_section);
+
+    let walking_duration = |sections: &[RouteSectionResult]| -> i64 {
+        sections
+            .iter()
+            .map(|section| i64::from(section.duration().unwrap()))
+            .sum()
+    };
+
+    // A route always contains at least one journey.
+    let first_journey = sections.iter().position(|s| !s.is_walking_trip()).unwrap();
+    let last_journey = sections.iter().rposition(|s| !s.is_walking_trip()).unwrap();
+
+    let departure_at = add_minutes_to_date_time(
+        sections[first_journey].departure_at().unwrap(),
+        -walking_duration(&sections[..first_journey]),
+    );
+    let arrival_at = add_minutes_to_date_time(
+        sections[last_journey].arrival_at().unwrap(),
+        walking_duration(&sections[last_journey + 1..]),
+    );
+
+    RouteResult::new(departure_at, arrival_at, sections)
+}
+
+/// The candidate arrives earlier, or at the same time but departs later.
+fn is_improving_solution(candidate: &RouteResult, solution: &RouteResult) -> bool {
+    candidate.arrival_at() < solution.arrival_at()
+        || (candidate.arrival_at() == solution.arrival_at()
+            && candidate.departure_at() > solution.departure_at())
+}
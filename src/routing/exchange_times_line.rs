@@ -0,0 +1,456 @@
+use std::{ops::RangeInclusive, str::FromStr};
+
+use hrdf_parser::{DataStorage, DirectionType, Journey, JourneyMetadataType};
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use super::{field_serializer::serialize_field, routing_index::RoutingIndex};
+
+// hrdf-parser does not expose the fields of ExchangeTimeLine nor the line and direction of a journey.
+// They are read through their serde representation, once when the timetable is indexed.
+
+#[derive(Debug, Clone, Deserialize)]
+struct ExchangeTimeLine {
+    stop_id: Option<i32>,
+    line_1: LineInfo,
+    line_2: LineInfo,
+    duration: i16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LineInfo {
+    administration: String,
+    transport_type_id: i32,
+    line_id: Option<String>,
+    direction: Option<DirectionType>,
+}
+
+impl LineInfo {
+    /// The number of optional criteria (line and direction) that are specified.
+    fn specificity(&self) -> usize {
+        usize::from(self.line_id.is_some()) + usize::from(self.direction.is_some())
+    }
+
+    fn matches(&self, journey: &Journey, journey_line: &JourneyLine) -> bool {
+        self.administration == journey.administration()
+            && self.transport_type_id == journey.transport_type_id()
+            && self
+                .line_id
+                .as_ref()
+                .is_none_or(|line_id| journey_line.has_line(line_id))
+            && self
+                .direction
+                .is_none_or(|direction| journey_line.direction == Some(direction))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JourneyMetadataEntryData {
+    from_stop_id: Option<i32>,
+    until_stop_id: Option<i32>,
+    pub resource_id: Option<i32>,
+    extra_field_1: Option<String>,
+}
+
+/// Returns the metadata of the journey, only the metadata field is serialized.
+pub fn journey_metadata(
+    journey: &Journey,
+) -> FxHashMap<JourneyMetadataType, Vec<JourneyMetadataEntryData>> {
+    serialize_field(journey, "metadata")
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+/// The line and the direction of a journey at a stop.
+#[derive(Debug, Default)]
+pub struct JourneyLine<'a> {
+    line_id: Option<i32>,
+    line_designation: Option<&'a str>,
+    direction: Option<DirectionType>,
+}
+
+impl JourneyLine<'_> {
+    /// A line id starting with "#" refers to a line, otherwise it is the line designation.
+    pub fn has_line(&self, line_id: &str) -> bool {
+        match line_id.strip_prefix('#') {
+            Some(id) => id.parse::<i32>().is_ok_and(|id| self.line_id == Some(id)),
+            None => self.line_designation == Some(line_id),
+        }
+    }
+}
+
+/// The lines and the directions of a journey, each with the positions in the route of the journey where it applies.
+#[derive(Debug, Clone, Default)]
+pub struct JourneyLines {
+    lines: Vec<(RangeInclusive<usize>, Option<i32>, Option<String>)>,
+    directions: Vec<(RangeInclusive<usize>, Option<DirectionType>)>,
+}
+
+impl JourneyLines {
+    /// The line and direction entries are taken from the metadata of the journey.
+    pub fn new(
+        journey: &Journey,
+        metadata: &mut FxHashMap<JourneyMetadataType, Vec<JourneyMetadataEntryData>>,
+    ) -> Self {
+        let mut entries = |k| {
+            metadata
+                .remove(&k)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entry| (applicable_positions(journey, &entry), entry))
+        };
+
+        let lines = entries(JourneyMetadataType::Line)
+            .map(|(positions, entry)| (positions, entry.resource_id, entry.extra_field_1))
+            .collect();
+        let directions = entries(JourneyMetadataType::Direction)
+            .map(|(positions, entry)| {
+                let direction = entry
+                    .extra_field_1
+                    .and_then(|direction| DirectionType::from_str(&direction).ok());
+                (positions, direction)
+            })
+            .collect();
+
+        Self { lines, directions }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.directions.is_empty()
+    }
+
+    /// Returns the line and the direction at the position of a stop in the route of the journey.
+    pub fn at(&self, position: usize) -> JourneyLine<'_> {
+        let line = self
+            .lines
+            .iter()
+            .find(|(positions, _, _)| positions.contains(&position));
+        let direction = self
+            .directions
+            .iter()
+            .find(|(positions, _)| positions.contains(&position));
+
+        JourneyLine {
+            line_id: line.and_then(|(_, line_id, _)| *line_id),
+            line_designation: line.and_then(|(_, _, designation)| designation.as_deref()),
+            direction: direction.and_then(|(_, direction)| *direction),
+        }
+    }
+}
+
+/// The line-related exchange times indexed by stop (None for the global ones) and transport types.
+/// For each key, the most specific exchange times come first.
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeTimesLine {
+    data: FxHashMap<(Option<i32>, i32, i32), Vec<ExchangeTimeLine>>,
+}
+
+impl ExchangeTimesLine {
+    pub fn new(data_storage: &DataStorage) -> Self {
+        let exchange_times = data_storage
+            .exchange_times_line()
+            .data()
+            .values()
+            .filter_map(|exchange_time| {
+                let exchange_time = serde_json::to_value(exchange_time)
+                    .ok()
+                    .and_then(|value| serde_json::from_value::<ExchangeTimeLine>(value).ok());
+
+                if exchange_time.is_none() {
+                    log::warn!("Exchange time line could not be read.");
+                }
+                exchange_time
+            });
+
+        Self::from_exchange_times(exchange_times)
+    }
+
+    fn from_exchange_times(exchange_times: impl IntoIterator<Item = ExchangeTimeLine>) -> Self {
+        let mut data: FxHashMap<_, Vec<ExchangeTimeLine>> = FxHashMap::default();
+
+        for exchange_time in exchange_times {
+            data.entry((
+                exchange_time.stop_id,
+                exchange_time.line_1.transport_type_id,
+                exchange_time.line_2.transport_type_id,
+            ))
+            .or_default()
+            .push(exchange_time);
+        }
+
+        data.values_mut().for_each(|exchange_times| {
+            exchange_times.sort_by_key(|exchange_time| {
+                std::cmp::Reverse(
+                    exchange_time.line_1.specificity() + exchange_time.line_2.specificity(),
+                )
+            })
+        });
+
+        Self { data }
+    }
+
+    // Functions
+
+    /// Returns the most specific exchange time from journey 1 to journey 2 bound to the stop (None for the global ones).
+    /// The lines and the directions of the journeys are only looked up if an exchange time may apply.
+    fn find<'a>(
+        &self,
+        stop_id: Option<i32>,
+        journey_1: &Journey,
+        journey_2: &Journey,
+        journey_lines: impl FnOnce() -> (JourneyLine<'a>, JourneyLine<'a>),
+    ) -> Option<i16> {
+        let candidates = self.data.get(&(
+            stop_id,
+            journey_1.transport_type_id(),
+            journey_2.transport_type_id(),
+        ))?;
+        let (journey_line_1, journey_line_2) = journey_lines();
+
+        candidates
+            .iter()
+            .find(|exchange_time| {
+                exchange_time.line_1.matches(journey_1, &journey_line_1)
+                    && exchange_time.line_2.matches(journey_2, &journey_line_2)
+            })
+            .map(|exchange_time| exchange_time.duration)
+    }
+}
+
+/// Returns the line-related exchange time from journey 1 to journey 2 at the stop.
+/// If is_global is true, only the exchange times that are not bound to a stop are considered.
+pub fn exchange_time_line(
+    index: &RoutingIndex,
+    stop_id: i32,
+    journey_1: &Journey,
+    journey_2: &Journey,
+    is_global: bool,
+) -> Option<i16> {
+    index.exchange_times_line().find(
+        (!is_global).then_some(stop_id),
+        journey_1,
+        journey_2,
+        || {
+            (
+                index.journey_line(journey_1, stop_id),
+                index.journey_line(journey_2, stop_id),
+            )
+        },
+    )
+}
+
+/// Returns the positions in the route of the journey between which the metadata entry applies.
+pub fn applicable_positions(
+    journey: &Journey,
+    entry: &JourneyMetadataEntryData,
+) -> RangeInclusive<usize> {
+    let position_of = |id: i32| {
+        journey
+            .route()
+            .iter()
+            .position(|route_entry| route_entry.stop_id() == id)
+    };
+
+    let from = entry.from_stop_id.and_then(position_of).unwrap_or(0);
+    let until = entry
+        .until_stop_id
+        .and_then(position_of)
+        .unwrap_or(usize::MAX);
+    from..=until
+}
+
+#[cfg(test)]
+mod tests {
+    use hrdf_parser::{DirectionType, Journey, JourneyMetadataEntry, JourneyMetadataType};
+
+    use super::{ExchangeTimeLine, ExchangeTimesLine, JourneyLine, LineInfo};
+
+    const STOP_ID: i32 = 8500001;
+    const TRAIN: i32 = 1;
+    const BUS: i32 = 2;
+
+    fn journey(id: i32, administration: &str, transport_type_id: i32) -> Journey {
+        let mut journey = Journey::new(id, administration.into());
+        journey.add_metadata_entry(
+            JourneyMetadataType::TransportType,
+            JourneyMetadataEntry::new(
+                None,
+                None,
+                Some(transport_type_id),
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+        );
+        journey
+    }
+
+    fn line(
+        administration: &str,
+        transport_type_id: i32,
+        line_id: Option<&str>,
+        direction: Option<DirectionType>,
+    ) -> LineInfo {
+        LineInfo {
+            administration: administration.into(),
+            transport_type_id,
+            line_id: line_id.map(Into::into),
+            direction,
+        }
+    }
+
+    fn exchange_time(
+        stop_id: Option<i32>,
+        line_1: LineInfo,
+        line_2: LineInfo,
+        duration: i16,
+    ) -> ExchangeTimeLine {
+        ExchangeTimeLine {
+            stop_id,
+            line_1,
+            line_2,
+            duration,
+        }
+    }
+
+    /// The train of line 12 (outbound) and the bus of line 7 (return).
+    fn find(exchange_times: Vec<ExchangeTimeLine>, stop_id: Option<i32>) -> Option<i16> {
+        ExchangeTimesLine::from_exchange_times(exchange_times).find(
+            stop_id,
+            &journey(1, "11", TRAIN),
+            &journey(2, "801", BUS),
+            || {
+                (
+                    JourneyLine {
+                        line_id: Some(12),
+                        line_designation: Some("S12"),
+                        direction: Some(DirectionType::Outbound),
+                    },
+                    JourneyLine {
+                        line_id: Some(7),
+                        line_designation: Some("7"),
+                        direction: Some(DirectionType::Return),
+                    },
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn line_at_stop_is_only_found_at_the_stop() {
+        let exchange_times = || {
+            vec![
+                exchange_time(
+                    Some(STOP_ID),
+                    line("11", TRAIN, None, None),
+                    line("801", BUS, None, None),
+                    4,
+                ),
+                exchange_time(
+                    None,
+                    line("11", TRAIN, None, None),
+                    line("801", BUS, None, None),
+                    9,
+                ),
+            ]
+        };
+
+        assert_eq!(find(exchange_times(), Some(STOP_ID)), Some(4));
+        assert_eq!(find(exchange_times(), Some(8500002)), None);
+        assert_eq!(find(exchange_times(), None), Some(9));
+    }
+
+    #[test]
+    fn specific_line_wins_over_wildcard_line() {
+        let exchange_times = vec![
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, None, None),
+                line("801", BUS, None, None),
+                5,
+            ),
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, Some("#12"), None),
+                line("801", BUS, None, None),
+                3,
+            ),
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, Some("#99"), None),
+                line("801", BUS, None, None),
+                1,
+            ),
+        ];
+
+        assert_eq!(find(exchange_times, Some(STOP_ID)), Some(3));
+    }
+
+    #[test]
+    fn specific_direction_wins_over_wildcard_direction() {
+        let exchange_times = vec![
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, Some("S12"), None),
+                line("801", BUS, None, None),
+                5,
+            ),
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, Some("S12"), None),
+                line("801", BUS, None, Some(DirectionType::Return)),
+                2,
+            ),
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, Some("S12"), None),
+                line("801", BUS, None, Some(DirectionType::Outbound)),
+                1,
+            ),
+        ];
+
+        assert_eq!(find(exchange_times, Some(STOP_ID)), Some(2));
+    }
+
+    #[test]
+    fn line_and_direction_win_over_line_only() {
+        let exchange_times = vec![
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, Some("#12"), None),
+                line("801", BUS, Some("7"), None),
+                6,
+            ),
+            exchange_time(
+                Some(STOP_ID),
+                line("11", TRAIN, Some("#12"), Some(DirectionType::Outbound)),
+                line("801", BUS, Some("7"), Some(DirectionType::Return)),
+                2,
+            ),
+        ];
+
+        assert_eq!(find(exchange_times, Some(STOP_ID)), Some(2));
+    }
+
+    #[test]
+    fn other_administration_or_transport_type_does_not_apply() {
+        let exchange_times = vec![
+            exchange_time(
+                Some(STOP_ID),
+                line("85", TRAIN, None, None),
+                line("801", BUS, None, None),
+                5,
+            ),
+            exchange_time(
+                Some(STOP_ID),
+                line("11", BUS, None, None),
+                line("801", BUS, None, None),
+                5,
+            ),
+        ];
+
+        assert_eq!(find(exchange_times, Some(STOP_ID)), None);
+    }
+}
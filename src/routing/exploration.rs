@@ -1,31 +1,56 @@
+use std::cmp::Reverse;
+
 use chrono::NaiveDateTime;
 use hrdf_parser::DataStorage;
-use rustc_hash::{FxHashMap, FxHashSet};
+use rustc_hash::FxHashMap;
 
 use crate::utils::add_minutes_to_date_time;
 
 use super::{
-    connections::get_connections,
-    models::{Route, RouteSection},
-    utils::{clone_update_route, get_stop_connections, sort_routes, sorted_insert},
+    connections::{get_connections, get_previous_connections},
+    cost::PartialRoute,
+    models::{AbortReason, ExplorationBudget, Route, RouteArena, RouteSection, RoutingOptions},
+    priority_queue::PriorityQueue,
+    real_time::operating_date_of_arrival,
+    routing_index::RoutingIndex,
+    trace::SearchEvent,
+    utils::{
+        find_stop, get_stop_connections_from, get_stop_connections_to, sort_routes,
+        sort_routes_backward,
+    },
 };
 
+/// The journeys to ignore are indexed by journey index, the earliest arrivals and the explored routes by stop index.
+/// The explored routes are only kept if the journey cost may prefer a route reaching a stop later than another route.
+/// Returns an error as soon as the exploration budget is exhausted.
+#[allow(clippy::too_many_arguments)]
 pub fn explore_routes<F>(
     data_storage: &DataStorage,
-    mut routes: Vec<Route>,
-    journeys_to_ignore: &mut FxHashSet<i32>,
-    earliest_arrival_by_stop_id: &mut FxHashMap<i32, NaiveDateTime>,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    routes: Vec<Route>,
+    journeys_to_ignore: &mut [bool],
+    earliest_arrival_by_stop: &mut [Option<NaiveDateTime>],
+    explored_routes_by_stop: &mut FxHashMap<u32, Vec<Route>>,
+    budget: &mut ExplorationBudget,
     mut can_continue_exploration: F,
-) -> Vec<Route>
+) -> Result<Vec<Route>, AbortReason>
 where
-    F: FnMut(&Route) -> bool,
+    F: FnMut(&RouteArena, &Route) -> bool,
 {
     let mut new_routes = Vec::new();
 
-    while !routes.is_empty() {
-        let route = routes.remove(0);
+    // The routes are explored by ascending arrival time.
+    let mut routes_to_explore = PriorityQueue::new();
+    routes
+        .into_iter()
+        .for_each(|route| routes_to_explore.push(route.arrival_at(), route));
+
+    while let Some(route) = routes_to_explore.pop() {
+        budget.explore()?;
 
-        if !can_continue_exploration(&route) {
+        if !can_continue_exploration(arena, &route) {
             continue;
         }
 
@@ -35,51 +60,107 @@ where
             continue;
         }
 
-        explore_last_route_section_more_if_possible(data_storage, &route, &mut routes);
+        explore_last_route_section_more_if_possible(
+            data_storage,
+            options,
+            index,
+            arena,
+            &route,
+            &mut routes_to_explore,
+        );
+
+        if !options.accepts_stop(route.arrival_stop_id()) {
+            // The journey can pass through an avoided stop, but it cannot be left there.
+            continue;
+        }
 
-        if !can_explore_connections(data_storage, &route, earliest_arrival_by_stop_id) {
+        if !can_explore_connections(
+            data_storage,
+            options,
+            arena,
+            &route,
+            earliest_arrival_by_stop,
+            explored_routes_by_stop,
+        ) {
             continue;
         }
 
-        explore_nearby_stops(data_storage, &route, &mut routes);
-        explore_connections(data_storage, &route, &journeys_to_ignore, &mut new_routes);
+        explore_nearby_stops(
+            data_storage,
+            options,
+            index,
+            arena,
+            &route,
+            &mut routes_to_explore,
+        );
+        explore_connections(
+            data_storage,
+            options,
+            index,
+            arena,
+            &route,
+            journeys_to_ignore,
+            &mut new_routes,
+        );
     }
 
     // All new journeys are recorded as not available for the next connection level.
     new_routes.iter().for_each(|route| {
-        if let Some(journey_id) = route.last_section().journey_id() {
-            journeys_to_ignore.insert(journey_id);
+        if let Some(journey_index) = route.last_section().journey_index() {
+            journeys_to_ignore[journey_index as usize] = true;
         }
     });
 
     sort_routes(&mut new_routes);
-    new_routes
+    Ok(new_routes)
 }
 
 fn explore_last_route_section_more_if_possible(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
     route: &Route,
-    routes: &mut Vec<Route>,
+    routes_to_explore: &mut PriorityQueue<NaiveDateTime, Route>,
 ) {
-    let Some(journey_id) = route.last_section().journey_id() else {
+    let (Some(journey), Some(journey_index)) = (
+        route.last_section().journey(data_storage, options),
+        route.last_section().journey_index(),
+    ) else {
         return;
     };
 
     // The next section (tronçon dans ce cas) is visited if possible.
-    let new_route = route.extend(data_storage, journey_id, route.arrival_at().date(), false);
+    let new_route = route.extend(
+        arena,
+        data_storage,
+        options,
+        index,
+        journey,
+        journey_index,
+        operating_date_of_arrival(
+            options,
+            journey,
+            route.arrival_stop_id(),
+            route.arrival_at(),
+        ),
+    );
 
     if let Some(rou) = new_route {
-        sorted_insert(routes, rou);
+        routes_to_explore.push(rou.arrival_at(), rou);
     }
 }
 
 fn can_explore_connections(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    arena: &RouteArena,
     route: &Route,
-    earliest_arrival_by_stop_id: &mut FxHashMap<i32, NaiveDateTime>,
+    earliest_arrival_by_stop: &mut [Option<NaiveDateTime>],
+    explored_routes_by_stop: &mut FxHashMap<u32, Vec<Route>>,
 ) -> bool {
     let stop_id = route.arrival_stop_id();
-    let stop = data_storage.stops().find(stop_id);
+    let stop = find_stop(data_storage, options, stop_id);
     let stop = if let Some(stop) = stop {
         stop
     } else {
@@ -87,69 +168,409 @@ fn can_explore_connections(
         return false;
     };
 
+    let arrival_at = route.arrival_at();
+
     if !stop.can_be_used_as_exchange_point() {
         // The arrival stop of the last RouteSection of a journey is not necessarily usable for exchange, hence the check.
+        options.record_event(|| SearchEvent::StopNotExchangePoint {
+            stop_id,
+            journey_id: route.last_section().journey_id(),
+            arrival_at,
+        });
         return false;
     }
 
-    let arrival_at = route.arrival_at();
+    let stop_index = route.arrival_stop_index();
+    let earliest_arrival = &mut earliest_arrival_by_stop[stop_index as usize];
+    let may_prefer_later_arrivals = options.journey_cost().may_prefer_later_arrivals();
 
-    if let Some(&earliest_arrival) = earliest_arrival_by_stop_id.get(&stop_id) {
-        if arrival_at < earliest_arrival {
+    let can_explore = if let Some(earliest_arrival) = earliest_arrival {
+        if arrival_at < *earliest_arrival {
             // The route arrived even earlier than the last route recorded for the stop.
-            earliest_arrival_by_stop_id.insert(stop_id, arrival_at);
+            *earliest_arrival = arrival_at;
+            true
+        } else if may_prefer_later_arrivals
+            && !is_dominated(
+                data_storage,
+                options,
+                arena,
+                route,
+                explored_routes_by_stop.get(&stop_index),
+            )
+        {
+            // Another route reached the stop faster, but the journey cost may prefer this one.
             true
         } else {
             // Another route reached the stop faster.
+            options.record_event(|| SearchEvent::RouteDominated {
+                stop_id,
+                journey_id: route.last_section().journey_id(),
+                arrival_at,
+                earliest_arrival_at: *earliest_arrival,
+            });
             false
         }
     } else {
         // This is the first time the stop has been found.
-        earliest_arrival_by_stop_id.insert(stop_id, arrival_at);
+        *earliest_arrival = Some(arrival_at);
         true
+    };
+
+    if can_explore && may_prefer_later_arrivals {
+        explored_routes_by_stop
+            .entry(stop_index)
+            .or_default()
+            .push(route.clone());
     }
+    can_explore
+}
+
+/// Returns true if a route explored from the stop arrived no later and is not worse according to the journey cost.
+/// Without any explored route, the stop was reached by another search sharing the earliest arrivals or it is a departure stop, the route is then dominated.
+fn is_dominated(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    arena: &RouteArena,
+    route: &Route,
+    explored_routes: Option<&Vec<Route>>,
+) -> bool {
+    let Some(explored_routes) = explored_routes.filter(|routes| !routes.is_empty()) else {
+        return true;
+    };
+
+    let candidate = PartialRoute::new(data_storage, options, arena, route);
+    explored_routes.iter().any(|explored_route| {
+        explored_route.arrival_at() <= route.arrival_at()
+            && !options.journey_cost().is_improving_solution(
+                &candidate,
+                &PartialRoute::new(data_storage, options, arena, explored_route),
+            )
+    })
 }
 
 fn explore_connections(
     data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
     route: &Route,
-    journeys_to_ignore: &FxHashSet<i32>,
+    journeys_to_ignore: &[bool],
     new_routes: &mut Vec<Route>,
 ) {
-    new_routes.extend(get_connections(data_storage, &route, journeys_to_ignore));
+    new_routes.extend(get_connections(
+        data_storage,
+        options,
+        index,
+        arena,
+        route,
+        journeys_to_ignore,
+    ));
 }
 
-fn explore_nearby_stops(data_storage: &DataStorage, route: &Route, routes: &mut Vec<Route>) {
+fn explore_nearby_stops(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    route: &Route,
+    routes_to_explore: &mut PriorityQueue<NaiveDateTime, Route>,
+) {
     if route.last_section().journey_id().is_none() {
         // No walking between 2 stops, after walking between 2 stops just before.
         return;
     }
 
-    match get_stop_connections(data_storage, route.arrival_stop_id()) {
-        Some(stop_connections) => stop_connections,
-        None => return,
-    }
-    .into_iter()
-    // Sometimes certain stop identifiers don't exist for unknown reasons.
-    .filter(|stop_connection| {
-        data_storage
-            .stops()
-            .data()
-            .contains_key(&stop_connection.stop_id_2())
-    })
-    // No return to a previously visited stop.
-    .filter(|stop_connection| !route.visited_stops().contains(&stop_connection.stop_id_2()))
-    .map(|stop_connection| {
-        clone_update_route(route, |cloned_sections, cloned_visited_stops| {
-            cloned_sections.push(RouteSection::new(
+    let stop_connections: Vec<_> =
+        get_stop_connections_from(data_storage, options, route.arrival_stop_id())
+            .into_iter()
+            // Sometimes certain stop identifiers don't exist for unknown reasons.
+            .filter_map(|stop_connection| {
+                let stop_index_2 = index.find_stop_index(stop_connection.stop_id_2())?;
+                Some((stop_connection, stop_index_2))
+            })
+            // No return to a previously visited stop.
+            .filter(|&(_, stop_index_2)| !route.has_visited_stop(arena, stop_index_2))
+            .filter(|(stop_connection, _)| {
+                options.accepts_stop(stop_connection.stop_id_2())
+                    && options.accepts_stop_connection(
+                        index,
+                        stop_connection.stop_id_1(),
+                        stop_connection.stop_id_2(),
+                    )
+                    && options.is_stop_connection_open(
+                        stop_connection.stop_id_1(),
+                        stop_connection.stop_id_2(),
+                        route.arrival_at(),
+                        add_minutes_to_date_time(
+                            route.arrival_at(),
+                            stop_connection.duration().into(),
+                        ),
+                    )
+            })
+            .collect();
+
+    for (stop_connection, stop_index_2) in stop_connections {
+        let visited_stops_start = arena.visited_stops().len();
+        arena.visited_stops_mut().push(stop_index_2);
+
+        let new_route = route.with_next_section(
+            arena,
+            data_storage,
+            index,
+            RouteSection::new(
+                None,
                 None,
                 stop_connection.stop_id_1(),
+                route.arrival_stop_index(),
                 stop_connection.stop_id_2(),
+                stop_index_2,
                 add_minutes_to_date_time(route.arrival_at(), stop_connection.duration().into()),
                 Some(stop_connection.duration()),
-            ));
-            cloned_visited_stops.insert(stop_connection.stop_id_2());
+            ),
+            visited_stops_start,
+        );
+        routes_to_explore.push(new_route.arrival_at(), new_route);
+    }
+}
+
+/// Counterpart of explore_routes, the routes are built backward from the arrival stop.
+#[allow(clippy::too_many_arguments)]
+pub fn explore_routes_backward<F>(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    routes: Vec<Route>,
+    journeys_to_ignore: &mut [bool],
+    latest_departure_by_stop: &mut [Option<NaiveDateTime>],
+    budget: &mut ExplorationBudget,
+    mut can_continue_exploration: F,
+) -> Result<Vec<Route>, AbortReason>
+where
+    F: FnMut(&RouteArena, &Route) -> bool,
+{
+    let mut new_routes = Vec::new();
+
+    // The routes are explored by descending departure time.
+    let mut routes_to_explore = PriorityQueue::new();
+    routes.into_iter().for_each(|route| {
+        routes_to_explore.push(
+            Reverse(route.last_section().departure_at(data_storage, options)),
+            route,
+        )
+    });
+
+    while let Some(route) = routes_to_explore.pop() {
+        budget.explore()?;
+
+        if !can_continue_exploration(arena, &route) {
+            continue;
+        }
+
+        if route.last_section().departure_stop_id() == route.last_section().arrival_stop_id() {
+            continue;
+        }
+
+        explore_first_route_section_more_if_possible(
+            data_storage,
+            options,
+            index,
+            arena,
+            &route,
+            &mut routes_to_explore,
+        );
+
+        if !options.accepts_stop(route.last_section().departure_stop_id()) {
+            continue;
+        }
+
+        if !can_explore_previous_connections(
+            data_storage,
+            options,
+            &route,
+            latest_departure_by_stop,
+        ) {
+            continue;
+        }
+
+        explore_nearby_stops_backward(
+            data_storage,
+            options,
+            index,
+            arena,
+            &route,
+            &mut routes_to_explore,
+        );
+        explore_previous_connections(
+            data_storage,
+            options,
+            index,
+            arena,
+            &route,
+            journeys_to_ignore,
+            &mut new_routes,
+        );
+    }
+
+    // All new journeys are recorded as not available for the next connection level.
+    new_routes.iter().for_each(|route| {
+        if let Some(journey_index) = route.last_section().journey_index() {
+            journeys_to_ignore[journey_index as usize] = true;
+        }
+    });
+
+    sort_routes_backward(data_storage, options, &mut new_routes);
+    Ok(new_routes)
+}
+
+fn explore_first_route_section_more_if_possible(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    route: &Route,
+    routes_to_explore: &mut PriorityQueue<Reverse<NaiveDateTime>, Route>,
+) {
+    let (Some(journey), Some(journey_index)) = (
+        route.last_section().journey(data_storage, options),
+        route.last_section().journey_index(),
+    ) else {
+        return;
+    };
+
+    // The previous section is visited if possible.
+    let new_route = route.extend_backward(
+        arena,
+        data_storage,
+        options,
+        index,
+        journey,
+        journey_index,
+        route.last_section().arrival_at(),
+    );
+
+    if let Some(rou) = new_route {
+        routes_to_explore.push(
+            Reverse(rou.last_section().departure_at(data_storage, options)),
+            rou,
+        );
+    }
+}
+
+fn can_explore_previous_connections(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    route: &Route,
+    latest_departure_by_stop: &mut [Option<NaiveDateTime>],
+) -> bool {
+    let stop_id = route.last_section().departure_stop_id();
+    let stop = if let Some(stop) = find_stop(data_storage, options, stop_id) {
+        stop
+    } else {
+        log::warn!("Stop: {} not found.", stop_id);
+        return false;
+    };
+
+    if !stop.can_be_used_as_exchange_point() {
+        return false;
+    }
+
+    let departure_at = route.last_section().departure_at(data_storage, options);
+    let latest_departure =
+        &mut latest_departure_by_stop[route.last_section().departure_stop_index() as usize];
+
+    if let Some(latest_departure) = latest_departure {
+        if departure_at > *latest_departure {
+            // The route leaves the stop even later than the last route recorded for the stop.
+            *latest_departure = departure_at;
+            true
+        } else {
+            // Another route can leave the stop later.
+            false
+        }
+    } else {
+        // This is the first time the stop has been found.
+        *latest_departure = Some(departure_at);
+        true
+    }
+}
+
+fn explore_previous_connections(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    route: &Route,
+    journeys_to_ignore: &[bool],
+    new_routes: &mut Vec<Route>,
+) {
+    new_routes.extend(get_previous_connections(
+        data_storage,
+        options,
+        index,
+        arena,
+        route,
+        journeys_to_ignore,
+    ));
+}
+
+fn explore_nearby_stops_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    arena: &mut RouteArena,
+    route: &Route,
+    routes_to_explore: &mut PriorityQueue<Reverse<NaiveDateTime>, Route>,
+) {
+    if route.last_section().journey_id().is_none() {
+        // No walking between 2 stops, after walking between 2 stops just before.
+        return;
+    }
+
+    let stop_id = route.last_section().departure_stop_id();
+    let departure_at = route.last_section().departure_at(data_storage, options);
+
+    let stop_connections: Vec<_> = get_stop_connections_to(data_storage, options, stop_id)
+        .into_iter()
+        .map(|stop_connection| {
+            let stop_index_1 = index.stop_index(stop_connection.stop_id_1());
+            (stop_connection, stop_index_1)
         })
-    })
-    .for_each(|new_route| sorted_insert(routes, new_route));
+        // No return to a previously visited stop.
+        .filter(|&(_, stop_index_1)| !route.has_visited_stop(arena, stop_index_1))
+        .filter(|(stop_connection, _)| {
+            options.accepts_stop(stop_connection.stop_id_1())
+                && options.accepts_stop_connection(
+                    index,
+                    stop_connection.stop_id_1(),
+                    stop_connection.stop_id_2(),
+                )
+        })
+        .collect();
+
+    for (stop_connection, stop_index_1) in stop_connections {
+        let visited_stops_start = arena.visited_stops().len();
+        arena.visited_stops_mut().push(stop_index_1);
+
+        let new_route = route.with_next_section(
+            arena,
+            data_storage,
+            index,
+            RouteSection::new(
+                None,
+                None,
+                stop_connection.stop_id_1(),
+                stop_index_1,
+                stop_connection.stop_id_2(),
+                route.last_section().departure_stop_index(),
+                departure_at,
+                Some(stop_connection.duration()),
+            ),
+            visited_stops_start,
+        );
+        routes_to_explore.push(
+            Reverse(new_route.last_section().departure_at(data_storage, options)),
+            new_route,
+        );
+    }
 }
@@ -0,0 +1,122 @@
+use serde::{
+    ser::{self, Impossible, SerializeStruct},
+    Serialize, Serializer,
+};
+
+/// Returns the serde representation of a field of a struct, the other fields are skipped without being serialized.
+/// Used to read the data that hrdf-parser does not expose.
+pub fn serialize_field<T: Serialize>(value: &T, name: &str) -> Option<serde_json::Value> {
+    value.serialize(FieldSerializer { name }).ok().flatten()
+}
+
+/// Serializes only the field with the given name of a struct.
+struct FieldSerializer<'a> {
+    name: &'a str,
+}
+
+struct FieldSerializerStruct<'a> {
+    name: &'a str,
+    value: Option<serde_json::Value>,
+}
+
+macro_rules! unsupported {
+    ($($f:ident($($t:ty),*) -> $r:ty;)*) => {
+        $(
+            fn $f(self, $(_: $t),*) -> Result<$r, Self::Error> {
+                Err(ser::Error::custom("A struct is expected."))
+            }
+        )*
+    };
+}
+
+impl<'a> Serializer for FieldSerializer<'a> {
+    type Ok = Option<serde_json::Value>;
+    type Error = serde_json::Error;
+    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = FieldSerializerStruct<'a>;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    unsupported! {
+        serialize_bool(bool) -> Self::Ok;
+        serialize_i8(i8) -> Self::Ok;
+        serialize_i16(i16) -> Self::Ok;
+        serialize_i32(i32) -> Self::Ok;
+        serialize_i64(i64) -> Self::Ok;
+        serialize_u8(u8) -> Self::Ok;
+        serialize_u16(u16) -> Self::Ok;
+        serialize_u32(u32) -> Self::Ok;
+        serialize_u64(u64) -> Self::Ok;
+        serialize_f32(f32) -> Self::Ok;
+        serialize_f64(f64) -> Self::Ok;
+        serialize_char(char) -> Self::Ok;
+        serialize_str(&str) -> Self::Ok;
+        serialize_bytes(&[u8]) -> Self::Ok;
+        serialize_none() -> Self::Ok;
+        serialize_unit() -> Self::Ok;
+        serialize_unit_struct(&'static str) -> Self::Ok;
+        serialize_unit_variant(&'static str, u32, &'static str) -> Self::Ok;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<Self::Ok, Self::Error> {
+        Err(ser::Error::custom("A struct is expected."))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(ser::Error::custom("A struct is expected."))
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(FieldSerializerStruct {
+            name: self.name,
+            value: None,
+        })
+    }
+}
+
+impl SerializeStruct for FieldSerializerStruct<'_> {
+    type Ok = Option<serde_json::Value>;
+    type Error = serde_json::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        if key == self.name {
+            self.value = Some(serde_json::to_value(value)?);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.value)
+    }
+}
@@ -1,31 +1,80 @@
-use chrono::NaiveDateTime;
-use hrdf_parser::{Coordinates, DataStorage, Journey};
+use std::{
+    borrow::Borrow,
+    error::Error,
+    fmt,
+    hash::Hash,
+    iter,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use hrdf_parser::{Coordinates, DataStorage, Journey, Model};
 use rustc_hash::FxHashSet;
 use serde::Serialize;
 
+use crate::utils::{add_minutes_to_date_time, create_time};
+
+use super::{
+    constants::{
+        DEFAULT_EVENING_CUTOFF_HOUR, DEFAULT_LOOKAHEAD_HORIZON_IN_MINUTES,
+        DEFAULT_MAXIMUM_NUMBER_OF_TRANSFERS, DEFAULT_MAXIMUM_WALKING_DISTANCE_IN_METERS,
+        DEFAULT_OVERNIGHT_CUTOFF_HOUR, DEFAULT_WALKING_SPEED_IN_KILOMETERS_PER_HOUR,
+        EXPLORATION_CHECK_INTERVAL,
+    },
+    cost::{EarliestArrivalCost, JourneyCost},
+    overlay::TimetableOverlay,
+    real_time::RealTimeUpdates,
+    routing_index::RoutingIndex,
+    scenario::Scenario,
+    through_services::is_through_service,
+    trace::{SearchEvent, SearchEventSink},
+    utils::find_journey,
+};
+
 #[derive(Debug, Clone)]
 pub struct RouteSection {
     journey_id: Option<i32>,
+    journey_index: Option<u32>,
     departure_stop_id: i32,
+    departure_stop_index: u32,
     arrival_stop_id: i32,
+    arrival_stop_index: u32,
     arrival_at: NaiveDateTime,
     duration: Option<i16>,
+    stop_count: usize,
+    journey_departure_at: Option<NaiveDateTime>,
+    transfer_slack: Option<i16>,
 }
 
 impl RouteSection {
+    /// The journey and the stops are given with their index in the RoutingIndex, converted once by the caller.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         journey_id: Option<i32>,
+        journey_index: Option<u32>,
         departure_stop_id: i32,
+        departure_stop_index: u32,
         arrival_stop_id: i32,
+        arrival_stop_index: u32,
         arrival_at: NaiveDateTime,
         duration: Option<i16>,
     ) -> Self {
         Self {
             journey_id,
+            journey_index,
             departure_stop_id,
+            departure_stop_index,
             arrival_stop_id,
+            arrival_stop_index,
             arrival_at,
             duration,
+            stop_count: 0,
+            journey_departure_at: None,
+            transfer_slack: None,
         }
     }
 
@@ -35,10 +84,26 @@ impl RouteSection {
         self.journey_id
     }
 
+    pub fn journey_index(&self) -> Option<u32> {
+        self.journey_index
+    }
+
     pub fn departure_stop_id(&self) -> i32 {
         self.departure_stop_id
     }
 
+    pub fn set_departure_stop_id(&mut self, value: i32) {
+        self.departure_stop_id = value;
+    }
+
+    pub fn departure_stop_index(&self) -> u32 {
+        self.departure_stop_index
+    }
+
+    pub fn set_departure_stop_index(&mut self, value: u32) {
+        self.departure_stop_index = value;
+    }
+
     pub fn arrival_stop_id(&self) -> i32 {
         self.arrival_stop_id
     }
@@ -47,6 +112,14 @@ impl RouteSection {
         self.arrival_stop_id = value;
     }
 
+    pub fn arrival_stop_index(&self) -> u32 {
+        self.arrival_stop_index
+    }
+
+    pub fn set_arrival_stop_index(&mut self, value: u32) {
+        self.arrival_stop_index = value;
+    }
+
     pub fn arrival_at(&self) -> NaiveDateTime {
         self.arrival_at
     }
@@ -59,78 +132,339 @@ impl RouteSection {
         self.duration
     }
 
+    /// The number of stops of the journey from the departure stop to the arrival stop, both included, 0 for a walking trip.
+    pub fn stop_count(&self) -> usize {
+        self.stop_count
+    }
+
+    pub fn set_stop_count(&mut self, value: usize) {
+        self.stop_count = value;
+    }
+
+    /// The departure of the journey from the departure stop, delayed according to the real-time updates.
+    /// Only set by the level exploration searching forward, None for a walking trip.
+    pub fn journey_departure_at(&self) -> Option<NaiveDateTime> {
+        self.journey_departure_at
+    }
+
+    pub fn set_journey_departure_at(&mut self, value: Option<NaiveDateTime>) {
+        self.journey_departure_at = value;
+    }
+
+    /// The time left in minutes when changing to the journey of the section, None if it is not a change.
+    /// Only set by the level exploration searching forward, if the journey cost uses it (see JourneyCost::uses_transfer_slacks).
+    pub fn transfer_slack(&self) -> Option<i16> {
+        self.transfer_slack
+    }
+
+    pub fn set_transfer_slack(&mut self, value: Option<i16>) {
+        self.transfer_slack = value;
+    }
+
     // Functions
 
-    pub fn journey<'a>(&'a self, data_storage: &'a DataStorage) -> Option<&Journey> {
+    /// The journey can be one of the timetable overlay.
+    pub fn journey<'a>(
+        &'a self,
+        data_storage: &'a DataStorage,
+        options: &'a RoutingOptions,
+    ) -> Option<&'a Journey> {
         self.journey_id.map(|id| {
-            data_storage
-                .journeys()
-                .find(id)
+            find_journey(data_storage, options, id)
                 .unwrap_or_else(|| panic!("Journey {:?} not found.", id))
         })
     }
+
+    /// Only used by the backward search, which does not support the real-time updates.
+    /// The journey can be one of the timetable overlay.
+    pub fn departure_at(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+    ) -> NaiveDateTime {
+        match self.journey(data_storage, options) {
+            Some(journey) => journey.departure_at_of_with_origin(
+                self.departure_stop_id,
+                self.arrival_at.date(),
+                false,
+                self.arrival_stop_id,
+            ),
+            // A walking trip always has a duration.
+            None => add_minutes_to_date_time(self.arrival_at, -i64::from(self.duration.unwrap())),
+        }
+    }
+
+    /// Returns true if the journey of the section continues as the journey of the next section (through service).
+    pub fn is_continued_by(
+        &self,
+        data_storage: &DataStorage,
+        index: &RoutingIndex,
+        next: &RouteSection,
+    ) -> bool {
+        let (Some(journey_id_1), Some(journey_id_2)) = (self.journey_id, next.journey_id) else {
+            return false;
+        };
+
+        self.arrival_stop_id == next.departure_stop_id
+            && is_through_service(
+                data_storage,
+                index,
+                self.arrival_stop_id,
+                journey_id_1,
+                journey_id_2,
+                self.arrival_at,
+            )
+    }
+}
+
+/// The previous sections and the visited stops of the routes explored during a search.
+/// The visited stops are stored by their index in the RoutingIndex.
+/// A route only owns its last section, the rest is shared with the routes it was extended from.
+#[derive(Debug, Default)]
+pub struct RouteArena {
+    sections: Vec<RouteSectionNode>,
+    visited_stops: Vec<u32>,
+    visited_stop_nodes: Vec<VisitedStopsNode>,
+}
+
+#[derive(Debug)]
+struct RouteSectionNode {
+    section: RouteSection,
+    previous: Option<usize>,
+}
+
+/// The stops visited by a section, which are visited_stops[start..end] in the arena.
+#[derive(Debug)]
+struct VisitedStopsNode {
+    start: usize,
+    end: usize,
+    previous: Option<usize>,
+}
+
+impl RouteArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Getters/Setters
+
+    pub fn visited_stops(&self) -> &Vec<u32> {
+        &self.visited_stops
+    }
+
+    /// The stops visited by a new section are pushed at the end, before the route is created.
+    pub fn visited_stops_mut(&mut self) -> &mut Vec<u32> {
+        &mut self.visited_stops
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Route {
-    sections: Vec<RouteSection>,
-    visited_stops: FxHashSet<i32>,
+    last_section: RouteSection,
+    // In the arena.
+    previous_section: Option<usize>,
+    section_count: usize,
+    // A journey continuing as another journey (through service) is not counted as a connection.
+    connection_count: usize,
+    walking_duration: i64,
+    // In the arena.
+    visited_stops: Option<usize>,
+    // One bit per visited stop, most stops are known not to have been visited without looking them up in the arena.
+    visited_stops_filter: [u64; 4],
 }
 
 impl Route {
-    pub fn new(sections: Vec<RouteSection>, visited_stops: FxHashSet<i32>) -> Self {
-        Self {
-            sections,
-            visited_stops,
-        }
+    /// The stops visited by the section must have been pushed to the arena from visited_stops_start.
+    pub fn new(arena: &mut RouteArena, section: RouteSection, visited_stops_start: usize) -> Self {
+        let mut route = Self {
+            connection_count: usize::from(section.journey_id().is_some()),
+            walking_duration: walking_duration_of(&section),
+            last_section: section,
+            previous_section: None,
+            section_count: 1,
+            visited_stops: None,
+            visited_stops_filter: [0; 4],
+        };
+        route.add_visited_stops(arena, visited_stops_start);
+        route
     }
 
     // Getters/Setters
 
-    pub fn sections(&self) -> &Vec<RouteSection> {
-        &self.sections
+    pub fn last_section(&self) -> &RouteSection {
+        &self.last_section
     }
 
-    pub fn visited_stops(&self) -> &FxHashSet<i32> {
-        &self.visited_stops
+    pub fn last_section_mut(&mut self) -> &mut RouteSection {
+        &mut self.last_section
     }
 
-    // Functions
+    pub fn section_count(&self) -> usize {
+        self.section_count
+    }
 
-    pub fn last_section(&self) -> &RouteSection {
-        // A route always contains at least one section.
-        self.sections.last().unwrap()
+    pub fn connection_count(&self) -> usize {
+        self.connection_count
     }
 
-    pub fn last_section_mut(&mut self) -> &mut RouteSection {
-        // A route always contains at least one section.
-        self.sections.last_mut().unwrap()
+    /// The total walking duration, in minutes.
+    pub fn walking_duration(&self) -> i64 {
+        self.walking_duration
     }
 
+    // Functions
+
     pub fn arrival_stop_id(&self) -> i32 {
         self.last_section().arrival_stop_id()
     }
 
+    pub fn arrival_stop_index(&self) -> u32 {
+        self.last_section().arrival_stop_index()
+    }
+
     pub fn arrival_at(&self) -> NaiveDateTime {
         self.last_section().arrival_at()
     }
 
-    pub fn has_visited_any_stops(&self, stops: &FxHashSet<i32>) -> bool {
-        !self.visited_stops.is_disjoint(stops)
+    /// Returns a new route, the section coming after the last section.
+    /// The stops visited by the section must have been pushed to the arena from visited_stops_start.
+    pub fn with_next_section(
+        &self,
+        arena: &mut RouteArena,
+        data_storage: &DataStorage,
+        index: &RoutingIndex,
+        section: RouteSection,
+        visited_stops_start: usize,
+    ) -> Route {
+        // The sections come in reverse order for the routes built backward.
+        let is_through_service = self
+            .last_section
+            .is_continued_by(data_storage, index, &section)
+            || section.is_continued_by(data_storage, index, &self.last_section);
+        let connection_count = self.connection_count
+            + usize::from(section.journey_id().is_some() && !is_through_service);
+
+        arena.sections.push(RouteSectionNode {
+            section: self.last_section.clone(),
+            previous: self.previous_section,
+        });
+
+        let mut route = Self {
+            connection_count,
+            walking_duration: self.walking_duration + walking_duration_of(&section),
+            last_section: section,
+            previous_section: Some(arena.sections.len() - 1),
+            section_count: self.section_count + 1,
+            ..self.clone()
+        };
+        route.add_visited_stops(arena, visited_stops_start);
+        route
     }
 
-    pub fn sections_having_journey(&self) -> Vec<&RouteSection> {
-        self.sections
+    /// Returns a new route, the section replacing the last section, which must have the same journey.
+    /// The stops visited by the section must have been pushed to the arena from visited_stops_start.
+    pub fn with_last_section(
+        &self,
+        arena: &mut RouteArena,
+        section: RouteSection,
+        visited_stops_start: usize,
+    ) -> Route {
+        let mut route = Self {
+            last_section: section,
+            ..self.clone()
+        };
+        route.add_visited_stops(arena, visited_stops_start);
+        route
+    }
+
+    fn add_visited_stops(&mut self, arena: &mut RouteArena, visited_stops_start: usize) {
+        let end = arena.visited_stops.len();
+
+        if visited_stops_start == end {
+            return;
+        }
+
+        for &stop_index in &arena.visited_stops[visited_stops_start..end] {
+            let (index, mask) = visited_stops_filter_bit(stop_index);
+            self.visited_stops_filter[index] |= mask;
+        }
+
+        arena.visited_stop_nodes.push(VisitedStopsNode {
+            start: visited_stops_start,
+            end,
+            previous: self.visited_stops,
+        });
+        self.visited_stops = Some(arena.visited_stop_nodes.len() - 1);
+    }
+
+    pub fn has_visited_stop(&self, arena: &RouteArena, stop_index: u32) -> bool {
+        let (index, mask) = visited_stops_filter_bit(stop_index);
+
+        if self.visited_stops_filter[index] & mask == 0 {
+            return false;
+        }
+
+        let mut node_index = self.visited_stops;
+
+        while let Some(i) = node_index {
+            let node = &arena.visited_stop_nodes[i];
+
+            if arena.visited_stops[node.start..node.end].contains(&stop_index) {
+                return true;
+            }
+
+            node_index = node.previous;
+        }
+
+        false
+    }
+
+    /// Returns true if any of the stops pushed to the arena from visited_stops_start has been visited.
+    pub fn has_visited_any_stops(&self, arena: &RouteArena, visited_stops_start: usize) -> bool {
+        arena.visited_stops[visited_stops_start..]
             .iter()
-            .filter(|section| section.journey_id().is_some())
-            .collect()
+            .any(|&stop_index| self.has_visited_stop(arena, stop_index))
+    }
+
+    /// Returns the sections from the last one to the first one.
+    pub fn sections_rev<'a>(
+        &'a self,
+        arena: &'a RouteArena,
+    ) -> impl Iterator<Item = &'a RouteSection> {
+        iter::successors(
+            Some((&self.last_section, self.previous_section)),
+            |&(_, previous)| {
+                previous.map(|i| {
+                    let node = &arena.sections[i];
+                    (&node.section, node.previous)
+                })
+            },
+        )
+        .map(|(section, _)| section)
+    }
+
+    pub fn sections(&self, arena: &RouteArena) -> Vec<RouteSection> {
+        let mut sections: Vec<_> = self.sections_rev(arena).cloned().collect();
+        sections.reverse();
+        sections
     }
+}
 
-    pub fn count_connections(&self) -> usize {
-        self.sections_having_journey().len()
+fn walking_duration_of(section: &RouteSection) -> i64 {
+    if section.journey_id().is_none() {
+        i64::from(section.duration().unwrap_or(0))
+    } else {
+        0
     }
 }
 
+/// Returns the word and the bit of the visited stops filter corresponding to the stop.
+/// The stop indices are dense, their lowest bits are evenly spread.
+fn visited_stops_filter_bit(stop_index: u32) -> (usize, u64) {
+    let bit = stop_index % 256;
+    ((bit / 64) as usize, 1 << (bit % 64))
+}
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum RoutingAlgorithmMode {
     SolveFromDepartureStopToArrivalStop,
@@ -189,6 +523,759 @@ impl RoutingAlgorithmArgs {
     }
 }
 
+/// A stop the route must go through, the route stays there at least the dwell duration if any.
+#[derive(Debug, Clone, Copy)]
+pub struct ViaStop {
+    stop_id: i32,
+    dwell_duration: Option<Duration>,
+}
+
+impl ViaStop {
+    pub fn new(stop_id: i32, dwell_duration: Option<Duration>) -> Self {
+        Self {
+            stop_id,
+            dwell_duration,
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn stop_id(&self) -> i32 {
+        self.stop_id
+    }
+
+    pub fn dwell_duration(&self) -> Option<Duration> {
+        self.dwell_duration
+    }
+}
+
+/// Either the values of the set are avoided or only them are allowed.
+#[derive(Debug, Clone)]
+pub enum RoutingFilter<T> {
+    Avoid(FxHashSet<T>),
+    Require(FxHashSet<T>),
+}
+
+impl<T> Default for RoutingFilter<T> {
+    fn default() -> Self {
+        Self::Avoid(FxHashSet::default())
+    }
+}
+
+impl<T: Eq + Hash> RoutingFilter<T> {
+    /// Returns true if the filter lets every value through.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Self::Avoid(values) if values.is_empty())
+    }
+
+    pub fn accepts<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        match self {
+            Self::Avoid(values) => !values.contains(value),
+            Self::Require(values) => values.contains(value),
+        }
+    }
+
+    /// Same as accepts, but the values of the filter are matched by the predicate.
+    pub fn accepts_by<F>(&self, predicate: F) -> bool
+    where
+        F: FnMut(&T) -> bool,
+    {
+        match self {
+            Self::Avoid(values) => !values.iter().any(predicate),
+            Self::Require(values) => values.iter().any(predicate),
+        }
+    }
+}
+
+/// The accessibility requirements of a routing query, e.g. for a wheelchair user.
+/// The attributes are identified by their designation (ATTRIBUT), e.g. "NF" for low-floor vehicles.
+#[derive(Debug, Clone, Default)]
+pub struct AccessibilityProfile {
+    required_attributes: FxHashSet<String>,
+    additional_exchange_time: i16,
+    inaccessible_stop_connection_attributes: FxHashSet<String>,
+}
+
+impl AccessibilityProfile {
+    pub fn new(
+        required_attributes: FxHashSet<String>,
+        additional_exchange_time: i16,
+        inaccessible_stop_connection_attributes: FxHashSet<String>,
+    ) -> Self {
+        Self {
+            required_attributes,
+            additional_exchange_time,
+            inaccessible_stop_connection_attributes,
+        }
+    }
+
+    // Getters/Setters
+
+    /// A journey can only be ridden between stops where it has all these attributes, from the stop where it is boarded to the stop where it is left.
+    pub fn required_attributes(&self) -> &FxHashSet<String> {
+        &self.required_attributes
+    }
+
+    /// Added to every exchange time and to the walked changes, except for the through services, in minutes.
+    pub fn additional_exchange_time(&self) -> i16 {
+        self.additional_exchange_time
+    }
+
+    /// A stop connection having one of these attributes cannot be walked.
+    pub fn inaccessible_stop_connection_attributes(&self) -> &FxHashSet<String> {
+        &self.inaccessible_stop_connection_attributes
+    }
+}
+
+/// The options of a routing query.
+/// The lookahead horizon and the overnight cutoff limit the departures considered at each stop,
+/// the lookahead horizon and the evening cutoff the arrivals considered by the backward search.
+/// They only apply to the level-by-level exploration, RAPTOR and CSA consider every departure.
+/// The filters apply to all the routing algorithms, a stop that is not accepted can neither be used for exchange nor walked to, but the journeys can pass through it.
+/// The timetable overlay is only supported by the level exploration, the event sink, the real-time updates, the scenario and the journey cost only by the level exploration searching forward,
+/// the other searches are aborted with AbortReason::UnsupportedOptions if one of them is set.
+#[derive(Debug, Clone)]
+pub struct RoutingOptions {
+    maximum_number_of_transfers: usize,
+    lookahead_horizon: Duration,
+    overnight_cutoff: NaiveTime,
+    evening_cutoff: NaiveTime,
+    stop_filter: RoutingFilter<i32>,
+    transport_type_filter: RoutingFilter<String>,
+    administration_filter: RoutingFilter<String>,
+    line_filter: RoutingFilter<String>,
+    accessibility_profile: Option<AccessibilityProfile>,
+    maximum_walking_distance: f64,
+    walking_speed: f64,
+    deadline: Option<Instant>,
+    cancellation_token: Option<CancellationToken>,
+    maximum_explored_routes: Option<usize>,
+    explored_routes_counter: Option<Arc<AtomicUsize>>,
+    event_sink: Option<Arc<dyn SearchEventSink>>,
+    real_time_updates: Option<Arc<RealTimeUpdates>>,
+    scenario: Option<Arc<Scenario>>,
+    timetable_overlay: Option<Arc<TimetableOverlay>>,
+    journey_cost: Option<Arc<dyn JourneyCost>>,
+    minimum_transfer_slack: Duration,
+}
+
+impl RoutingOptions {
+    pub fn new(
+        maximum_number_of_transfers: usize,
+        lookahead_horizon: Duration,
+        overnight_cutoff: NaiveTime,
+    ) -> Self {
+        Self {
+            maximum_number_of_transfers,
+            lookahead_horizon,
+            overnight_cutoff,
+            evening_cutoff: create_time(DEFAULT_EVENING_CUTOFF_HOUR, 0),
+            stop_filter: RoutingFilter::default(),
+            transport_type_filter: RoutingFilter::default(),
+            administration_filter: RoutingFilter::default(),
+            line_filter: RoutingFilter::default(),
+            accessibility_profile: None,
+            maximum_walking_distance: DEFAULT_MAXIMUM_WALKING_DISTANCE_IN_METERS,
+            walking_speed: DEFAULT_WALKING_SPEED_IN_KILOMETERS_PER_HOUR,
+            deadline: None,
+            cancellation_token: None,
+            maximum_explored_routes: None,
+            explored_routes_counter: None,
+            event_sink: None,
+            real_time_updates: None,
+            scenario: None,
+            timetable_overlay: None,
+            journey_cost: None,
+            minimum_transfer_slack: Duration::zero(),
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn maximum_number_of_transfers(&self) -> usize {
+        self.maximum_number_of_transfers
+    }
+
+    pub fn set_maximum_number_of_transfers(&mut self, value: usize) {
+        self.maximum_number_of_transfers = value;
+    }
+
+    /// The departures are considered up to this duration after the arrival at a stop.
+    pub fn lookahead_horizon(&self) -> Duration {
+        self.lookahead_horizon
+    }
+
+    pub fn set_lookahead_horizon(&mut self, value: Duration) {
+        self.lookahead_horizon = value;
+    }
+
+    /// Overnight and early in the morning, the departures are considered up to this time.
+    pub fn overnight_cutoff(&self) -> NaiveTime {
+        self.overnight_cutoff
+    }
+
+    pub fn set_overnight_cutoff(&mut self, value: NaiveTime) {
+        self.overnight_cutoff = value;
+    }
+
+    /// Late in the evening, the backward search considers the arrivals down to this time, the counterpart of the overnight cutoff.
+    pub fn evening_cutoff(&self) -> NaiveTime {
+        self.evening_cutoff
+    }
+
+    pub fn set_evening_cutoff(&mut self, value: NaiveTime) {
+        self.evening_cutoff = value;
+    }
+
+    pub fn stop_filter(&self) -> &RoutingFilter<i32> {
+        &self.stop_filter
+    }
+
+    pub fn set_stop_filter(&mut self, value: RoutingFilter<i32>) {
+        self.stop_filter = value;
+    }
+
+    /// The transport types are identified by their designation (e.g. "IC", "B").
+    pub fn transport_type_filter(&self) -> &RoutingFilter<String> {
+        &self.transport_type_filter
+    }
+
+    pub fn set_transport_type_filter(&mut self, value: RoutingFilter<String>) {
+        self.transport_type_filter = value;
+    }
+
+    pub fn administration_filter(&self) -> &RoutingFilter<String> {
+        &self.administration_filter
+    }
+
+    pub fn set_administration_filter(&mut self, value: RoutingFilter<String>) {
+        self.administration_filter = value;
+    }
+
+    /// The lines are identified as in the HRDF, "#" followed by the line id or the line designation.
+    pub fn line_filter(&self) -> &RoutingFilter<String> {
+        &self.line_filter
+    }
+
+    pub fn set_line_filter(&mut self, value: RoutingFilter<String>) {
+        self.line_filter = value;
+    }
+
+    pub fn accessibility_profile(&self) -> Option<&AccessibilityProfile> {
+        self.accessibility_profile.as_ref()
+    }
+
+    pub fn set_accessibility_profile(&mut self, value: Option<AccessibilityProfile>) {
+        self.accessibility_profile = value;
+    }
+
+    /// In meters, the stops within this distance of a point of origin or destination can be walked to.
+    pub fn maximum_walking_distance(&self) -> f64 {
+        self.maximum_walking_distance
+    }
+
+    pub fn set_maximum_walking_distance(&mut self, value: f64) {
+        self.maximum_walking_distance = value;
+    }
+
+    /// In kilometers per hour, used to walk from a point of origin and to a point of destination.
+    pub fn walking_speed(&self) -> f64 {
+        self.walking_speed
+    }
+
+    pub fn set_walking_speed(&mut self, value: f64) {
+        self.walking_speed = value;
+    }
+
+    /// The search is aborted once this instant has passed.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn set_deadline(&mut self, value: Option<Instant>) {
+        self.deadline = value;
+    }
+
+    /// The search is aborted once the token is cancelled.
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancellation_token.as_ref()
+    }
+
+    pub fn set_cancellation_token(&mut self, value: Option<CancellationToken>) {
+        self.cancellation_token = value;
+    }
+
+    /// The search is aborted once this number of routes has been explored.
+    /// The connection scan counts the connections instead of the routes.
+    pub fn maximum_explored_routes(&self) -> Option<usize> {
+        self.maximum_explored_routes
+    }
+
+    pub fn set_maximum_explored_routes(&mut self, value: Option<usize>) {
+        self.maximum_explored_routes = value;
+    }
+
+    /// Shared by the searches run with the clones of the options, which then share the maximum number of explored routes.
+    pub(crate) fn explored_routes_counter(&self) -> Option<&Arc<AtomicUsize>> {
+        self.explored_routes_counter.as_ref()
+    }
+
+    pub(crate) fn set_explored_routes_counter(&mut self, value: Option<Arc<AtomicUsize>>) {
+        self.explored_routes_counter = value;
+    }
+
+    /// Receives the events explaining why routes and journeys were not explored further.
+    pub fn event_sink(&self) -> Option<&Arc<dyn SearchEventSink>> {
+        self.event_sink.as_ref()
+    }
+
+    pub fn set_event_sink(&mut self, value: Option<Arc<dyn SearchEventSink>>) {
+        self.event_sink = value;
+    }
+
+    /// The delays and cancellations applied on top of the static timetable, see RealTimeFeed to replace them at runtime.
+    pub fn real_time_updates(&self) -> Option<&RealTimeUpdates> {
+        self.real_time_updates.as_deref()
+    }
+
+    pub fn set_real_time_updates(&mut self, value: Option<Arc<RealTimeUpdates>>) {
+        self.real_time_updates = value;
+    }
+
+    /// The disruption masking stops, journeys, stop connections and segments, the timetable itself is left untouched.
+    pub fn scenario(&self) -> Option<&Scenario> {
+        self.scenario.as_deref()
+    }
+
+    pub fn set_scenario(&mut self, value: Option<Arc<Scenario>>) {
+        self.scenario = value;
+    }
+
+    /// The hypothetical stops, stop connections and journeys added on top of the timetable.
+    pub fn timetable_overlay(&self) -> Option<&TimetableOverlay> {
+        self.timetable_overlay.as_deref()
+    }
+
+    pub fn set_timetable_overlay(&mut self, value: Option<Arc<TimetableOverlay>>) {
+        self.timetable_overlay = value;
+    }
+
+    /// Decides which route is kept as the solution at each stop, EarliestArrivalCost if there is none.
+    pub fn journey_cost(&self) -> &dyn JourneyCost {
+        self.journey_cost.as_deref().unwrap_or(&EarliestArrivalCost)
+    }
+
+    pub fn set_journey_cost(&mut self, value: Option<Arc<dyn JourneyCost>>) {
+        self.journey_cost = value;
+    }
+
+    /// The time that must be left when changing, once the exchange time or the walk is over.
+    pub fn minimum_transfer_slack(&self) -> Duration {
+        self.minimum_transfer_slack
+    }
+
+    pub fn set_minimum_transfer_slack(&mut self, value: Duration) {
+        self.minimum_transfer_slack = value;
+    }
+
+    // Functions
+
+    /// The event is only built if there is an event sink.
+    pub fn record_event<F: FnOnce() -> SearchEvent>(&self, event: F) {
+        if let Some(event_sink) = &self.event_sink {
+            event_sink.record(event());
+        }
+    }
+
+    /// The real-time arrival delay in minutes, 0 without real-time updates.
+    pub fn arrival_delay(&self, journey_id: i32, operating_date: NaiveDate, stop_id: i32) -> i64 {
+        self.real_time_updates().map_or(0, |updates| {
+            updates
+                .arrival_delay(journey_id, operating_date, stop_id)
+                .into()
+        })
+    }
+
+    /// The real-time departure delay in minutes, 0 without real-time updates.
+    pub fn departure_delay(&self, journey_id: i32, operating_date: NaiveDate, stop_id: i32) -> i64 {
+        self.real_time_updates().map_or(0, |updates| {
+            updates
+                .departure_delay(journey_id, operating_date, stop_id)
+                .into()
+        })
+    }
+
+    /// Returns true if the journey is cancelled or does not stop at the stop according to the real-time updates.
+    pub fn is_stop_cancelled(
+        &self,
+        journey_id: i32,
+        operating_date: NaiveDate,
+        stop_id: i32,
+    ) -> bool {
+        self.real_time_updates()
+            .is_some_and(|updates| updates.is_stop_cancelled(journey_id, operating_date, stop_id))
+    }
+
+    /// Returns true if the journey departing from its first stop on the operating date can be boarded or left at the stop at the given time,
+    /// according to the real-time updates and the scenario.
+    pub fn is_stop_served(
+        &self,
+        journey_id: i32,
+        operating_date: NaiveDate,
+        stop_id: i32,
+        at: NaiveDateTime,
+    ) -> bool {
+        !self.is_stop_cancelled(journey_id, operating_date, stop_id)
+            && self.scenario().is_none_or(|scenario| {
+                !scenario.is_stop_closed(stop_id, at)
+                    && !scenario.is_journey_cancelled(journey_id, at)
+            })
+    }
+
+    /// Returns true if the stop connection can be walked from the departure stop at the given time, and the arrival stop is not closed when reached.
+    pub fn is_stop_connection_open(
+        &self,
+        departure_stop_id: i32,
+        arrival_stop_id: i32,
+        departure_at: NaiveDateTime,
+        arrival_at: NaiveDateTime,
+    ) -> bool {
+        self.scenario().is_none_or(|scenario| {
+            !scenario.is_stop_connection_closed(departure_stop_id, arrival_stop_id, departure_at)
+                && !scenario.is_stop_closed(arrival_stop_id, arrival_at)
+        })
+    }
+
+    pub fn maximum_number_of_journeys(&self) -> usize {
+        self.maximum_number_of_transfers + 1
+    }
+
+    /// Returns true if an option only supported by the level exploration is set.
+    pub fn has_level_exploration_options(&self) -> bool {
+        self.has_forward_search_options() || self.timetable_overlay.is_some()
+    }
+
+    /// Returns true if an option only supported by the level exploration searching forward is set.
+    pub fn has_forward_search_options(&self) -> bool {
+        self.event_sink.is_some()
+            || self.real_time_updates.is_some()
+            || self.scenario.is_some()
+            || self.journey_cost.is_some()
+    }
+
+    /// Returns true if some journeys may not be accepted.
+    pub fn has_journey_filters(&self) -> bool {
+        !self.transport_type_filter.is_empty()
+            || !self.administration_filter.is_empty()
+            || !self.line_filter.is_empty()
+            || self
+                .accessibility_profile
+                .as_ref()
+                .is_some_and(|profile| !profile.required_attributes.is_empty())
+    }
+
+    pub fn additional_exchange_time(&self) -> i16 {
+        self.accessibility_profile
+            .as_ref()
+            .map_or(0, |profile| profile.additional_exchange_time)
+    }
+
+    pub fn accepts_stop(&self, stop_id: i32) -> bool {
+        self.stop_filter.accepts(&stop_id)
+    }
+
+    /// Returns true if the journey can be boarded at the stop.
+    pub fn accepts_journey(
+        &self,
+        data_storage: &DataStorage,
+        index: &RoutingIndex,
+        journey: &Journey,
+        stop_id: i32,
+    ) -> bool {
+        self.accepts_journey_at(data_storage, index, journey, stop_id, true)
+    }
+
+    /// Returns true if the journey can be left at the stop, the counterpart of accepts_journey for the backward search.
+    pub fn accepts_journey_arriving(
+        &self,
+        data_storage: &DataStorage,
+        index: &RoutingIndex,
+        journey: &Journey,
+        stop_id: i32,
+    ) -> bool {
+        self.accepts_journey_at(data_storage, index, journey, stop_id, false)
+    }
+
+    fn accepts_journey_at(
+        &self,
+        data_storage: &DataStorage,
+        index: &RoutingIndex,
+        journey: &Journey,
+        stop_id: i32,
+        is_boarding: bool,
+    ) -> bool {
+        if !self.transport_type_filter.is_empty()
+            && !self
+                .transport_type_filter
+                .accepts(journey.transport_type(data_storage).designation())
+        {
+            return false;
+        }
+
+        if !self.administration_filter.accepts(journey.administration()) {
+            return false;
+        }
+
+        if !self.line_filter.is_empty() {
+            let journey_line = index.journey_line(journey, stop_id);
+
+            if !self
+                .line_filter
+                .accepts_by(|line_id| journey_line.has_line(line_id))
+            {
+                return false;
+            }
+        }
+
+        match &self.accessibility_profile {
+            Some(profile) if !profile.required_attributes.is_empty() => {
+                let route = journey.route();
+
+                // The stop to the next one if the journey is boarded, the previous stop to the stop otherwise.
+                let segment = if is_boarding {
+                    route
+                        .iter()
+                        .position(|route_entry| route_entry.stop_id() == stop_id)
+                } else {
+                    route
+                        .iter()
+                        .skip(1)
+                        .position(|route_entry| route_entry.stop_id() == stop_id)
+                };
+
+                segment.is_some_and(|position| {
+                    self.accepts_journey_segment(index, journey.id(), position)
+                })
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns true if the journey can be ridden from the stop at the position in its route to the next one.
+    pub fn accepts_journey_segment(
+        &self,
+        index: &RoutingIndex,
+        journey_id: i32,
+        position: usize,
+    ) -> bool {
+        self.accessibility_profile.as_ref().is_none_or(|profile| {
+            index.journey_has_attributes(
+                journey_id,
+                &profile.required_attributes,
+                position,
+                position + 1,
+            )
+        })
+    }
+
+    /// Returns true if the stop connection from stop 1 to stop 2 can be walked.
+    pub fn accepts_stop_connection(
+        &self,
+        index: &RoutingIndex,
+        stop_id_1: i32,
+        stop_id_2: i32,
+    ) -> bool {
+        let Some(profile) = &self.accessibility_profile else {
+            return true;
+        };
+
+        if profile.inaccessible_stop_connection_attributes.is_empty() {
+            return true;
+        }
+
+        index
+            .stop_connection_attribute(stop_id_1, stop_id_2)
+            .is_none_or(|attribute| {
+                !profile
+                    .inaccessible_stop_connection_attributes
+                    .contains(attribute)
+            })
+    }
+}
+
+impl Default for RoutingOptions {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_MAXIMUM_NUMBER_OF_TRANSFERS,
+            Duration::minutes(DEFAULT_LOOKAHEAD_HORIZON_IN_MINUTES),
+            create_time(DEFAULT_OVERNIGHT_CUTOFF_HOUR, 0),
+        )
+    }
+}
+
+/// Cancels a search from another thread, the clones of a token share its state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    is_cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Functions
+
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortReason {
+    Cancelled,
+    DeadlineExceeded,
+    MaximumExploredRoutesReached,
+    UnsupportedOptions,
+}
+
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            AbortReason::Cancelled => "the search was cancelled",
+            AbortReason::DeadlineExceeded => "the deadline was exceeded",
+            AbortReason::MaximumExploredRoutesReached => {
+                "the maximum number of explored routes was reached"
+            }
+            AbortReason::UnsupportedOptions => "the routing options are not supported",
+        };
+        f.write_str(description)
+    }
+}
+
+/// The search was aborted before it completed, the partial result holds the best routes found until then.
+#[derive(Debug)]
+pub struct SearchAborted<T> {
+    reason: AbortReason,
+    partial_result: T,
+}
+
+impl<T> SearchAborted<T> {
+    pub fn new(reason: AbortReason, partial_result: T) -> Self {
+        Self {
+            reason,
+            partial_result,
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn reason(&self) -> AbortReason {
+        self.reason
+    }
+
+    pub fn partial_result(&self) -> &T {
+        &self.partial_result
+    }
+
+    pub fn into_partial_result(self) -> T {
+        self.partial_result
+    }
+
+    // Functions
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> SearchAborted<U> {
+        SearchAborted::new(self.reason, f(self.partial_result))
+    }
+}
+
+impl<T> fmt::Display for SearchAborted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Search aborted: {}.", self.reason)
+    }
+}
+
+impl<T: fmt::Debug> Error for SearchAborted<T> {}
+
+pub type SearchResult<T> = Result<T, SearchAborted<T>>;
+
+/// Counts the routes explored by a search, which is aborted once a limit of the routing options is reached.
+#[derive(Debug)]
+pub struct ExplorationBudget {
+    deadline: Option<Instant>,
+    cancellation_token: Option<CancellationToken>,
+    maximum_explored_routes: Option<usize>,
+    explored_routes: usize,
+    explored_routes_counter: Option<Arc<AtomicUsize>>,
+}
+
+impl ExplorationBudget {
+    pub fn new(options: &RoutingOptions) -> Self {
+        Self {
+            deadline: options.deadline(),
+            cancellation_token: options.cancellation_token().cloned(),
+            maximum_explored_routes: options.maximum_explored_routes(),
+            explored_routes: 0,
+            explored_routes_counter: options.explored_routes_counter().cloned(),
+        }
+    }
+
+    // Functions
+
+    /// Records one more explored route, returns an error if the search must be aborted.
+    /// The deadline and the cancellation are only checked at intervals, starting with the first route, as reading the clock is costly.
+    pub fn explore(&mut self) -> Result<(), AbortReason> {
+        let is_check_due = self
+            .explored_routes
+            .is_multiple_of(EXPLORATION_CHECK_INTERVAL);
+        self.explored_routes += 1;
+
+        // The routes explored by the other searches sharing the counter also count.
+        let explored_routes = self
+            .explored_routes_counter
+            .as_ref()
+            .map_or(self.explored_routes, |counter| {
+                counter.fetch_add(1, Ordering::Relaxed) + 1
+            });
+
+        if self
+            .maximum_explored_routes
+            .is_some_and(|maximum| explored_routes > maximum)
+        {
+            return Err(AbortReason::MaximumExploredRoutesReached);
+        }
+
+        if !is_check_due {
+            return Ok(());
+        }
+
+        if self
+            .cancellation_token
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled())
+        {
+            return Err(AbortReason::Cancelled);
+        }
+
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(AbortReason::DeadlineExceeded);
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct RouteResult {
     departure_at: NaiveDateTime,
@@ -211,6 +1298,10 @@ impl RouteResult {
 
     // Getters/Setters
 
+    pub fn departure_at(&self) -> NaiveDateTime {
+        self.departure_at
+    }
+
     pub fn arrival_at(&self) -> NaiveDateTime {
         self.arrival_at
     }
@@ -218,6 +1309,10 @@ impl RouteResult {
     pub fn sections(&self) -> &Vec<RouteSectionResult> {
         &self.sections
     }
+
+    pub fn into_sections(self) -> Vec<RouteSectionResult> {
+        self.sections
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -232,9 +1327,14 @@ pub struct RouteSectionResult {
     departure_at: Option<NaiveDateTime>,
     arrival_at: Option<NaiveDateTime>,
     duration: Option<i16>,
+    is_through_service: bool,
+    stopover_duration: Option<i16>,
+    accessibility_attributes: Vec<String>,
+    transfer_slack: Option<i16>,
 }
 
 impl RouteSectionResult {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         journey_id: Option<i32>,
         departure_stop_id: i32,
@@ -258,6 +1358,10 @@ impl RouteSectionResult {
             departure_at,
             arrival_at,
             duration,
+            is_through_service: false,
+            stopover_duration: None,
+            accessibility_attributes: Vec::new(),
+            transfer_slack: None,
         }
     }
 
@@ -275,13 +1379,21 @@ impl RouteSectionResult {
         self.arrival_stop_id
     }
 
+    pub fn departure_stop_lv95_coordinates(&self) -> Option<Coordinates> {
+        self.departure_stop_lv95_coordinates
+    }
+
+    pub fn departure_stop_wgs84_coordinates(&self) -> Option<Coordinates> {
+        self.departure_stop_wgs84_coordinates
+    }
+
     pub fn arrival_stop_lv95_coordinates(&self) -> Option<Coordinates> {
         self.arrival_stop_lv95_coordinates
     }
 
-    // pub fn arrival_stop_wgs84_coordinates(&self) -> Option<Coordinates> {
-    //     self.arrival_stop_wgs84_coordinates
-    // }
+    pub fn arrival_stop_wgs84_coordinates(&self) -> Option<Coordinates> {
+        self.arrival_stop_wgs84_coordinates
+    }
 
     pub fn arrival_at(&self) -> Option<NaiveDateTime> {
         self.arrival_at
@@ -291,13 +1403,53 @@ impl RouteSectionResult {
         self.duration
     }
 
+    /// Returns true if the journey of the section is the continuation of the journey of the previous section, the passengers stay seated.
+    pub fn is_through_service(&self) -> bool {
+        self.is_through_service
+    }
+
+    pub fn set_is_through_service(&mut self, value: bool) {
+        self.is_through_service = value;
+    }
+
+    /// The time spent at the arrival stop of the section when it is a via stop, in minutes.
+    pub fn stopover_duration(&self) -> Option<i16> {
+        self.stopover_duration
+    }
+
+    pub fn set_stopover_duration(&mut self, value: Option<i16>) {
+        self.stopover_duration = value;
+    }
+
+    /// The attributes of the accessibility profile that the journey of the section has, from its departure stop to its arrival stop.
+    pub fn accessibility_attributes(&self) -> &Vec<String> {
+        &self.accessibility_attributes
+    }
+
+    pub fn set_accessibility_attributes(&mut self, value: Vec<String>) {
+        self.accessibility_attributes = value;
+    }
+
+    /// The time left when changing to the journey of the section once the exchange time or the walk is over, in minutes.
+    /// None for a walking trip, the first journey and a through service.
+    pub fn transfer_slack(&self) -> Option<i16> {
+        self.transfer_slack
+    }
+
+    pub fn set_transfer_slack(&mut self, value: Option<i16>) {
+        self.transfer_slack = value;
+    }
+
     // Functions
 
-    pub fn journey<'a>(&'a self, data_storage: &'a DataStorage) -> Option<&Journey> {
+    /// The journey can be one of the timetable overlay.
+    pub fn journey<'a>(
+        &'a self,
+        data_storage: &'a DataStorage,
+        options: &'a RoutingOptions,
+    ) -> Option<&'a Journey> {
         self.journey_id.map(|id| {
-            data_storage
-                .journeys()
-                .find(id)
+            find_journey(data_storage, options, id)
                 .unwrap_or_else(|| panic!("Journey {:?} not found.", id))
         })
     }
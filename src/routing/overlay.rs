@@ -0,0 +1,362 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io::Read,
+};
+
+use chrono::{NaiveDate, NaiveTime};
+use hrdf_parser::{
+    CoordinateSystem, Coordinates, DataStorage, Journey, JourneyMetadataEntry, JourneyMetadataType,
+    JourneyRouteEntry, Model, Stop, StopConnection,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
+
+use crate::isochrone::wgs84_to_lv95;
+
+/// Hypothetical stops, stop connections and journeys added on top of the timetable, which is left untouched.
+/// The synthetic journeys are given negative ids, so that they never collide with the journeys of the timetable.
+#[derive(Debug, Default)]
+pub struct TimetableOverlay {
+    stops: FxHashMap<i32, Stop>,
+    stop_connections: FxHashMap<i32, Vec<StopConnection>>,
+    journeys: FxHashMap<i32, Journey>,
+    operating_days: FxHashMap<i32, FxHashSet<NaiveDate>>,
+    journeys_by_stop_id: FxHashMap<i32, Vec<i32>>,
+}
+
+impl TimetableOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the overlay in the JSON format below, the stops are added first, then the stop connections and the journeys.
+    /// The times are local times, a time earlier than the departure from the first stop is on the next day.
+    ///
+    /// {"stops": [{"stop_id": 8599001, "name": "Olten, Neuquartier", "latitude": 47.35, "longitude": 7.90}],
+    ///  "stop_connections": [{"stop_id_1": 8500218, "stop_id_2": 8599001, "duration": 6}],
+    ///  "journeys": [{"transport_type": "B", "line": "599", "operating_days": ["2024-06-01"],
+    ///                "route": [{"stop_id": 8500218, "departure_time": "10:00"}, {"stop_id": 8599001, "arrival_time": "10:07"}]}]}
+    pub fn from_json<R: Read>(
+        data_storage: &DataStorage,
+        reader: R,
+    ) -> Result<Self, Box<dyn Error>> {
+        let message: OverlayMessage = serde_json::from_reader(reader)?;
+        let mut overlay = Self::new();
+
+        for entry in message.stops {
+            let coordinates = match (entry.latitude, entry.longitude) {
+                (Some(latitude), Some(longitude)) => Some(Coordinates::new(
+                    CoordinateSystem::WGS84,
+                    latitude,
+                    longitude,
+                )),
+                _ => None,
+            };
+            overlay.add_stop(data_storage, entry.stop_id, entry.name, coordinates)?;
+        }
+
+        for entry in message.stop_connections {
+            overlay.add_stop_connection(
+                data_storage,
+                entry.stop_id_1,
+                entry.stop_id_2,
+                entry.duration,
+            )?;
+        }
+
+        for entry in message.journeys {
+            let route = entry
+                .route
+                .into_iter()
+                .map(|stop| (stop.stop_id, stop.arrival_time, stop.departure_time))
+                .collect();
+            overlay.add_journey(
+                data_storage,
+                &entry.transport_type,
+                entry.line,
+                route,
+                entry.operating_days,
+            )?;
+        }
+
+        Ok(overlay)
+    }
+
+    // Getters/Setters
+
+    pub fn stops(&self) -> impl Iterator<Item = &Stop> {
+        self.stops.values()
+    }
+
+    pub fn journeys(&self) -> impl Iterator<Item = &Journey> {
+        self.journeys.values()
+    }
+
+    // Functions
+
+    /// Adds a stop that can be used for exchange, the coordinates must be WGS84 ones and are also converted to LV95.
+    pub fn add_stop(
+        &mut self,
+        data_storage: &DataStorage,
+        stop_id: i32,
+        name: String,
+        wgs84_coordinates: Option<Coordinates>,
+    ) -> Result<(), TimetableOverlayError> {
+        if data_storage.stops().find(stop_id).is_some() || self.stops.contains_key(&stop_id) {
+            return Err(TimetableOverlayError::StopAlreadyExists(stop_id));
+        }
+
+        let mut stop = Stop::new(stop_id, name, None, None, None);
+        stop.set_exchange_flag(1);
+
+        if let Some(coordinates) = wgs84_coordinates {
+            let (easting, northing) =
+                wgs84_to_lv95(coordinates.latitude(), coordinates.longitude());
+            stop.set_lv95_coordinates(Coordinates::new(CoordinateSystem::LV95, easting, northing));
+            stop.set_wgs84_coordinates(coordinates);
+        }
+
+        self.stops.insert(stop_id, stop);
+        Ok(())
+    }
+
+    /// Adds a stop connection that can be walked in both directions, the duration is in minutes.
+    pub fn add_stop_connection(
+        &mut self,
+        data_storage: &DataStorage,
+        stop_id_1: i32,
+        stop_id_2: i32,
+        duration: i16,
+    ) -> Result<(), TimetableOverlayError> {
+        for stop_id in [stop_id_1, stop_id_2] {
+            self.check_stop_exists(data_storage, stop_id)?;
+        }
+
+        for (stop_id_1, stop_id_2) in [(stop_id_1, stop_id_2), (stop_id_2, stop_id_1)] {
+            // The stop connections are not looked up by id.
+            let stop_connection = StopConnection::new(0, stop_id_1, stop_id_2, duration);
+            self.stop_connections
+                .entry(stop_id_1)
+                .or_default()
+                .push(stop_connection);
+        }
+        Ok(())
+    }
+
+    /// Adds a journey and returns its id.
+    /// Each stop of the route comes with its arrival and departure times, the first stop has no arrival time and the last stop no departure time.
+    pub fn add_journey(
+        &mut self,
+        data_storage: &DataStorage,
+        transport_type_designation: &str,
+        line_designation: Option<String>,
+        route: Vec<(i32, Option<NaiveTime>, Option<NaiveTime>)>,
+        operating_days: Vec<NaiveDate>,
+    ) -> Result<i32, TimetableOverlayError> {
+        let transport_type_id = data_storage
+            .transport_types()
+            .entries()
+            .into_iter()
+            .find(|transport_type| transport_type.designation() == transport_type_designation)
+            .map(|transport_type| transport_type.id())
+            .ok_or_else(|| {
+                TimetableOverlayError::TransportTypeNotFound(transport_type_designation.to_string())
+            })?;
+
+        let last = route.len().saturating_sub(1);
+        let is_valid_route = route.len() >= 2
+            && route
+                .iter()
+                .enumerate()
+                .all(|(i, &(_, arrival, departure))| {
+                    (i == 0 || arrival.is_some()) && (i == last || departure.is_some())
+                });
+
+        if !is_valid_route {
+            return Err(TimetableOverlayError::InvalidRoute);
+        }
+
+        for &(stop_id, _, _) in &route {
+            self.check_stop_exists(data_storage, stop_id)?;
+        }
+
+        let journey_id = -(self.journeys.len() as i32) - 1;
+        let mut journey = Journey::new(journey_id, String::new());
+
+        journey.add_metadata_entry(
+            JourneyMetadataType::BitField,
+            JourneyMetadataEntry::new(None, None, None, None, None, None, None, None),
+        );
+        journey.add_metadata_entry(
+            JourneyMetadataType::TransportType,
+            JourneyMetadataEntry::new(
+                None,
+                None,
+                Some(transport_type_id),
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+        );
+
+        if let Some(line_designation) = line_designation {
+            journey.add_metadata_entry(
+                JourneyMetadataType::Line,
+                JourneyMetadataEntry::new(
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(line_designation),
+                    None,
+                ),
+            );
+        }
+
+        for (i, &(stop_id, arrival_time, departure_time)) in route.iter().enumerate() {
+            // The journey is also listed at its last stop, where the backward search looks for it.
+            let journey_ids = self.journeys_by_stop_id.entry(stop_id).or_default();
+
+            if !journey_ids.contains(&journey_id) {
+                journey_ids.push(journey_id);
+            }
+
+            journey.add_route_entry(JourneyRouteEntry::new(
+                stop_id,
+                if i == 0 { None } else { arrival_time },
+                if i == last { None } else { departure_time },
+            ));
+        }
+
+        self.operating_days
+            .insert(journey_id, operating_days.into_iter().collect());
+        self.journeys.insert(journey_id, journey);
+        Ok(journey_id)
+    }
+
+    pub fn find_stop(&self, stop_id: i32) -> Option<&Stop> {
+        self.stops.get(&stop_id)
+    }
+
+    pub fn find_journey(&self, journey_id: i32) -> Option<&Journey> {
+        self.journeys.get(&journey_id)
+    }
+
+    /// Returns the stop connections starting from the stop.
+    pub fn stop_connections(&self, stop_id: i32) -> &[StopConnection] {
+        self.stop_connections
+            .get(&stop_id)
+            .map_or(&[], |stop_connections| stop_connections.as_slice())
+    }
+
+    /// Returns the journeys passing through the stop whose first departure is on the date.
+    pub fn operating_journeys(&self, date: NaiveDate, stop_id: i32) -> Vec<&Journey> {
+        self.journeys_by_stop_id
+            .get(&stop_id)
+            .map_or(Vec::new(), |journey_ids| {
+                journey_ids
+                    .iter()
+                    .filter(|journey_id| self.operating_days[journey_id].contains(&date))
+                    .map(|journey_id| &self.journeys[journey_id])
+                    .collect()
+            })
+    }
+
+    fn check_stop_exists(
+        &self,
+        data_storage: &DataStorage,
+        stop_id: i32,
+    ) -> Result<(), TimetableOverlayError> {
+        if data_storage.stops().find(stop_id).is_none() && !self.stops.contains_key(&stop_id) {
+            return Err(TimetableOverlayError::StopNotFound(stop_id));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimetableOverlayError {
+    StopAlreadyExists(i32),
+    StopNotFound(i32),
+    TransportTypeNotFound(String),
+    /// The route has less than 2 stops or misses an arrival or a departure time.
+    InvalidRoute,
+}
+
+impl Display for TimetableOverlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StopAlreadyExists(stop_id) => write!(f, "Stop {} already exists.", stop_id),
+            Self::StopNotFound(stop_id) => write!(f, "Stop {} not found.", stop_id),
+            Self::TransportTypeNotFound(designation) => {
+                write!(f, "Transport type {} not found.", designation)
+            }
+            Self::InvalidRoute => write!(f, "Invalid route."),
+        }
+    }
+}
+
+impl Error for TimetableOverlayError {}
+
+#[derive(Debug, Deserialize)]
+struct OverlayMessage {
+    #[serde(default)]
+    stops: Vec<StopEntry>,
+    #[serde(default)]
+    stop_connections: Vec<StopConnectionEntry>,
+    #[serde(default)]
+    journeys: Vec<JourneyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopEntry {
+    stop_id: i32,
+    name: String,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopConnectionEntry {
+    stop_id_1: i32,
+    stop_id_2: i32,
+    duration: i16,
+}
+
+#[derive(Debug, Deserialize)]
+struct JourneyEntry {
+    transport_type: String,
+    line: Option<String>,
+    operating_days: Vec<NaiveDate>,
+    route: Vec<RouteEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteEntry {
+    stop_id: i32,
+    #[serde(default, with = "optional_time")]
+    arrival_time: Option<NaiveTime>,
+    #[serde(default, with = "optional_time")]
+    departure_time: Option<NaiveTime>,
+}
+
+// The times are written as "HH:MM".
+mod optional_time {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| {
+                NaiveTime::parse_from_str(&value, "%H:%M").map_err(serde::de::Error::custom)
+            })
+            .transpose()
+    }
+}
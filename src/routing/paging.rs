@@ -0,0 +1,235 @@
+use chrono::{Duration, NaiveDateTime};
+use hrdf_parser::{timetable_start_date, DataStorage};
+
+use crate::utils::{add_minutes_to_date_time, create_time};
+
+use super::{
+    algorithm::RoutingAlgorithm,
+    constants::MAXIMUM_EARLIER_DEPARTURE_LOOKBEHIND_IN_HOURS,
+    models::{RouteResult, RoutingAlgorithmArgs, RoutingOptions, SearchResult},
+    utils::{map_search_result, to_search_result, with_shared_budget},
+};
+
+/// The first route departing strictly later than the route, between the same stops.
+/// The route must have been planned from stop to stop, a door-to-door route is not supported.
+pub fn compute_later_routing(
+    data_storage: &DataStorage,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    route: &RouteResult,
+    verbose: bool,
+) -> SearchResult<Option<RouteResult>> {
+    let (departure_stop_id, arrival_stop_id) = route_stop_ids(route);
+
+    find_route(
+        data_storage,
+        algorithm,
+        options,
+        departure_stop_id,
+        arrival_stop_id,
+        add_minutes_to_date_time(route.departure_at(), 1),
+        verbose,
+    )
+}
+
+/// Among the routes arriving strictly earlier than the route between the same stops, the one departing as late as possible.
+/// The route must have been planned from stop to stop, a door-to-door route is not supported.
+/// The forward searches being the only ones available to all algorithms, the latest departure time from which
+/// the arrival is still earlier is narrowed down minute by minute, looking back up to a day.
+/// The searches share the deadline and the maximum number of explored routes of the options.
+pub fn compute_earlier_routing(
+    data_storage: &DataStorage,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    route: &RouteResult,
+    verbose: bool,
+) -> SearchResult<Option<RouteResult>> {
+    let options = &with_shared_budget(options);
+    let (departure_stop_id, arrival_stop_id) = route_stop_ids(route);
+    let timetable_start_at = NaiveDateTime::new(
+        timetable_start_date(data_storage.timetable_metadata()).unwrap(),
+        create_time(0, 0),
+    );
+    let lookbehind_limit_at = route
+        .departure_at()
+        .checked_sub_signed(Duration::hours(
+            MAXIMUM_EARLIER_DEPARTURE_LOOKBEHIND_IN_HOURS,
+        ))
+        .unwrap()
+        .max(timetable_start_at);
+
+    // Some algorithms only support some departures (e.g. the connection scan, its service day), the earlier ones are not searched.
+    let Some(earliest_departure_at) =
+        earliest_supported_departure_at(algorithm, lookbehind_limit_at, route.departure_at())
+    else {
+        return Ok(None);
+    };
+
+    let find_earlier_route = |departure_at| {
+        map_search_result(
+            find_route(
+                data_storage,
+                algorithm,
+                options,
+                departure_stop_id,
+                arrival_stop_id,
+                departure_at,
+                verbose,
+            ),
+            |candidate| candidate.filter(|candidate| candidate.arrival_at() < route.arrival_at()),
+        )
+    };
+
+    // Departing at the arrival time or later, the arrival cannot be earlier.
+    let mut upper_bound = route.arrival_at();
+    let mut lookbehind = Duration::hours(1);
+
+    // Looks further and further back until a route arrives earlier.
+    let (mut lower_bound, mut solution) = loop {
+        let departure_at = route
+            .departure_at()
+            .checked_sub_signed(lookbehind)
+            .unwrap()
+            .max(earliest_departure_at);
+
+        if let Some(solution) = find_earlier_route(departure_at)? {
+            break (departure_at.max(solution.departure_at()), solution);
+        }
+
+        if departure_at == earliest_departure_at {
+            return Ok(None);
+        }
+        upper_bound = departure_at;
+        lookbehind = (lookbehind * 2).min(Duration::hours(
+            MAXIMUM_EARLIER_DEPARTURE_LOOKBEHIND_IN_HOURS,
+        ));
+    };
+
+    // The routes departing from the lower bound arrive earlier, the ones departing from the upper bound do not.
+    while (upper_bound - lower_bound).num_minutes() > 1 {
+        let departure_at =
+            add_minutes_to_date_time(lower_bound, (upper_bound - lower_bound).num_minutes() / 2);
+
+        match find_earlier_route(departure_at) {
+            Ok(Some(candidate)) => {
+                lower_bound = departure_at.max(candidate.departure_at());
+                solution = candidate;
+            }
+            Ok(None) => upper_bound = departure_at,
+            // The solution found until then arrives earlier, but may not depart as late as possible.
+            Err(aborted) => return to_search_result(Some(solution), Some(aborted.reason())),
+        }
+    }
+
+    Ok(Some(solution))
+}
+
+/// The routes departing one after the other from the departure date and time, at most count of them.
+/// Each route departs strictly later than the previous one, so that the same trip chain is never returned twice.
+/// The searches share the deadline and the maximum number of explored routes of the options.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_next_routings(
+    data_storage: &DataStorage,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    departure_at: NaiveDateTime,
+    count: usize,
+    verbose: bool,
+) -> SearchResult<Vec<RouteResult>> {
+    let options = &with_shared_budget(options);
+    let mut routes = Vec::new();
+    let mut departure_at = departure_at;
+
+    while routes.len() < count {
+        let route = match find_route(
+            data_storage,
+            algorithm,
+            options,
+            departure_stop_id,
+            arrival_stop_id,
+            departure_at,
+            verbose,
+        ) {
+            Ok(Some(route)) => route,
+            Ok(None) => break,
+            // The routes found until then are returned.
+            Err(aborted) => return to_search_result(routes, Some(aborted.reason())),
+        };
+
+        departure_at = add_minutes_to_date_time(route.departure_at(), 1);
+        routes.push(route);
+    }
+
+    Ok(routes)
+}
+
+/// Returns the earliest departure between the two that the algorithm supports, None if it does not support the latest one.
+/// The departures supported are expected to follow each other (e.g. a service day).
+fn earliest_supported_departure_at(
+    algorithm: &dyn RoutingAlgorithm,
+    earliest_departure_at: NaiveDateTime,
+    latest_departure_at: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    if !algorithm.supports_departure_at(latest_departure_at) {
+        return None;
+    }
+
+    if algorithm.supports_departure_at(earliest_departure_at) {
+        return Some(earliest_departure_at);
+    }
+
+    // The earliest departure is not supported, the latest one is.
+    let mut lower_bound = earliest_departure_at;
+    let mut upper_bound = latest_departure_at;
+
+    while (upper_bound - lower_bound).num_minutes() > 1 {
+        let departure_at =
+            add_minutes_to_date_time(lower_bound, (upper_bound - lower_bound).num_minutes() / 2);
+
+        if algorithm.supports_departure_at(departure_at) {
+            upper_bound = departure_at;
+        } else {
+            lower_bound = departure_at;
+        }
+    }
+
+    Some(upper_bound)
+}
+
+/// Returns None without searching if the algorithm does not support the departure.
+fn find_route(
+    data_storage: &DataStorage,
+    algorithm: &dyn RoutingAlgorithm,
+    options: &RoutingOptions,
+    departure_stop_id: i32,
+    arrival_stop_id: i32,
+    departure_at: NaiveDateTime,
+    verbose: bool,
+) -> SearchResult<Option<RouteResult>> {
+    if !algorithm.supports_departure_at(departure_at) {
+        return Ok(None);
+    }
+
+    map_search_result(
+        algorithm.compute_routing(
+            data_storage,
+            options,
+            departure_stop_id,
+            departure_at,
+            verbose,
+            RoutingAlgorithmArgs::solve_from_departure_stop_to_arrival_stop(arrival_stop_id),
+        ),
+        |mut routes| routes.remove(&arrival_stop_id),
+    )
+}
+
+/// Returns the departure stop and the arrival stop of the route.
+fn route_stop_ids(route: &RouteResult) -> (i32, i32) {
+    let sections = route.sections();
+    (
+        sections.first().unwrap().departure_stop_id(),
+        sections.last().unwrap().arrival_stop_id(),
+    )
+}
@@ -0,0 +1,136 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+/// A binary heap giving the value having the smallest key first.
+/// The values having the same key are given in the order in which they were pushed.
+pub struct PriorityQueue<K: Ord, T> {
+    heap: BinaryHeap<PriorityQueueEntry<K, T>>,
+    push_count: usize,
+}
+
+impl<K: Ord, T> PriorityQueue<K, T> {
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            push_count: 0,
+        }
+    }
+
+    // Functions
+
+    pub fn push(&mut self, key: K, value: T) {
+        self.heap.push(PriorityQueueEntry {
+            key,
+            position: self.push_count,
+            value,
+        });
+        self.push_count += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|entry| entry.value)
+    }
+}
+
+impl<K: Ord, T> Default for PriorityQueue<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct PriorityQueueEntry<K: Ord, T> {
+    key: K,
+    position: usize,
+    value: T,
+}
+
+impl<K: Ord, T> PartialEq for PriorityQueueEntry<K, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Ord, T> Eq for PriorityQueueEntry<K, T> {}
+
+impl<K: Ord, T> PartialOrd for PriorityQueueEntry<K, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Ord, T> Ord for PriorityQueueEntry<K, T> {
+    // The ordering is reversed, as BinaryHeap gives the greatest entry first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&other.key, other.position).cmp(&(&self.key, self.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Reverse;
+
+    use super::PriorityQueue;
+
+    /// Checks that the values are given in the same order as with a vector sorted by key,
+    /// a value being inserted after the values having the same key (as in the exploration before the binary heap).
+    fn check_order<K: Ord + Copy>(keys: &[K]) {
+        let mut expected: Vec<(K, usize)> = Vec::new();
+        let mut queue = PriorityQueue::new();
+
+        for (i, &key) in keys.iter().enumerate() {
+            let index = expected
+                .iter()
+                .position(|&(k, _)| key < k)
+                .unwrap_or(expected.len());
+            expected.insert(index, (key, i));
+            queue.push(key, i);
+
+            // Some values are taken along the way, as during the exploration.
+            if i % 3 == 0 {
+                assert_eq!(queue.pop(), Some(expected.remove(0).1));
+            }
+        }
+
+        while !expected.is_empty() {
+            assert_eq!(queue.pop(), Some(expected.remove(0).1));
+        }
+
+        assert_eq!(queue.pop(), None);
+    }
+
+    /// Many keys are equal, as are the arrival times of the routes.
+    fn keys() -> Vec<u32> {
+        (0..500u32).map(|i| (i * 7919) % 37).collect()
+    }
+
+    #[test]
+    fn pops_smallest_key_first() {
+        check_order(&keys());
+    }
+
+    #[test]
+    fn pops_greatest_reversed_key_first() {
+        // The backward exploration gives the latest departure first.
+        check_order(&keys().into_iter().map(Reverse).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pops_equal_keys_in_push_order() {
+        let mut queue = PriorityQueue::new();
+        queue.push(2, "c");
+        queue.push(1, "a");
+        queue.push(2, "d");
+        queue.push(1, "b");
+        queue.push(2, "e");
+
+        assert_eq!(queue.pop(), Some("a"));
+        assert_eq!(queue.pop(), Some("b"));
+        queue.push(1, "f");
+        queue.push(2, "g");
+        assert_eq!(queue.pop(), Some("f"));
+        assert_eq!(queue.pop(), Some("c"));
+        assert_eq!(queue.pop(), Some("d"));
+        assert_eq!(queue.pop(), Some("e"));
+        assert_eq!(queue.pop(), Some("g"));
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -0,0 +1,880 @@
+use chrono::{NaiveDateTime, NaiveTime, Timelike};
+use hrdf_parser::{DataStorage, Model};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::utils::{add_1_day, add_minutes_to_date_time, create_time, sub_1_day};
+
+use super::{
+    algorithm::RoutingAlgorithm,
+    connections::get_exchange_time,
+    is_through_service,
+    models::{
+        AbortReason, ExplorationBudget, RouteResult, RouteSection, RoutingAlgorithmArgs,
+        RoutingAlgorithmMode, RoutingOptions, SearchResult,
+    },
+    route_impl::sections_to_route_result,
+    routing_index::RoutingIndex,
+    utils::{get_stop_connections, to_search_result},
+};
+
+const MINUTES_PER_DAY: i32 = 1440;
+
+/// Round-based Public Transit Routing (RAPTOR), each round corresponding to one more connection.
+/// The journeys serving the same sequence of stops are grouped into a route, the stops and routes are stored in dense arrays.
+pub struct Raptor {
+    index: RoutingIndex,
+    exchange_points: Vec<bool>,
+    // The stops where a journey can continue as another journey (through service).
+    through_service_points: Vec<bool>,
+    routes: Vec<RaptorRoute>,
+    // For each stop, the routes serving it and the position of the stop in each route.
+    routes_by_stop: Vec<Vec<(usize, usize)>>,
+    footpaths_by_stop: Vec<Vec<(usize, i16)>>,
+}
+
+/// The trips of a route can be boarded and left at the same stops and never overtake each other,
+/// so they are sorted by ascending departure time at every stop.
+struct RaptorRoute {
+    stops: Vec<usize>,
+    trips: Vec<RaptorTrip>,
+}
+
+struct RaptorTrip {
+    journey_id: i32,
+    bit_field_id: i32,
+    // In minutes since midnight of the day the journey departs from its first stop.
+    arrivals: Vec<Option<u16>>,
+    departures: Vec<Option<u16>>,
+}
+
+impl RaptorTrip {
+    /// Returns true if the trip arrives at and departs from each stop no later than the other trip.
+    fn precedes(&self, other: &RaptorTrip) -> bool {
+        self.arrivals
+            .iter()
+            .zip(&other.arrivals)
+            .all(|(a, b)| a <= b)
+            && self
+                .departures
+                .iter()
+                .zip(&other.departures)
+                .all(|(a, b)| a <= b)
+    }
+}
+
+// The stops of a trip and, at each of them, if it arrives and if it departs.
+type TripPattern = (Vec<usize>, Vec<(bool, bool)>);
+
+#[derive(Debug, Clone, Copy)]
+enum Label {
+    Origin {
+        arrival: i32,
+    },
+    Trip {
+        arrival: i32,
+        journey_id: i32,
+        boarding_stop: usize,
+        boarding_round: usize,
+        boarding_after_walking: bool,
+        journey_count: usize,
+    },
+    Walk {
+        arrival: i32,
+        departure_stop: usize,
+        duration: i16,
+        journey_count: usize,
+    },
+}
+
+impl Label {
+    fn arrival(&self) -> i32 {
+        match *self {
+            Label::Origin { arrival } => arrival,
+            Label::Trip { arrival, .. } => arrival,
+            Label::Walk { arrival, .. } => arrival,
+        }
+    }
+
+    fn journey_id(&self) -> Option<i32> {
+        match *self {
+            Label::Trip { journey_id, .. } => Some(journey_id),
+            _ => None,
+        }
+    }
+
+    fn journey_count(&self) -> usize {
+        match *self {
+            Label::Origin { .. } => 0,
+            Label::Trip { journey_count, .. } => journey_count,
+            Label::Walk { journey_count, .. } => journey_count,
+        }
+    }
+}
+
+/// The labels of each round, the arrival times are in minutes since midnight of the departure day.
+struct Labels {
+    by_trip: Vec<Vec<Option<Label>>>,
+    by_walk: Vec<Vec<Option<Label>>>,
+}
+
+struct TripBoarding {
+    trip: usize,
+    // In minutes, the journey may depart the day before or the day after the departure day.
+    offset: i32,
+    departure: i32,
+    stop: usize,
+    round: usize,
+    after_walking: bool,
+    journey_count: usize,
+}
+
+impl Raptor {
+    pub fn new(data_storage: &DataStorage) -> Self {
+        let index = RoutingIndex::new(data_storage);
+
+        let exchange_points = (0..index.stop_count() as u32)
+            .map(|stop| {
+                let stop_id = index.stop_id(stop);
+                data_storage
+                    .stops()
+                    .find(stop_id)
+                    .unwrap_or_else(|| panic!("Stop {:?} not found.", stop_id))
+                    .can_be_used_as_exchange_point()
+            })
+            .collect();
+
+        let through_service_points = (0..index.stop_count() as u32)
+            .map(|stop| index.through_services().has_stop(index.stop_id(stop)))
+            .collect();
+
+        // The trips serving the same stops, with the same stops where they can be boarded and left.
+        let mut trips_by_pattern: Vec<(Vec<usize>, Vec<RaptorTrip>)> = Vec::new();
+        let mut pattern_index_by_key: FxHashMap<TripPattern, usize> = FxHashMap::default();
+
+        for journey in data_storage.journeys().entries() {
+            // Sometimes certain stop identifiers don't exist for unknown reasons.
+            let Some(stops) = journey
+                .route()
+                .iter()
+                .map(|route_entry| find_stop(&index, route_entry.stop_id()))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+
+            if stops.len() < 2 {
+                continue;
+            }
+
+            let first_departure_time = journey.route()[0].departure_time().unwrap();
+            let minutes_of = |time: NaiveTime| {
+                let minutes = time.hour() * 60 + time.minute();
+                // The time is on the next day if it is earlier than the first departure time.
+                let minutes = if time < first_departure_time {
+                    minutes + MINUTES_PER_DAY as u32
+                } else {
+                    minutes
+                };
+                u16::try_from(minutes).unwrap()
+            };
+
+            let trip = RaptorTrip {
+                journey_id: journey.id(),
+                // If the journey has no bit_field_id, the default value is 0. A value of 0 means that the journey operates every day.
+                bit_field_id: journey.bit_field_id().unwrap_or(0),
+                arrivals: journey
+                    .route()
+                    .iter()
+                    .map(|route_entry| route_entry.arrival_time().map(minutes_of))
+                    .collect(),
+                departures: journey
+                    .route()
+                    .iter()
+                    .map(|route_entry| route_entry.departure_time().map(minutes_of))
+                    .collect(),
+            };
+
+            let key = (
+                stops,
+                trip.arrivals
+                    .iter()
+                    .zip(&trip.departures)
+                    .map(|(arrival, departure)| (arrival.is_some(), departure.is_some()))
+                    .collect(),
+            );
+            let pattern_index = *pattern_index_by_key.entry(key).or_insert_with_key(|key| {
+                trips_by_pattern.push((key.0.clone(), Vec::new()));
+                trips_by_pattern.len() - 1
+            });
+            trips_by_pattern[pattern_index].1.push(trip);
+        }
+
+        // A trip overtaking another one goes to another route.
+        let mut routes: Vec<RaptorRoute> = Vec::new();
+
+        for (stops, mut trips) in trips_by_pattern {
+            trips.sort_by_key(|trip| trip.departures[0]);
+            let first_route_index = routes.len();
+
+            for trip in trips {
+                match routes[first_route_index..]
+                    .iter_mut()
+                    .find(|route| route.trips.last().unwrap().precedes(&trip))
+                {
+                    Some(route) => route.trips.push(trip),
+                    None => routes.push(RaptorRoute {
+                        stops: stops.clone(),
+                        trips: vec![trip],
+                    }),
+                }
+            }
+        }
+
+        let mut routes_by_stop = vec![Vec::new(); index.stop_count()];
+
+        for (route_index, route) in routes.iter().enumerate() {
+            for (position, &stop) in route.stops.iter().enumerate() {
+                routes_by_stop[stop].push((route_index, position));
+            }
+        }
+
+        let footpaths_by_stop = (0..index.stop_count() as u32)
+            .map(|stop| {
+                get_stop_connections(data_storage, index.stop_id(stop))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|stop_connection| {
+                        find_stop(&index, stop_connection.stop_id_2())
+                            .map(|stop| (stop, stop_connection.duration()))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            index,
+            exchange_points,
+            through_service_points,
+            routes,
+            routes_by_stop,
+            footpaths_by_stop,
+        }
+    }
+
+    // Functions
+
+    fn stop_id(&self, stop: usize) -> i32 {
+        self.index.stop_id(stop as u32)
+    }
+
+    /// Each scanned route counts as an explored route, if the budget is exhausted the labels set until then are returned with the reason.
+    #[allow(clippy::too_many_arguments)]
+    fn search(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        origins: &[(usize, i32)],
+        midnight: NaiveDateTime,
+        target: Option<usize>,
+        time_limit: i32,
+        verbose: bool,
+        budget: &mut ExplorationBudget,
+    ) -> (Labels, Option<AbortReason>) {
+        let stop_count = self.index.stop_count();
+        let departure_date = midnight.date();
+
+        // The journeys departing the day before can still be running on the departure day.
+        let operating_bit_fields: Vec<(i32, &FxHashSet<i32>)> = [
+            (-MINUTES_PER_DAY, sub_1_day(departure_date)),
+            (0, departure_date),
+            (MINUTES_PER_DAY, add_1_day(departure_date)),
+        ]
+        .into_iter()
+        .filter_map(|(offset, date)| {
+            data_storage
+                .bit_fields_by_day()
+                .get(&date)
+                .map(|bit_fields| (offset, bit_fields))
+        })
+        .collect();
+
+        let mut labels = Labels {
+            by_trip: vec![vec![None; stop_count]],
+            by_walk: vec![vec![None; stop_count]],
+        };
+        let mut earliest_arrival = vec![i32::MAX; stop_count];
+
+        let mut marked_stops = Vec::new();
+
+        for &(origin, departure) in origins {
+            labels.by_trip[0][origin] = Some(Label::Origin { arrival: departure });
+            earliest_arrival[origin] = departure;
+            marked_stops.push(origin);
+        }
+
+        // Walking from the departure stops, which alone is not a valid solution, hence the earliest arrival is not updated.
+        for &(origin, departure) in origins {
+            for &(stop, duration) in &self.footpaths_by_stop[origin] {
+                if !options.accepts_stop(self.stop_id(stop))
+                    || !options.accepts_stop_connection(
+                        &self.index,
+                        self.stop_id(origin),
+                        self.stop_id(stop),
+                    )
+                {
+                    continue;
+                }
+
+                let arrival = departure + i32::from(duration);
+
+                if labels.by_walk[0][stop].is_some_and(|label| label.arrival() <= arrival) {
+                    continue;
+                }
+
+                labels.by_walk[0][stop] = Some(Label::Walk {
+                    arrival,
+                    departure_stop: origin,
+                    duration,
+                    journey_count: 0,
+                });
+                marked_stops.push(stop);
+            }
+        }
+
+        // A journey continuing as another journey (through service) is not counted, so there can be more rounds than journeys.
+        for round in 1.. {
+            // After the maximum number of journeys, only the through services can be boarded.
+            marked_stops.retain(|&stop| {
+                [
+                    labels.by_trip[round - 1][stop],
+                    labels.by_walk[round - 1][stop],
+                ]
+                .into_iter()
+                .flatten()
+                .any(|label| {
+                    label.journey_count() < options.maximum_number_of_journeys()
+                        || (label.journey_id().is_some() && self.through_service_points[stop])
+                })
+            });
+
+            if marked_stops.is_empty() {
+                break;
+            }
+
+            if verbose {
+                log::info!("{}", marked_stops.len());
+            }
+
+            labels.by_trip.push(vec![None; stop_count]);
+            labels.by_walk.push(vec![None; stop_count]);
+
+            // The routes serving the marked stops are scanned from the earliest marked stop.
+            let mut route_queue: FxHashMap<usize, usize> = FxHashMap::default();
+
+            for &stop in &marked_stops {
+                for &(route_index, position) in &self.routes_by_stop[stop] {
+                    route_queue
+                        .entry(route_index)
+                        .and_modify(|start| *start = (*start).min(position))
+                        .or_insert(position);
+                }
+            }
+
+            marked_stops.clear();
+
+            for (route_index, start) in route_queue {
+                if let Err(reason) = budget.explore() {
+                    return (labels, Some(reason));
+                }
+
+                let route = &self.routes[route_index];
+                let mut boarding: Option<TripBoarding> = None;
+
+                for position in start..route.stops.len() {
+                    let stop = route.stops[position];
+
+                    if let Some(current) = &boarding {
+                        let trip = &route.trips[current.trip];
+
+                        // The journey must be left before a segment without the required attributes.
+                        if !options.accepts_journey_segment(
+                            &self.index,
+                            trip.journey_id,
+                            position - 1,
+                        ) {
+                            boarding = None;
+                        } else if let Some(arrival) = trip.arrivals[position] {
+                            let arrival = i32::from(arrival) + current.offset;
+                            let bound = target.map_or(time_limit, |t| earliest_arrival[t]);
+
+                            if arrival < earliest_arrival[stop] && arrival <= bound {
+                                labels.by_trip[round][stop] = Some(Label::Trip {
+                                    arrival,
+                                    journey_id: trip.journey_id,
+                                    boarding_stop: current.stop,
+                                    boarding_round: current.round,
+                                    boarding_after_walking: current.after_walking,
+                                    journey_count: current.journey_count,
+                                });
+                                earliest_arrival[stop] = arrival;
+                                marked_stops.push(stop);
+                            }
+                        }
+                    }
+
+                    let Some(candidate) = self.earliest_boarding(
+                        data_storage,
+                        options,
+                        &labels,
+                        &operating_bit_fields,
+                        midnight,
+                        route_index,
+                        position,
+                        round - 1,
+                    ) else {
+                        continue;
+                    };
+
+                    if boarding.as_ref().is_none_or(|current| {
+                        route.trips[current.trip].departures[position].is_none_or(|departure| {
+                            candidate.departure < i32::from(departure) + current.offset
+                        })
+                    }) {
+                        boarding = Some(candidate);
+                    }
+                }
+            }
+
+            // Walking is only possible after a journey and from a stop that can be used for exchange.
+            let walking_stops: Vec<usize> = marked_stops
+                .iter()
+                .copied()
+                .filter(|&stop| {
+                    self.exchange_points[stop] && options.accepts_stop(self.stop_id(stop))
+                })
+                .collect();
+
+            for stop in walking_stops {
+                let label = labels.by_trip[round][stop].unwrap();
+                let arrival = label.arrival();
+
+                for &(next_stop, duration) in &self.footpaths_by_stop[stop] {
+                    if !options.accepts_stop(self.stop_id(next_stop))
+                        || !options.accepts_stop_connection(
+                            &self.index,
+                            self.stop_id(stop),
+                            self.stop_id(next_stop),
+                        )
+                    {
+                        continue;
+                    }
+
+                    let next_arrival = arrival + i32::from(duration);
+                    let bound = target.map_or(time_limit, |t| earliest_arrival[t]);
+
+                    if next_arrival < earliest_arrival[next_stop] && next_arrival <= bound {
+                        labels.by_walk[round][next_stop] = Some(Label::Walk {
+                            arrival: next_arrival,
+                            departure_stop: stop,
+                            duration,
+                            journey_count: label.journey_count(),
+                        });
+                        earliest_arrival[next_stop] = next_arrival;
+                        marked_stops.push(next_stop);
+                    }
+                }
+            }
+        }
+
+        (labels, None)
+    }
+
+    /// Returns the trip of the route that departs the earliest from the stop at the given position, given the labels of the previous rounds.
+    #[allow(clippy::too_many_arguments)]
+    fn earliest_boarding(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        labels: &Labels,
+        operating_bit_fields: &[(i32, &FxHashSet<i32>)],
+        midnight: NaiveDateTime,
+        route_index: usize,
+        position: usize,
+        max_round: usize,
+    ) -> Option<TripBoarding> {
+        let route = &self.routes[route_index];
+        let stop = route.stops[position];
+
+        // The trips of the route can all be boarded at the stop, or none of them.
+        route.trips[0].departures[position]?;
+
+        // The journeys cannot be boarded at an avoided stop.
+        if !options.accepts_stop(self.stop_id(stop)) {
+            return None;
+        }
+
+        let mut result: Option<TripBoarding> = None;
+        let minimum_transfer_slack =
+            i32::try_from(options.minimum_transfer_slack().num_minutes()).unwrap();
+
+        // The most recent labels are the earliest ones, the older ones are only used if the recent ones have reached the maximum number of journeys.
+        for round in (0..=max_round).rev() {
+            let mut can_transfer = false;
+
+            for (label, after_walking) in [
+                (labels.by_trip[round][stop], false),
+                (labels.by_walk[round][stop], true),
+            ] {
+                let Some(label) = label else {
+                    continue;
+                };
+
+                // After the maximum number of journeys, only a journey continuing as another journey (through service) can be boarded.
+                let only_through_services =
+                    label.journey_count() >= options.maximum_number_of_journeys();
+                can_transfer |= !only_through_services;
+
+                if only_through_services && label.journey_id().is_none() {
+                    continue;
+                }
+
+                if !matches!(label, Label::Origin { .. }) && !self.exchange_points[stop] {
+                    continue;
+                }
+
+                // After walking from a journey, the minimum transfer slack must elapse and the accessibility profile may require more time.
+                let ready_at = match label {
+                    Label::Walk { journey_count, .. } if journey_count > 0 => {
+                        label.arrival()
+                            + minimum_transfer_slack
+                            + i32::from(options.additional_exchange_time())
+                    }
+                    _ => label.arrival(),
+                };
+
+                for &(offset, bit_fields) in operating_bit_fields {
+                    let start = route.trips.partition_point(|trip| {
+                        trip.departures[position]
+                            .is_none_or(|departure| i32::from(departure) + offset < ready_at)
+                    });
+
+                    for (trip_index, trip) in route.trips.iter().enumerate().skip(start) {
+                        let Some(departure) = trip.departures[position] else {
+                            continue;
+                        };
+                        let departure = i32::from(departure) + offset;
+                        let departure_at = add_minutes_to_date_time(midnight, departure.into());
+
+                        if result.as_ref().is_some_and(|r| departure >= r.departure) {
+                            break;
+                        }
+
+                        if !bit_fields.contains(&trip.bit_field_id) {
+                            continue;
+                        }
+
+                        let is_through_service = label.journey_id().is_some_and(|journey_id| {
+                            is_through_service(
+                                data_storage,
+                                &self.index,
+                                self.stop_id(stop),
+                                journey_id,
+                                trip.journey_id,
+                                departure_at,
+                            )
+                        });
+
+                        if only_through_services && !is_through_service {
+                            continue;
+                        }
+
+                        if options.has_journey_filters() {
+                            let journey = data_storage
+                                .journeys()
+                                .find(trip.journey_id)
+                                .unwrap_or_else(|| {
+                                    panic!("Journey {:?} not found.", trip.journey_id)
+                                });
+
+                            if !options.accepts_journey(
+                                data_storage,
+                                &self.index,
+                                journey,
+                                self.stop_id(stop),
+                            ) {
+                                continue;
+                            }
+                        }
+
+                        // It is checked that there is enough time to embark on the journey (exchange time), leaving the minimum transfer slack.
+                        if let Some(journey_id) = label.journey_id() {
+                            let exchange_time = get_exchange_time(
+                                data_storage,
+                                options,
+                                &self.index,
+                                self.stop_id(stop),
+                                journey_id,
+                                trip.journey_id,
+                                departure_at,
+                            );
+
+                            let slack = if is_through_service {
+                                0
+                            } else {
+                                minimum_transfer_slack
+                            };
+
+                            if label.arrival() + i32::from(exchange_time) + slack > departure {
+                                continue;
+                            }
+                        }
+
+                        result = Some(TripBoarding {
+                            trip: trip_index,
+                            offset,
+                            departure,
+                            stop,
+                            round,
+                            after_walking,
+                            journey_count: label.journey_count() + usize::from(!is_through_service),
+                        });
+                        break;
+                    }
+                }
+            }
+
+            if can_transfer {
+                break;
+            }
+        }
+
+        result
+    }
+
+    /// Returns the round and the kind (false for a journey, true for walking) of the best label of the stop, ignoring the walks from the departure stop.
+    fn best_label(&self, labels: &Labels, stop: usize) -> Option<(usize, bool, i32)> {
+        let mut result: Option<(usize, bool, i32)> = None;
+
+        for round in 1..labels.by_trip.len() {
+            for (label, is_walk) in [
+                (labels.by_trip[round][stop], false),
+                (labels.by_walk[round][stop], true),
+            ] {
+                if let Some(label) = label {
+                    if result.is_none_or(|(_, _, arrival)| label.arrival() < arrival) {
+                        result = Some((round, is_walk, label.arrival()));
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn reconstruct_route(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        labels: &Labels,
+        midnight: NaiveDateTime,
+        mut round: usize,
+        mut stop: usize,
+        mut is_walk: bool,
+    ) -> RouteResult {
+        let mut sections = Vec::new();
+
+        loop {
+            let label = if is_walk {
+                labels.by_walk[round][stop]
+            } else {
+                labels.by_trip[round][stop]
+            }
+            .unwrap();
+
+            match label {
+                Label::Origin { .. } => break,
+                Label::Trip {
+                    arrival,
+                    journey_id,
+                    boarding_stop,
+                    boarding_round,
+                    boarding_after_walking,
+                    ..
+                } => {
+                    sections.push(RouteSection::new(
+                        Some(journey_id),
+                        Some(self.index.journey_index(journey_id)),
+                        self.stop_id(boarding_stop),
+                        boarding_stop as u32,
+                        self.stop_id(stop),
+                        stop as u32,
+                        add_minutes_to_date_time(midnight, arrival.into()),
+                        None,
+                    ));
+                    stop = boarding_stop;
+                    round = boarding_round;
+                    is_walk = boarding_after_walking;
+                }
+                Label::Walk {
+                    arrival,
+                    departure_stop,
+                    duration,
+                    ..
+                } => {
+                    sections.push(RouteSection::new(
+                        None,
+                        None,
+                        self.stop_id(departure_stop),
+                        departure_stop as u32,
+                        self.stop_id(stop),
+                        stop as u32,
+                        add_minutes_to_date_time(midnight, arrival.into()),
+                        Some(duration),
+                    ));
+                    stop = departure_stop;
+                    is_walk = false;
+                }
+            }
+        }
+
+        sections.reverse();
+        // The sections are built with the static timetable.
+        sections_to_route_result(data_storage, options, &self.index, &sections)
+    }
+}
+
+impl RoutingAlgorithm for Raptor {
+    fn supports_options(&self, options: &RoutingOptions) -> bool {
+        !options.has_level_exploration_options()
+    }
+
+    fn compute_routing_from_departure_stops(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        departure_stops: &[(i32, NaiveDateTime)],
+        verbose: bool,
+        args: RoutingAlgorithmArgs,
+    ) -> SearchResult<FxHashMap<i32, RouteResult>> {
+        let mut result = FxHashMap::default();
+
+        if !self.supports_options(options) {
+            return to_search_result(result, Some(AbortReason::UnsupportedOptions));
+        }
+
+        let Some(first_departure_at) = departure_stops.iter().map(|&(_, at)| at).min() else {
+            return Ok(result);
+        };
+
+        // The arrival times are in minutes since midnight of the earliest departure day.
+        let midnight = NaiveDateTime::new(first_departure_at.date(), create_time(0, 0));
+
+        let origins: Vec<(usize, i32)> = departure_stops
+            .iter()
+            .filter_map(|(stop_id, departure_at)| {
+                let origin = find_stop(&self.index, *stop_id)?;
+                let departure = to_minutes(departure_at.time())
+                    + i32::try_from((departure_at.date() - midnight.date()).num_days()).unwrap()
+                        * MINUTES_PER_DAY;
+                Some((origin, departure))
+            })
+            .collect();
+
+        if origins.is_empty() {
+            return Ok(result);
+        }
+
+        let mut budget = ExplorationBudget::new(options);
+
+        let abort_reason = match args.mode() {
+            RoutingAlgorithmMode::SolveFromDepartureStopToArrivalStop => {
+                let Some(target) = find_stop(&self.index, args.arrival_stop_id()) else {
+                    return Ok(result);
+                };
+
+                if origins.iter().any(|&(origin, _)| origin == target) {
+                    return Ok(result);
+                }
+
+                let (labels, abort_reason) = self.search(
+                    data_storage,
+                    options,
+                    &origins,
+                    midnight,
+                    Some(target),
+                    i32::MAX,
+                    verbose,
+                    &mut budget,
+                );
+
+                if let Some((round, is_walk, _)) = self.best_label(&labels, target) {
+                    result.insert(
+                        args.arrival_stop_id(),
+                        self.reconstruct_route(
+                            data_storage,
+                            options,
+                            &labels,
+                            midnight,
+                            round,
+                            target,
+                            is_walk,
+                        ),
+                    );
+                }
+
+                abort_reason
+            }
+            RoutingAlgorithmMode::SolveFromDepartureStopToReachableArrivalStops => {
+                let time_limit = to_minutes(args.time_limit().time())
+                    + i32::try_from((args.time_limit().date() - midnight.date()).num_days())
+                        .unwrap()
+                        * MINUTES_PER_DAY;
+
+                let (labels, abort_reason) = self.search(
+                    data_storage,
+                    options,
+                    &origins,
+                    midnight,
+                    None,
+                    time_limit,
+                    verbose,
+                    &mut budget,
+                );
+
+                // The journeys can pass through the avoided stops, but they are not reachable.
+                for stop in (0..self.index.stop_count()).filter(|&stop| {
+                    !origins.iter().any(|&(origin, _)| origin == stop)
+                        && options.accepts_stop(self.stop_id(stop))
+                }) {
+                    if let Some((round, is_walk, _)) = self.best_label(&labels, stop) {
+                        result.insert(
+                            self.stop_id(stop),
+                            self.reconstruct_route(
+                                data_storage,
+                                options,
+                                &labels,
+                                midnight,
+                                round,
+                                stop,
+                                is_walk,
+                            ),
+                        );
+                    }
+                }
+
+                abort_reason
+            }
+        };
+
+        to_search_result(result, abort_reason)
+    }
+}
+
+fn to_minutes(time: NaiveTime) -> i32 {
+    i32::try_from(time.hour() * 60 + time.minute()).unwrap()
+}
+
+fn find_stop(index: &RoutingIndex, stop_id: i32) -> Option<usize> {
+    index.find_stop_index(stop_id).map(|stop| stop as usize)
+}
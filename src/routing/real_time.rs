@@ -0,0 +1,290 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use hrdf_parser::{DataStorage, Journey, Model};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
+
+use crate::utils::{add_1_day, add_minutes_to_date_time, sub_1_day};
+
+use super::models::RoutingOptions;
+
+/// The delays and cancellations of the journeys, applied on top of the static timetable.
+/// An update applies to the run of the journey departing from its first stop on the operating date, the delays are expected to be less than a day.
+#[derive(Debug, Default)]
+pub struct RealTimeUpdates {
+    cancelled_journeys: FxHashSet<(i32, NaiveDate)>,
+    cancelled_stops: FxHashSet<(i32, NaiveDate, i32)>,
+    // The arrival and departure delays in minutes, indexed by journey id, operating date and stop id.
+    delays: FxHashMap<(i32, NaiveDate, i32), (i16, i16)>,
+}
+
+impl RealTimeUpdates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the updates in the JSON format below, the delays are in minutes.
+    /// The delay of a stop is propagated to the following stops of the journey that have no update.
+    ///
+    /// {"journeys": [{"journey_id": 1, "operating_date": "2024-06-10", "is_cancelled": false, "stops": [{"stop_id": 8500002, "arrival_delay": 3, "departure_delay": 4, "is_cancelled": false}]}]}
+    pub fn from_json<R: Read>(data_storage: &DataStorage, reader: R) -> serde_json::Result<Self> {
+        let message: FeedMessage = serde_json::from_reader(reader)?;
+        let mut updates = Self::new();
+
+        for journey_update in message.journeys {
+            updates.add_journey_update(data_storage, journey_update);
+        }
+
+        Ok(updates)
+    }
+
+    // Functions
+
+    /// Returns true if no journey is delayed or cancelled.
+    pub fn is_empty(&self) -> bool {
+        self.cancelled_journeys.is_empty()
+            && self.cancelled_stops.is_empty()
+            && self.delays.is_empty()
+    }
+
+    pub fn is_journey_cancelled(&self, journey_id: i32, operating_date: NaiveDate) -> bool {
+        self.cancelled_journeys
+            .contains(&(journey_id, operating_date))
+    }
+
+    /// Returns true if the journey does not stop at the stop, it can neither be boarded nor left there.
+    pub fn is_stop_cancelled(
+        &self,
+        journey_id: i32,
+        operating_date: NaiveDate,
+        stop_id: i32,
+    ) -> bool {
+        self.is_journey_cancelled(journey_id, operating_date)
+            || self
+                .cancelled_stops
+                .contains(&(journey_id, operating_date, stop_id))
+    }
+
+    pub fn arrival_delay(&self, journey_id: i32, operating_date: NaiveDate, stop_id: i32) -> i16 {
+        self.delays
+            .get(&(journey_id, operating_date, stop_id))
+            .map_or(0, |&(arrival_delay, _)| arrival_delay)
+    }
+
+    pub fn departure_delay(&self, journey_id: i32, operating_date: NaiveDate, stop_id: i32) -> i16 {
+        self.delays
+            .get(&(journey_id, operating_date, stop_id))
+            .map_or(0, |&(_, departure_delay)| departure_delay)
+    }
+
+    fn add_journey_update(&mut self, data_storage: &DataStorage, journey_update: JourneyUpdate) {
+        let journey_id = journey_update.journey_id;
+        let operating_date = journey_update.operating_date;
+        let Some(journey) = data_storage.journeys().find(journey_id) else {
+            log::warn!("Journey: {} not found.", journey_id);
+            return;
+        };
+
+        if journey_update.is_cancelled {
+            self.cancelled_journeys.insert((journey_id, operating_date));
+            return;
+        }
+
+        let mut delay = 0;
+
+        for route_entry in journey.route() {
+            let stop_id = route_entry.stop_id();
+
+            if let Some(stop_update) = journey_update
+                .stops
+                .iter()
+                .find(|stop_update| stop_update.stop_id == stop_id)
+            {
+                if stop_update.is_cancelled {
+                    self.cancelled_stops
+                        .insert((journey_id, operating_date, stop_id));
+                }
+
+                let arrival_delay = stop_update.arrival_delay.unwrap_or(delay);
+                delay = stop_update.departure_delay.unwrap_or(arrival_delay);
+                self.delays.insert(
+                    (journey_id, operating_date, stop_id),
+                    (arrival_delay, delay),
+                );
+            } else if delay != 0 {
+                self.delays
+                    .insert((journey_id, operating_date, stop_id), (delay, delay));
+            }
+        }
+    }
+}
+
+/// Holds the latest real-time updates, which can be replaced at any time without reloading the timetable.
+/// A search uses the updates it was given until it is done, even if they are replaced in the meantime.
+#[derive(Debug, Default)]
+pub struct RealTimeFeed {
+    updates: RwLock<Arc<RealTimeUpdates>>,
+}
+
+impl RealTimeFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Getters/Setters
+
+    pub fn updates(&self) -> Arc<RealTimeUpdates> {
+        Arc::clone(&self.updates.read().unwrap())
+    }
+
+    pub fn set_updates(&self, value: RealTimeUpdates) {
+        *self.updates.write().unwrap() = Arc::new(value);
+    }
+
+    // Functions
+
+    /// Replaces the updates with the ones of the JSON file, see RealTimeUpdates::from_json for the format.
+    pub fn load_json_file<P: AsRef<Path>>(
+        &self,
+        data_storage: &DataStorage,
+        path: P,
+    ) -> Result<(), Box<dyn Error>> {
+        let file = File::open(path)?;
+        self.set_updates(RealTimeUpdates::from_json(
+            data_storage,
+            BufReader::new(file),
+        )?);
+        Ok(())
+    }
+}
+
+/// Returns the departure from the stop of the journey departing from its first stop on the operating date, delayed according to the real-time updates.
+pub fn delayed_departure_at(
+    options: &RoutingOptions,
+    journey: &Journey,
+    stop_id: i32,
+    operating_date: NaiveDate,
+) -> NaiveDateTime {
+    add_minutes_to_date_time(
+        journey.departure_at_of(stop_id, operating_date),
+        options.departure_delay(journey.id(), operating_date, stop_id),
+    )
+}
+
+/// Returns the arrival at the stop of the journey departing from its first stop on the operating date, delayed according to the real-time updates.
+pub fn delayed_arrival_at(
+    options: &RoutingOptions,
+    journey: &Journey,
+    stop_id: i32,
+    operating_date: NaiveDate,
+) -> NaiveDateTime {
+    add_minutes_to_date_time(
+        journey.arrival_at_of_with_origin(stop_id, operating_date, true, journey.first_stop_id()),
+        options.arrival_delay(journey.id(), operating_date, stop_id),
+    )
+}
+
+/// Returns the arrivals at the stops following the departure stop, in the order of the route, as delayed_arrival_at would.
+/// Computed in a single pass over the route.
+pub fn delayed_arrivals_after(
+    options: &RoutingOptions,
+    journey: &Journey,
+    departure_stop_id: i32,
+    operating_date: NaiveDate,
+) -> Vec<(i32, NaiveDateTime)> {
+    let route = journey.route();
+    let first_departure_time = route.first().unwrap().departure_time().unwrap();
+
+    route
+        .iter()
+        .skip_while(|route_entry| route_entry.stop_id() != departure_stop_id)
+        .skip(1)
+        .map(|route_entry| {
+            let arrival_time = route_entry.arrival_time().unwrap();
+            // The arrival is on the next day if it is earlier than the departure from the first stop.
+            let date = if arrival_time < first_departure_time {
+                add_1_day(operating_date)
+            } else {
+                operating_date
+            };
+            let arrival_at = add_minutes_to_date_time(
+                NaiveDateTime::new(date, arrival_time),
+                options.arrival_delay(journey.id(), operating_date, route_entry.stop_id()),
+            );
+            (route_entry.stop_id(), arrival_at)
+        })
+        .collect()
+}
+
+/// Counterpart of delayed_departure_at, returns the operating date of the journey departing from the stop at the given time.
+pub fn operating_date_of_departure(
+    options: &RoutingOptions,
+    journey: &Journey,
+    stop_id: i32,
+    departure_at: NaiveDateTime,
+) -> NaiveDate {
+    operating_date_of(departure_at, |operating_date| {
+        delayed_departure_at(options, journey, stop_id, operating_date)
+    })
+}
+
+/// Counterpart of delayed_arrival_at, returns the operating date of the journey arriving at the stop at the given time.
+pub fn operating_date_of_arrival(
+    options: &RoutingOptions,
+    journey: &Journey,
+    stop_id: i32,
+    arrival_at: NaiveDateTime,
+) -> NaiveDate {
+    operating_date_of(arrival_at, |operating_date| {
+        delayed_arrival_at(options, journey, stop_id, operating_date)
+    })
+}
+
+/// The journey departs from its first stop on the day of the date and time or up to two days before, or on the next day if it is early.
+fn operating_date_of<F: Fn(NaiveDate) -> NaiveDateTime>(
+    date_time: NaiveDateTime,
+    delayed_at: F,
+) -> NaiveDate {
+    let date = date_time.date();
+
+    [
+        date,
+        sub_1_day(date),
+        add_1_day(date),
+        sub_1_day(sub_1_day(date)),
+    ]
+    .into_iter()
+    .find(|&operating_date| delayed_at(operating_date) == date_time)
+    .unwrap_or_else(|| panic!("Operating date of {:?} not found.", date_time))
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedMessage {
+    journeys: Vec<JourneyUpdate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JourneyUpdate {
+    journey_id: i32,
+    operating_date: NaiveDate,
+    #[serde(default)]
+    is_cancelled: bool,
+    #[serde(default)]
+    stops: Vec<StopUpdate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopUpdate {
+    stop_id: i32,
+    arrival_delay: Option<i16>,
+    departure_delay: Option<i16>,
+    #[serde(default)]
+    is_cancelled: bool,
+}
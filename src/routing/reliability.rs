@@ -0,0 +1,181 @@
+use std::cmp::Ordering;
+
+use chrono::NaiveDateTime;
+use hrdf_parser::DataStorage;
+use rustc_hash::FxHashMap;
+
+use crate::utils::add_minutes_to_date_time;
+
+use super::{
+    connections::get_exchange_time,
+    cost::{is_improving_by_connections, JourneyCost, PartialRoute},
+    models::{RouteResult, RoutingOptions},
+    routing_index::RoutingIndex,
+};
+
+/// The time left when changing from a journey to the next one, in minutes.
+/// Changing at the same stop (no walking duration), the exchange time must have elapsed, otherwise the walk must be over.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_slack(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    previous_journey_id: i32,
+    arrival_at: NaiveDateTime,
+    walking_duration: Option<i16>,
+    stop_id: i32,
+    journey_id: i32,
+    departure_at: NaiveDateTime,
+) -> i16 {
+    // After walking, the accessibility profile may require more time, as get_exchange_time adds it otherwise.
+    let duration = walking_duration
+        .map(|duration| duration + options.additional_exchange_time())
+        .unwrap_or_else(|| {
+            get_exchange_time(
+                data_storage,
+                options,
+                index,
+                stop_id,
+                previous_journey_id,
+                journey_id,
+                departure_at,
+            )
+        });
+    let ready_at = add_minutes_to_date_time(arrival_at, duration.into());
+    i16::try_from((departure_at - ready_at).num_minutes()).unwrap_or(i16::MAX)
+}
+
+/// The arrival time in minutes increased by the risk of missing the changes, the lowest cost wins.
+/// The risk of a change goes from 1.0 without slack down to 0.0 once the slack reaches the tolerance of the transport type arrived with,
+/// a delay being more likely with some transport types (e.g. "B") than with others.
+/// Each unit of risk costs the penalty in minutes. The ties are broken as by EarliestArrivalCost.
+#[derive(Debug, Clone)]
+pub struct ReliabilityCost {
+    risk_penalty: f64,
+    default_tolerance: i16,
+    transport_type_tolerances: FxHashMap<String, i16>,
+}
+
+impl ReliabilityCost {
+    pub fn new(risk_penalty: f64, default_tolerance: i16) -> Self {
+        Self {
+            risk_penalty,
+            default_tolerance,
+            transport_type_tolerances: FxHashMap::default(),
+        }
+    }
+
+    // Getters/Setters
+
+    /// The cost of a missed change, in minutes.
+    pub fn risk_penalty(&self) -> f64 {
+        self.risk_penalty
+    }
+
+    pub fn set_risk_penalty(&mut self, value: f64) {
+        self.risk_penalty = value;
+    }
+
+    /// The slack from which a change is considered safe, in minutes.
+    pub fn default_tolerance(&self) -> i16 {
+        self.default_tolerance
+    }
+
+    pub fn set_default_tolerance(&mut self, value: i16) {
+        self.default_tolerance = value;
+    }
+
+    /// The tolerance when arriving with a transport type (e.g. "B"), the default one if it has none.
+    pub fn transport_type_tolerance(&self, designation: &str) -> i16 {
+        self.transport_type_tolerances
+            .get(designation)
+            .copied()
+            .unwrap_or(self.default_tolerance)
+    }
+
+    pub fn set_transport_type_tolerance(&mut self, designation: &str, value: i16) {
+        self.transport_type_tolerances
+            .insert(designation.to_string(), value);
+    }
+
+    // Functions
+
+    /// The risk of missing a change, designation being the one of the transport type arrived with.
+    pub fn transfer_risk(&self, transfer_slack: i16, designation: &str) -> f64 {
+        let tolerance = self.transport_type_tolerance(designation).max(1);
+        (1.0 - f64::from(transfer_slack) / f64::from(tolerance)).clamp(0.0, 1.0)
+    }
+
+    /// The sum of the risks of the changes of the route.
+    pub fn risk_score(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        route: &RouteResult,
+    ) -> f64 {
+        let mut designation = "";
+        let mut risk_score = 0.0;
+
+        for section in route.sections() {
+            let Some(journey) = section.journey(data_storage, options) else {
+                continue;
+            };
+
+            if let Some(transfer_slack) = section.transfer_slack() {
+                risk_score += self.transfer_risk(transfer_slack, designation);
+            }
+            designation = journey.transport_type(data_storage).designation();
+        }
+
+        risk_score
+    }
+
+    fn partial_risk_score(&self, route: &PartialRoute) -> f64 {
+        let data_storage = route.data_storage();
+
+        route
+            .transfer_slacks()
+            .into_iter()
+            .map(|(journey, transfer_slack)| {
+                let designation = journey.transport_type(data_storage).designation();
+                self.transfer_risk(transfer_slack, designation)
+            })
+            .sum()
+    }
+
+    /// Compares the costs of the two routes.
+    fn compare(&self, route_1: &PartialRoute, route_2: &PartialRoute) -> Ordering {
+        let difference = (route_1.arrival_at() - route_2.arrival_at()).num_minutes() as f64
+            + self.risk_penalty
+                * (self.partial_risk_score(route_1) - self.partial_risk_score(route_2));
+        difference.total_cmp(&0.0)
+    }
+}
+
+impl JourneyCost for ReliabilityCost {
+    fn is_improving_solution(&self, candidate: &PartialRoute, solution: &PartialRoute) -> bool {
+        match self.compare(candidate, solution) {
+            Ordering::Equal => is_improving_by_connections(candidate, solution).unwrap_or(false),
+            ordering => ordering == Ordering::Less,
+        }
+    }
+
+    // The risk of the changes already made does not change as the route is extended.
+    fn can_improve_solution(&self, route: &PartialRoute, solution: &PartialRoute) -> bool {
+        self.compare(route, solution) != Ordering::Greater
+    }
+
+    // A later departure leaves more time for the change.
+    fn may_prefer_later_departures(&self) -> bool {
+        self.risk_penalty > 0.0
+    }
+
+    // A later arrival may come after safer changes.
+    fn may_prefer_later_arrivals(&self) -> bool {
+        self.risk_penalty > 0.0
+    }
+
+    fn uses_transfer_slacks(&self) -> bool {
+        true
+    }
+}
@@ -1,155 +1,455 @@
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use hrdf_parser::{DataStorage, Journey, Model};
-use rustc_hash::FxHashSet;
 
 use super::{
-    models::{Route, RouteResult, RouteSection, RouteSectionResult},
-    utils::clone_update_route,
+    models::{Route, RouteArena, RouteResult, RouteSection, RouteSectionResult, RoutingOptions},
+    real_time::{delayed_arrival_at, delayed_departure_at, operating_date_of_arrival},
+    reliability::transfer_slack,
+    routing_index::RoutingIndex,
+    utils::find_stop,
 };
 
 impl Route {
+    /// The operating date is the one of the run of the journey.
+    #[allow(clippy::too_many_arguments)]
     pub fn extend(
         &self,
+        arena: &mut RouteArena,
         data_storage: &DataStorage,
-        journey_id: i32,
-        date: NaiveDate,
-        is_departure_date: bool,
+        options: &RoutingOptions,
+        index: &RoutingIndex,
+        journey: &Journey,
+        journey_index: u32,
+        operating_date: NaiveDate,
     ) -> Option<Route> {
-        let journey = data_storage
-            .journeys()
-            .find(journey_id)
-            .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id));
-
         if journey.is_last_stop(self.arrival_stop_id(), false) {
             return None;
         }
 
         let is_same_journey = self
             .last_section()
-            .journey_id()
-            .map_or(false, |id| id == journey_id);
+            .journey_index()
+            .is_some_and(|index| index == journey_index);
 
-        RouteSection::find_next(
+        let visited_stops_start = arena.visited_stops().len();
+        let new_section = RouteSection::find_next(
             data_storage,
+            options,
+            index,
             journey,
+            journey_index,
             self.arrival_stop_id(),
-            date,
-            is_departure_date,
-        )
-        .and_then(|(new_section, new_visited_stops)| {
-            if self.has_visited_any_stops(&new_visited_stops)
-                && new_section.arrival_stop_id() != journey.first_stop_id()
-            {
-                return None;
+            operating_date,
+            arena.visited_stops_mut(),
+        )?;
+
+        if self.has_visited_any_stops(arena, visited_stops_start)
+            && new_section.arrival_stop_id() != journey.first_stop_id()
+        {
+            arena.visited_stops_mut().truncate(visited_stops_start);
+            return None;
+        }
+
+        let new_route = if is_same_journey {
+            let mut last_section = self.last_section().clone();
+            last_section.set_arrival_stop_id(new_section.arrival_stop_id());
+            last_section.set_arrival_stop_index(new_section.arrival_stop_index());
+            last_section.set_arrival_at(new_section.arrival_at());
+            // The stop where the sections meet is counted once.
+            last_section.set_stop_count(last_section.stop_count() + new_section.stop_count() - 1);
+            self.with_last_section(arena, last_section, visited_stops_start)
+        } else {
+            let mut new_section = new_section;
+            let journey_departure_at =
+                delayed_departure_at(options, journey, self.arrival_stop_id(), operating_date);
+            new_section.set_journey_departure_at(Some(journey_departure_at));
+            if options.journey_cost().uses_transfer_slacks() {
+                new_section.set_transfer_slack(self.transfer_slack_to(
+                    arena,
+                    data_storage,
+                    options,
+                    index,
+                    &new_section,
+                ));
             }
+            self.with_next_section(arena, data_storage, index, new_section, visited_stops_start)
+        };
+        Some(new_route)
+    }
 
-            let new_route = clone_update_route(self, |cloned_sections, cloned_visited_stops| {
-                if is_same_journey {
-                    let last_section = cloned_sections.last_mut().unwrap();
-                    last_section.set_arrival_stop_id(new_section.arrival_stop_id());
-                    last_section.set_arrival_at(new_section.arrival_at());
-                } else {
-                    cloned_sections.push(new_section);
-                }
+    /// The time left in minutes when changing to the journey of the new section, None if the route has no journey yet or if the journey is a through service.
+    /// The departure of the journey of the new section must have been set.
+    fn transfer_slack_to(
+        &self,
+        arena: &RouteArena,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        index: &RoutingIndex,
+        new_section: &RouteSection,
+    ) -> Option<i16> {
+        let mut walking_duration = None;
+        let previous_section = self.sections_rev(arena).find(|section| {
+            if section.journey_id().is_none() {
+                walking_duration =
+                    Some(walking_duration.unwrap_or(0) + section.duration().unwrap_or(0));
+            }
+            section.journey_id().is_some()
+        })?;
+
+        if previous_section.is_continued_by(data_storage, index, new_section) {
+            return None;
+        }
 
-                cloned_visited_stops.extend(new_visited_stops);
-            });
-            Some(new_route)
-        })
+        Some(transfer_slack(
+            data_storage,
+            options,
+            index,
+            previous_section.journey_id().unwrap(),
+            previous_section.arrival_at(),
+            walking_duration,
+            self.arrival_stop_id(),
+            new_section.journey_id().unwrap(),
+            new_section.journey_departure_at().unwrap(),
+        ))
     }
 
-    pub fn to_route_result(&self, data_storage: &DataStorage) -> RouteResult {
-        let sections: Vec<_> = self
-            .sections()
-            .iter()
-            .map(|section| section.to_route_section_result(data_storage))
-            .collect();
+    /// Counterpart of extend, the route must have been built backward, its last section being the earliest one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn extend_backward(
+        &self,
+        arena: &mut RouteArena,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        index: &RoutingIndex,
+        journey: &Journey,
+        journey_index: u32,
+        arrival_at: NaiveDateTime,
+    ) -> Option<Route> {
+        let departure_stop_id = self.last_section().departure_stop_id();
+        let is_same_journey = self
+            .last_section()
+            .journey_index()
+            .is_some_and(|index| index == journey_index);
+
+        // A journey that loops can be boarded at its first stop and left at its last stop, which are the same.
+        if journey.first_stop_id() == departure_stop_id
+            && (is_same_journey || !journey.is_last_stop(departure_stop_id, false))
+        {
+            return None;
+        }
+
+        let visited_stops_start = arena.visited_stops().len();
+        let new_section = RouteSection::find_previous(
+            data_storage,
+            options,
+            index,
+            journey,
+            journey_index,
+            departure_stop_id,
+            arrival_at,
+            arena.visited_stops_mut(),
+        )?;
+
+        if self.has_visited_any_stops(arena, visited_stops_start)
+            && new_section.departure_stop_id() != journey.last_stop_id()
+        {
+            arena.visited_stops_mut().truncate(visited_stops_start);
+            return None;
+        }
 
-        let departure_at = if sections.first().unwrap().is_walking_trip() {
-            // This section is guaranteed not to be a walking trip.
-            sections[1].departure_at().unwrap()
+        let new_route = if is_same_journey {
+            let mut last_section = self.last_section().clone();
+            last_section.set_departure_stop_id(new_section.departure_stop_id());
+            last_section.set_departure_stop_index(new_section.departure_stop_index());
+            // The stop where the sections meet is counted once.
+            last_section.set_stop_count(last_section.stop_count() + new_section.stop_count() - 1);
+            self.with_last_section(arena, last_section, visited_stops_start)
         } else {
-            sections.first().unwrap().departure_at().unwrap()
+            self.with_next_section(arena, data_storage, index, new_section, visited_stops_start)
         };
+        Some(new_route)
+    }
 
-        let arrival_at = if sections.last().unwrap().is_walking_trip() {
-            // This section is guaranteed not to be a walking trip.
-            sections[sections.len() - 2].arrival_at().unwrap()
-        } else {
-            sections.last().unwrap().arrival_at().unwrap()
+    pub fn to_route_result(
+        &self,
+        arena: &RouteArena,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        index: &RoutingIndex,
+    ) -> RouteResult {
+        sections_to_route_result(data_storage, options, index, &self.sections(arena))
+    }
+
+    /// The route must have been built backward, the sections are put back in chronological order.
+    pub fn to_route_result_backward(
+        &self,
+        arena: &RouteArena,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        index: &RoutingIndex,
+    ) -> RouteResult {
+        let sections: Vec<_> = self.sections_rev(arena).cloned().collect();
+        sections_to_route_result(data_storage, options, index, &sections)
+    }
+}
+
+/// The sections must be in chronological order.
+pub fn sections_to_route_result(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    index: &RoutingIndex,
+    route_sections: &[RouteSection],
+) -> RouteResult {
+    let mut sections: Vec<_> = route_sections
+        .iter()
+        .map(|section| section.to_route_section_result(data_storage, options))
+        .collect();
+
+    for (i, pair) in route_sections.windows(2).enumerate() {
+        if pair[0].is_continued_by(data_storage, index, &pair[1]) {
+            sections[i + 1].set_is_through_service(true);
+        }
+    }
+
+    if let Some(profile) = options.accessibility_profile() {
+        for (section, section_result) in route_sections.iter().zip(sections.iter_mut()) {
+            let Some(journey) = section.journey(data_storage, options) else {
+                continue;
+            };
+
+            let route = journey.route();
+            let departure_position = route
+                .iter()
+                .position(|route_entry| route_entry.stop_id() == section.departure_stop_id())
+                .unwrap();
+            let arrival_position = departure_position
+                + route[departure_position..]
+                    .iter()
+                    .position(|route_entry| route_entry.stop_id() == section.arrival_stop_id())
+                    .unwrap();
+
+            // The attributes must apply all the way from the departure stop to the arrival stop.
+            let mut attributes: Vec<_> = profile
+                .required_attributes()
+                .iter()
+                .filter(|&attribute| {
+                    index
+                        .journey_attributes(journey.id())
+                        .is_some_and(|journey_attributes| {
+                            journey_attributes.has_attribute_between(
+                                attribute,
+                                departure_position,
+                                arrival_position,
+                            )
+                        })
+                })
+                .cloned()
+                .collect();
+            attributes.sort();
+            section_result.set_accessibility_attributes(attributes);
+        }
+    }
+
+    // The walking trips between two journeys are part of the change.
+    let mut previous: Option<(i32, NaiveDateTime)> = None;
+    let mut walking_duration = None;
+
+    for (section, section_result) in route_sections.iter().zip(sections.iter_mut()) {
+        let Some(journey_id) = section.journey_id() else {
+            walking_duration =
+                Some(walking_duration.unwrap_or(0) + section.duration().unwrap_or(0));
+            continue;
         };
 
-        RouteResult::new(departure_at, arrival_at, sections)
+        if let Some((previous_journey_id, arrival_at)) =
+            previous.filter(|_| !section_result.is_through_service())
+        {
+            section_result.set_transfer_slack(Some(transfer_slack(
+                data_storage,
+                options,
+                index,
+                previous_journey_id,
+                arrival_at,
+                walking_duration,
+                section.departure_stop_id(),
+                journey_id,
+                section_result.departure_at().unwrap(),
+            )));
+        }
+
+        previous = Some((journey_id, section_result.arrival_at().unwrap()));
+        walking_duration = None;
     }
+
+    let departure_at = if sections.first().unwrap().is_walking_trip() {
+        // This section is guaranteed not to be a walking trip.
+        sections[1].departure_at().unwrap()
+    } else {
+        sections.first().unwrap().departure_at().unwrap()
+    };
+
+    let arrival_at = if sections.last().unwrap().is_walking_trip() {
+        // This section is guaranteed not to be a walking trip.
+        sections[sections.len() - 2].arrival_at().unwrap()
+    } else {
+        sections.last().unwrap().arrival_at().unwrap()
+    };
+
+    RouteResult::new(departure_at, arrival_at, sections)
 }
 
 impl RouteSection {
+    /// The stops visited by the section are pushed to visited_stops.
+    /// The journey cannot be left at a stop where it does not stop according to the real-time updates and the scenario.
+    #[allow(clippy::too_many_arguments)]
     pub fn find_next(
         data_storage: &DataStorage,
+        options: &RoutingOptions,
+        index: &RoutingIndex,
         journey: &Journey,
+        journey_index: u32,
         departure_stop_id: i32,
-        date: NaiveDate,
-        is_departure_date: bool,
-    ) -> Option<(RouteSection, FxHashSet<i32>)> {
-        let mut route_iter = journey.route().iter();
+        operating_date: NaiveDate,
+        visited_stops: &mut Vec<u32>,
+    ) -> Option<RouteSection> {
+        let stop_indices = index.journey_stop_indices(journey_index);
+        let mut route_iter = journey.route().iter().zip(stop_indices).enumerate();
+
+        let (departure_position, departure_stop_index) = route_iter
+            .by_ref()
+            .find(|(_, (route_entry, _))| route_entry.stop_id() == departure_stop_id)
+            .map(|(position, (_, &stop_index))| (position, stop_index))?;
 
-        while let Some(route_entry) = route_iter.next() {
-            if route_entry.stop_id() == departure_stop_id {
+        let visited_stops_start = visited_stops.len();
+        let arrival_at_of =
+            |stop_id: i32| delayed_arrival_at(options, journey, stop_id, operating_date);
+        let mut previous_stop_id = departure_stop_id;
+
+        for (position, (route_entry, &stop_index)) in route_iter {
+            let stop = find_stop(data_storage, options, route_entry.stop_id())
+                .unwrap_or_else(|| panic!("Stop {:?} not found.", route_entry.stop_id()));
+            visited_stops.push(stop_index);
+
+            // The journey cannot go further than where it lacks the required attributes.
+            if !options.accepts_journey_segment(index, journey.id(), position - 1) {
                 break;
             }
-        }
 
-        let mut visited_stops = FxHashSet::default();
-
-        while let Some(route_entry) = route_iter.next() {
-            let stop = route_entry.stop(data_storage);
-            visited_stops.insert(stop.id());
+            if let Some(scenario) = options.scenario() {
+                // The journey cannot go further than a cut segment, which is checked at the arrival at its end.
+                if scenario.has_cut_segment(previous_stop_id, stop.id())
+                    && scenario.is_segment_cut(
+                        previous_stop_id,
+                        stop.id(),
+                        arrival_at_of(stop.id()),
+                    )
+                {
+                    break;
+                }
+            }
+            previous_stop_id = stop.id();
 
             if stop.can_be_used_as_exchange_point() || journey.is_last_stop(stop.id(), false) {
-                let arrival_at = journey.arrival_at_of_with_origin(
-                    stop.id(),
-                    date,
-                    is_departure_date,
+                let arrival_at = arrival_at_of(stop.id());
+
+                if !options.is_stop_served(journey.id(), operating_date, stop.id(), arrival_at) {
+                    continue;
+                }
+
+                let mut section = RouteSection::new(
+                    Some(journey.id()),
+                    Some(journey_index),
                     departure_stop_id,
+                    departure_stop_index,
+                    stop.id(),
+                    stop_index,
+                    arrival_at,
+                    None,
                 );
+                section.set_stop_count(position - departure_position + 1);
+                return Some(section);
+            }
+        }
 
-                return Some((
-                    RouteSection::new(
-                        Some(journey.id()),
-                        departure_stop_id,
-                        stop.id(),
-                        arrival_at,
-                        None,
-                    ),
-                    visited_stops,
-                ));
+        visited_stops.truncate(visited_stops_start);
+        None
+    }
+
+    /// Counterpart of find_next, the journey is traversed backward from the arrival stop.
+    #[allow(clippy::too_many_arguments)]
+    pub fn find_previous(
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        index: &RoutingIndex,
+        journey: &Journey,
+        journey_index: u32,
+        arrival_stop_id: i32,
+        arrival_at: NaiveDateTime,
+        visited_stops: &mut Vec<u32>,
+    ) -> Option<RouteSection> {
+        let stop_indices = index.journey_stop_indices(journey_index);
+        let mut route_iter = journey.route().iter().zip(stop_indices).enumerate().rev();
+
+        let (arrival_position, arrival_stop_index) = route_iter
+            .by_ref()
+            .find(|(_, (route_entry, _))| route_entry.stop_id() == arrival_stop_id)
+            .map(|(position, (_, &stop_index))| (position, stop_index))?;
+
+        let visited_stops_start = visited_stops.len();
+
+        for (position, (route_entry, &stop_index)) in route_iter {
+            let stop = find_stop(data_storage, options, route_entry.stop_id())
+                .unwrap_or_else(|| panic!("Stop {:?} not found.", route_entry.stop_id()));
+            visited_stops.push(stop_index);
+
+            // The journey cannot come from further than where it lacks the required attributes.
+            if !options.accepts_journey_segment(index, journey.id(), position) {
+                break;
+            }
+
+            if stop.can_be_used_as_exchange_point() || stop.id() == journey.first_stop_id() {
+                let mut section = RouteSection::new(
+                    Some(journey.id()),
+                    Some(journey_index),
+                    stop.id(),
+                    stop_index,
+                    arrival_stop_id,
+                    arrival_stop_index,
+                    arrival_at,
+                    None,
+                );
+                section.set_stop_count(arrival_position - position + 1);
+                return Some(section);
             }
         }
 
+        visited_stops.truncate(visited_stops_start);
         None
     }
 
-    pub fn to_route_section_result(&self, data_storage: &DataStorage) -> RouteSectionResult {
-        let departure_stop = data_storage
-            .stops()
-            .find(self.departure_stop_id())
+    pub fn to_route_section_result(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+    ) -> RouteSectionResult {
+        let departure_stop = find_stop(data_storage, options, self.departure_stop_id())
             .unwrap_or_else(|| panic!("Stop {:?} not found.", self.departure_stop_id()));
-        let arrival_stop = data_storage
-            .stops()
-            .find(self.arrival_stop_id())
+        let arrival_stop = find_stop(data_storage, options, self.arrival_stop_id())
             .unwrap_or_else(|| panic!("Stop {:?} not found.", self.arrival_stop_id()));
 
-        let (departure_at, arrival_at) = if self.journey_id().is_some() {
-            let departure_at = self
-                .journey(data_storage)
-                .unwrap()
-                .departure_at_of_with_origin(
+        let (departure_at, arrival_at) = if let Some(journey) = self.journey(data_storage, options)
+        {
+            let operating_date =
+                operating_date_of_arrival(options, journey, arrival_stop.id(), self.arrival_at());
+            (
+                Some(delayed_departure_at(
+                    options,
+                    journey,
                     departure_stop.id(),
-                    self.arrival_at().date(),
-                    false,
-                    arrival_stop.id(),
-                );
-            (Some(departure_at), Some(self.arrival_at()))
+                    operating_date,
+                )),
+                Some(self.arrival_at()),
+            )
         } else {
             (None, None)
         };
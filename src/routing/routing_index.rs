@@ -0,0 +1,326 @@
+use std::{borrow::Cow, sync::Arc};
+
+use hrdf_parser::{DataStorage, Journey, Model};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::{
+    accessibility::{Attributes, JourneyAttributes},
+    exchange_times_line::{journey_metadata, ExchangeTimesLine, JourneyLine, JourneyLines},
+    overlay::TimetableOverlay,
+    through_services::ThroughServices,
+};
+
+/// Maps the stops and the journeys to dense indices, the state of a search for each stop or journey is stored in flat vectors.
+/// It is built once and shared by all the searches, together with the timetable data that hrdf-parser does not expose.
+/// A search with a timetable overlay uses a copy sharing the timetable data and extended with a small index of the overlay.
+#[derive(Debug, Clone)]
+pub struct RoutingIndex {
+    timetable: Arc<TimetableIndex>,
+    overlay: Option<OverlayIndex>,
+}
+
+#[derive(Debug)]
+struct TimetableIndex {
+    stop_ids: Vec<i32>,
+    stop_index_by_id: FxHashMap<i32, u32>,
+    journey_count: usize,
+    journey_index_by_id: FxHashMap<i32, u32>,
+    // Indexed by journey index.
+    journey_stop_indices: Vec<Vec<u32>>,
+    exchange_times_line: ExchangeTimesLine,
+    // Only the journeys having a line or a direction are kept.
+    journey_lines: FxHashMap<i32, JourneyLines>,
+    attributes: Attributes,
+    // Only the journeys having attributes are kept.
+    journey_attributes: FxHashMap<i32, JourneyAttributes>,
+    through_services: ThroughServices,
+}
+
+/// The stops and the journeys of the timetable overlay, indexed after the ones of the timetable.
+#[derive(Debug, Clone, Default)]
+struct OverlayIndex {
+    stop_ids: Vec<i32>,
+    stop_index_by_id: FxHashMap<i32, u32>,
+    journey_index_by_id: FxHashMap<i32, u32>,
+    // Indexed by journey index, minus the number of journeys of the timetable.
+    journey_stop_indices: Vec<Vec<u32>>,
+    journey_lines: FxHashMap<i32, JourneyLines>,
+    journey_attributes: FxHashMap<i32, JourneyAttributes>,
+}
+
+impl RoutingIndex {
+    pub fn new(data_storage: &DataStorage) -> Self {
+        let mut stop_ids: Vec<i32> = data_storage.stops().data().keys().copied().collect();
+        stop_ids.sort();
+
+        let stop_index_by_id: FxHashMap<i32, u32> = stop_ids
+            .iter()
+            .enumerate()
+            .map(|(index, &stop_id)| (stop_id, index as u32))
+            .collect();
+
+        let mut journey_ids: Vec<i32> = data_storage.journeys().data().keys().copied().collect();
+        journey_ids.sort();
+
+        let journey_index_by_id = journey_ids
+            .iter()
+            .enumerate()
+            .map(|(index, &journey_id)| (journey_id, index as u32))
+            .collect();
+
+        let journey_stop_indices: Vec<_> = journey_ids
+            .iter()
+            .map(|&journey_id| {
+                let journey = data_storage.journeys().find(journey_id).unwrap();
+                to_stop_indices(journey, |stop_id| stop_index_by_id.get(&stop_id).copied())
+            })
+            .collect();
+
+        // The journeys of the timetable overlay are checked when they are added, only the ones of the timetable may stop at an unknown stop.
+        let unridable_journey_ids: Vec<i32> = journey_ids
+            .iter()
+            .zip(&journey_stop_indices)
+            .filter(|(_, stop_indices)| stop_indices.is_empty())
+            .map(|(&journey_id, _)| journey_id)
+            .collect();
+
+        if !unridable_journey_ids.is_empty() {
+            log::warn!(
+                "{} journeys stop at stops that do not exist and cannot be ridden: {:?}.",
+                unridable_journey_ids.len(),
+                unridable_journey_ids
+            );
+        }
+
+        let mut timetable = TimetableIndex {
+            stop_ids,
+            stop_index_by_id,
+            journey_count: journey_ids.len(),
+            journey_index_by_id,
+            journey_stop_indices,
+            exchange_times_line: ExchangeTimesLine::new(data_storage),
+            journey_lines: FxHashMap::default(),
+            attributes: Attributes::new(data_storage),
+            journey_attributes: FxHashMap::default(),
+            through_services: ThroughServices::new(data_storage),
+        };
+
+        for journey in data_storage.journeys().entries() {
+            add_journey_metadata(
+                &timetable.attributes,
+                &mut timetable.journey_lines,
+                &mut timetable.journey_attributes,
+                journey,
+            );
+        }
+
+        Self {
+            timetable: Arc::new(timetable),
+            overlay: None,
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn exchange_times_line(&self) -> &ExchangeTimesLine {
+        &self.timetable.exchange_times_line
+    }
+
+    pub fn through_services(&self) -> &ThroughServices {
+        &self.timetable.through_services
+    }
+
+    // Functions
+
+    /// Returns the index extended with the stops and the journeys of the timetable overlay, if there is one.
+    /// Only the timetable overlay is indexed, the timetable data is shared with this index.
+    pub fn with_timetable_overlay(
+        &self,
+        timetable_overlay: Option<&TimetableOverlay>,
+    ) -> Cow<'_, RoutingIndex> {
+        let Some(timetable_overlay) = timetable_overlay else {
+            return Cow::Borrowed(self);
+        };
+
+        let timetable = &self.timetable;
+        let mut overlay = OverlayIndex::default();
+
+        for stop in timetable_overlay.stops() {
+            let stop_index = timetable.stop_ids.len() + overlay.stop_ids.len();
+            overlay
+                .stop_index_by_id
+                .insert(stop.id(), stop_index as u32);
+            overlay.stop_ids.push(stop.id());
+        }
+
+        for journey in timetable_overlay.journeys() {
+            let journey_index = timetable.journey_count + overlay.journey_index_by_id.len();
+            overlay
+                .journey_index_by_id
+                .insert(journey.id(), journey_index as u32);
+            overlay
+                .journey_stop_indices
+                .push(to_stop_indices(journey, |stop_id| {
+                    timetable
+                        .stop_index_by_id
+                        .get(&stop_id)
+                        .or_else(|| overlay.stop_index_by_id.get(&stop_id))
+                        .copied()
+                }));
+            add_journey_metadata(
+                &timetable.attributes,
+                &mut overlay.journey_lines,
+                &mut overlay.journey_attributes,
+                journey,
+            );
+        }
+
+        Cow::Owned(Self {
+            timetable: Arc::clone(timetable),
+            overlay: Some(overlay),
+        })
+    }
+
+    pub fn stop_count(&self) -> usize {
+        self.timetable.stop_ids.len() + self.overlay.as_ref().map_or(0, |o| o.stop_ids.len())
+    }
+
+    pub fn journey_count(&self) -> usize {
+        self.timetable.journey_count
+            + self
+                .overlay
+                .as_ref()
+                .map_or(0, |o| o.journey_index_by_id.len())
+    }
+
+    pub fn stop_id(&self, stop_index: u32) -> i32 {
+        let stop_count = self.timetable.stop_ids.len();
+
+        match self.overlay.as_ref() {
+            Some(overlay) if stop_index as usize >= stop_count => {
+                overlay.stop_ids[stop_index as usize - stop_count]
+            }
+            _ => self.timetable.stop_ids[stop_index as usize],
+        }
+    }
+
+    pub fn stop_index(&self, stop_id: i32) -> u32 {
+        self.find_stop_index(stop_id)
+            .unwrap_or_else(|| panic!("Stop {:?} not found.", stop_id))
+    }
+
+    /// Returns None if the stop does not exist, which can happen for the stops given by the user.
+    pub fn find_stop_index(&self, stop_id: i32) -> Option<u32> {
+        self.timetable
+            .stop_index_by_id
+            .get(&stop_id)
+            .or_else(|| self.overlay.as_ref()?.stop_index_by_id.get(&stop_id))
+            .copied()
+    }
+
+    /// Returns the line and the direction of the journey at the stop.
+    pub fn journey_line(&self, journey: &Journey, stop_id: i32) -> JourneyLine<'_> {
+        let Some(journey_lines) = self
+            .timetable
+            .journey_lines
+            .get(&journey.id())
+            .or_else(|| self.overlay.as_ref()?.journey_lines.get(&journey.id()))
+        else {
+            return JourneyLine::default();
+        };
+
+        journey
+            .route()
+            .iter()
+            .position(|route_entry| route_entry.stop_id() == stop_id)
+            .map_or_else(JourneyLine::default, |position| journey_lines.at(position))
+    }
+
+    /// Returns None if the journey has no attributes.
+    pub fn journey_attributes(&self, journey_id: i32) -> Option<&JourneyAttributes> {
+        self.timetable
+            .journey_attributes
+            .get(&journey_id)
+            .or_else(|| self.overlay.as_ref()?.journey_attributes.get(&journey_id))
+    }
+
+    /// Returns true if the journey has all the attributes all the way from position 1 to position 2 in its route.
+    pub fn journey_has_attributes(
+        &self,
+        journey_id: i32,
+        designations: &FxHashSet<String>,
+        position_1: usize,
+        position_2: usize,
+    ) -> bool {
+        if designations.is_empty() {
+            return true;
+        }
+
+        self.journey_attributes(journey_id)
+            .is_some_and(|journey_attributes| {
+                designations.iter().all(|designation| {
+                    journey_attributes.has_attribute_between(designation, position_1, position_2)
+                })
+            })
+    }
+
+    /// Returns the designation of the attribute of the stop connection from stop 1 to stop 2, if any.
+    pub fn stop_connection_attribute(&self, stop_id_1: i32, stop_id_2: i32) -> Option<&str> {
+        self.timetable
+            .attributes
+            .stop_connection_attribute(stop_id_1, stop_id_2)
+    }
+
+    pub fn journey_index(&self, journey_id: i32) -> u32 {
+        *self
+            .timetable
+            .journey_index_by_id
+            .get(&journey_id)
+            .or_else(|| self.overlay.as_ref()?.journey_index_by_id.get(&journey_id))
+            .unwrap_or_else(|| panic!("Journey {:?} not found.", journey_id))
+    }
+
+    /// Returns the indices of the stops of the journey, in the order of its route.
+    /// Empty if one of the stops does not exist, the journey then cannot be ridden (as with RAPTOR and CSA).
+    /// Such journeys are logged when the index is built.
+    pub fn journey_stop_indices(&self, journey_index: u32) -> &[u32] {
+        let journey_count = self.timetable.journey_count;
+
+        match self.overlay.as_ref() {
+            Some(overlay) if journey_index as usize >= journey_count => {
+                &overlay.journey_stop_indices[journey_index as usize - journey_count]
+            }
+            _ => &self.timetable.journey_stop_indices[journey_index as usize],
+        }
+    }
+}
+
+/// The stop ids of the route of the journey are converted once, the searches then carry the indices.
+fn to_stop_indices(journey: &Journey, find_stop_index: impl Fn(i32) -> Option<u32>) -> Vec<u32> {
+    journey
+        .route()
+        .iter()
+        .map(|route_entry| find_stop_index(route_entry.stop_id()))
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default()
+}
+
+/// The metadata of each journey is read once.
+fn add_journey_metadata(
+    attributes: &Attributes,
+    journey_lines: &mut FxHashMap<i32, JourneyLines>,
+    journey_attributes: &mut FxHashMap<i32, JourneyAttributes>,
+    journey: &Journey,
+) {
+    let mut metadata = journey_metadata(journey);
+
+    let new_journey_lines = JourneyLines::new(journey, &mut metadata);
+    if !new_journey_lines.is_empty() {
+        journey_lines.insert(journey.id(), new_journey_lines);
+    }
+
+    let new_journey_attributes = JourneyAttributes::new(journey, &mut metadata, attributes);
+    if !new_journey_attributes.is_empty() {
+        journey_attributes.insert(journey.id(), new_journey_attributes);
+    }
+}
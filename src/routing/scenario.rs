@@ -0,0 +1,168 @@
+use std::io::Read;
+
+use chrono::NaiveDateTime;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+/// A what-if disruption, masking stops, journeys, stop connections and segments between two consecutive stops for time intervals.
+/// The start of an interval is included, its end is excluded.
+/// A closed stop can neither be boarded, left nor walked to, but the journeys pass through it.
+/// A cancelled journey can neither be boarded nor left, a cut segment cannot be travelled, and a closed stop connection cannot be walked.
+/// The stop connections and the segments are closed in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    closed_stops: FxHashMap<i32, Vec<(NaiveDateTime, NaiveDateTime)>>,
+    cancelled_journeys: FxHashMap<i32, Vec<(NaiveDateTime, NaiveDateTime)>>,
+    closed_stop_connections: FxHashMap<(i32, i32), Vec<(NaiveDateTime, NaiveDateTime)>>,
+    cut_segments: FxHashMap<(i32, i32), Vec<(NaiveDateTime, NaiveDateTime)>>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the scenario in the JSON format below, all the lists are optional.
+    ///
+    /// {"closed_stops": [{"stop_id": 8500218, "from": "2024-06-01T10:00:00", "to": "2024-06-01T14:00:00"}],
+    ///  "cancelled_journeys": [{"journey_id": 1, "from": ..., "to": ...}],
+    ///  "closed_stop_connections": [{"stop_id_1": 8500218, "stop_id_2": 8572747, "from": ..., "to": ...}],
+    ///  "cut_segments": [{"stop_id_1": 8500218, "stop_id_2": 8500023, "from": ..., "to": ...}]}
+    pub fn from_json<R: Read>(reader: R) -> serde_json::Result<Self> {
+        let message: ScenarioMessage = serde_json::from_reader(reader)?;
+        let mut scenario = Self::new();
+
+        for entry in message.closed_stops {
+            scenario.close_stop(entry.stop_id, entry.from, entry.to);
+        }
+
+        for entry in message.cancelled_journeys {
+            scenario.cancel_journey(entry.journey_id, entry.from, entry.to);
+        }
+
+        for entry in message.closed_stop_connections {
+            scenario.close_stop_connection(entry.stop_id_1, entry.stop_id_2, entry.from, entry.to);
+        }
+
+        for entry in message.cut_segments {
+            scenario.cut_segment(entry.stop_id_1, entry.stop_id_2, entry.from, entry.to);
+        }
+
+        Ok(scenario)
+    }
+
+    // Functions
+
+    pub fn close_stop(&mut self, stop_id: i32, from: NaiveDateTime, to: NaiveDateTime) {
+        self.closed_stops
+            .entry(stop_id)
+            .or_default()
+            .push((from, to));
+    }
+
+    pub fn cancel_journey(&mut self, journey_id: i32, from: NaiveDateTime, to: NaiveDateTime) {
+        self.cancelled_journeys
+            .entry(journey_id)
+            .or_default()
+            .push((from, to));
+    }
+
+    pub fn close_stop_connection(
+        &mut self,
+        stop_id_1: i32,
+        stop_id_2: i32,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) {
+        self.closed_stop_connections
+            .entry(pair_key(stop_id_1, stop_id_2))
+            .or_default()
+            .push((from, to));
+    }
+
+    pub fn cut_segment(
+        &mut self,
+        stop_id_1: i32,
+        stop_id_2: i32,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) {
+        self.cut_segments
+            .entry(pair_key(stop_id_1, stop_id_2))
+            .or_default()
+            .push((from, to));
+    }
+
+    pub fn is_stop_closed(&self, stop_id: i32, at: NaiveDateTime) -> bool {
+        is_masked(self.closed_stops.get(&stop_id), at)
+    }
+
+    pub fn is_journey_cancelled(&self, journey_id: i32, at: NaiveDateTime) -> bool {
+        is_masked(self.cancelled_journeys.get(&journey_id), at)
+    }
+
+    pub fn is_stop_connection_closed(
+        &self,
+        stop_id_1: i32,
+        stop_id_2: i32,
+        at: NaiveDateTime,
+    ) -> bool {
+        is_masked(
+            self.closed_stop_connections
+                .get(&pair_key(stop_id_1, stop_id_2)),
+            at,
+        )
+    }
+
+    /// Returns true if the segment is cut at some time, which avoids computing the time the segment is travelled at.
+    pub fn has_cut_segment(&self, stop_id_1: i32, stop_id_2: i32) -> bool {
+        self.cut_segments
+            .contains_key(&pair_key(stop_id_1, stop_id_2))
+    }
+
+    pub fn is_segment_cut(&self, stop_id_1: i32, stop_id_2: i32, at: NaiveDateTime) -> bool {
+        is_masked(self.cut_segments.get(&pair_key(stop_id_1, stop_id_2)), at)
+    }
+}
+
+fn pair_key(stop_id_1: i32, stop_id_2: i32) -> (i32, i32) {
+    (stop_id_1.min(stop_id_2), stop_id_1.max(stop_id_2))
+}
+
+fn is_masked(intervals: Option<&Vec<(NaiveDateTime, NaiveDateTime)>>, at: NaiveDateTime) -> bool {
+    intervals.is_some_and(|intervals| intervals.iter().any(|&(from, to)| from <= at && at < to))
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenarioMessage {
+    #[serde(default)]
+    closed_stops: Vec<ClosedStop>,
+    #[serde(default)]
+    cancelled_journeys: Vec<CancelledJourney>,
+    #[serde(default)]
+    closed_stop_connections: Vec<ClosedStopPair>,
+    #[serde(default)]
+    cut_segments: Vec<ClosedStopPair>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClosedStop {
+    stop_id: i32,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelledJourney {
+    journey_id: i32,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClosedStopPair {
+    stop_id_1: i32,
+    stop_id_2: i32,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+}
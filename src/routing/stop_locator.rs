@@ -0,0 +1,147 @@
+use hrdf_parser::{Coordinates, DataStorage, Model, Stop};
+use kd_tree::KdTree;
+
+use crate::utils::haversine_distance;
+
+use super::models::RoutingOptions;
+
+/// The radius of the earth used by haversine_distance, in kilometers.
+const RADIUS_OF_EARTH_IN_KILOMETERS: f64 = 6371.0;
+
+/// Finds the stops near a point, the stops of the timetable are indexed once in a kd-tree shared by all the searches.
+/// The stops are placed on the unit sphere, the straight-line distance between two of them grows with the haversine distance.
+#[derive(Debug, Clone)]
+pub struct StopLocator {
+    tree: KdTree<([f64; 3], i32)>,
+    stop_count: usize,
+}
+
+impl StopLocator {
+    pub fn new(data_storage: &DataStorage) -> Self {
+        let points: Vec<_> = data_storage
+            .stops()
+            .entries()
+            .into_iter()
+            .filter_map(|stop| {
+                let coord = stop.wgs84_coordinates()?;
+                Some((to_unit_sphere(coord), stop.id()))
+            })
+            .collect();
+        let stop_count = points.len();
+
+        Self {
+            tree: KdTree::build_by_ordered_float(points),
+            stop_count,
+        }
+    }
+
+    // Functions
+
+    /// Returns the stops within the distance (in meters) of the point with their distance, the nearest first.
+    /// If there are none, the nearest stop is returned.
+    /// The stops of the timetable overlay are included, the stops not accepted by the options are not.
+    pub(crate) fn find_stops_within_distance(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        point: Coordinates,
+        distance: f64,
+    ) -> Vec<(i32, f64)> {
+        let query = to_unit_sphere(point);
+        let chord = 2.0 * (distance / 1000.0 / (2.0 * RADIUS_OF_EARTH_IN_KILOMETERS)).sin();
+
+        let mut stops: Vec<(i32, f64)> = self
+            .tree
+            .within_radius(&query, chord)
+            .into_iter()
+            .map(|&(_, stop_id)| stop_id)
+            .filter(|&stop_id| options.accepts_stop(stop_id))
+            .map(|stop_id| {
+                let stop = data_storage.stops().find(stop_id).unwrap();
+                (stop_id, distance_to_stop(point, stop))
+            })
+            // The overlay only adds a few stops, they are not indexed.
+            .chain(overlay_stops(options, point))
+            .filter(|&(_, stop_distance)| stop_distance <= distance)
+            .collect();
+
+        if stops.is_empty() {
+            stops.extend(self.find_nearest_stop(data_storage, options, point));
+        }
+
+        stops.sort_by(|(_, distance_1), (_, distance_2)| distance_1.total_cmp(distance_2));
+        stops
+    }
+
+    /// Returns the nearest stop accepted by the options with its distance (in meters).
+    fn find_nearest_stop(
+        &self,
+        data_storage: &DataStorage,
+        options: &RoutingOptions,
+        point: Coordinates,
+    ) -> Option<(i32, f64)> {
+        let query = to_unit_sphere(point);
+        let mut count = 1;
+
+        // The nearest stops are searched in growing numbers until one of them is accepted.
+        let nearest_stop = loop {
+            let nearest_stop = self
+                .tree
+                .nearests(&query, count)
+                .into_iter()
+                .map(|nearest| nearest.item.1)
+                .find(|&stop_id| options.accepts_stop(stop_id));
+
+            if nearest_stop.is_some() || count >= self.stop_count {
+                break nearest_stop;
+            }
+            count *= 2;
+        };
+
+        nearest_stop
+            .map(|stop_id| {
+                let stop = data_storage.stops().find(stop_id).unwrap();
+                (stop_id, distance_to_stop(point, stop))
+            })
+            .into_iter()
+            .chain(overlay_stops(options, point))
+            .min_by(|(_, distance_1), (_, distance_2)| distance_1.total_cmp(distance_2))
+    }
+}
+
+/// Returns the stops of the timetable overlay accepted by the options with their distance (in meters) to the point.
+fn overlay_stops<'a>(
+    options: &'a RoutingOptions,
+    point: Coordinates,
+) -> impl Iterator<Item = (i32, f64)> + 'a {
+    options
+        .timetable_overlay()
+        .into_iter()
+        .flat_map(|overlay| overlay.stops())
+        .filter(|stop| options.accepts_stop(stop.id()))
+        .filter(|stop| stop.wgs84_coordinates().is_some())
+        .map(move |stop| (stop.id(), distance_to_stop(point, stop)))
+}
+
+/// Returns the distance between the point and the stop, in meters.
+fn distance_to_stop(point: Coordinates, stop: &Stop) -> f64 {
+    let coord = stop.wgs84_coordinates().unwrap();
+
+    haversine_distance(
+        point.latitude(),
+        point.longitude(),
+        coord.latitude(),
+        coord.longitude(),
+    ) * 1000.0
+}
+
+fn to_unit_sphere(coord: Coordinates) -> [f64; 3] {
+    let latitude = coord.latitude().to_radians();
+    let longitude = coord.longitude().to_radians();
+
+    [
+        latitude.cos() * longitude.cos(),
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+    ]
+}
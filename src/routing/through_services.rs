@@ -0,0 +1,127 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use hrdf_parser::{DataStorage, Journey};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Deserialize;
+
+use crate::utils::sub_1_day;
+
+use super::{field_serializer::serialize_field, routing_index::RoutingIndex};
+
+// hrdf-parser does not expose the through services (DURCHBI) of the DataStorage.
+// They are read through the serde representation of the DataStorage.
+
+#[derive(Debug, Deserialize)]
+struct ThroughService {
+    journey_1_id: i32,
+    journey_1_stop_id: i32,
+    journey_2_id: i32,
+    bit_field_id: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ThroughServiceStorage {
+    data: FxHashMap<i32, ThroughService>,
+}
+
+/// The through services indexed by journey pair, with the stop where journey 1 continues as journey 2 and the bit field.
+#[derive(Debug, Clone, Default)]
+pub struct ThroughServices {
+    data: FxHashMap<(i32, i32), Vec<(i32, i32)>>,
+    stop_ids: FxHashSet<i32>,
+}
+
+impl ThroughServices {
+    pub fn new(data_storage: &DataStorage) -> Self {
+        let Some(storage) = serialize_field(data_storage, "through_service")
+            .and_then(|value| serde_json::from_value::<ThroughServiceStorage>(value).ok())
+        else {
+            log::warn!("Through services could not be read.");
+            return Self::default();
+        };
+
+        let mut data: FxHashMap<_, Vec<_>> = FxHashMap::default();
+        let mut stop_ids = FxHashSet::default();
+
+        for through_service in storage.data.into_values() {
+            data.entry((through_service.journey_1_id, through_service.journey_2_id))
+                .or_default()
+                .push((
+                    through_service.journey_1_stop_id,
+                    through_service.bit_field_id,
+                ));
+            stop_ids.insert(through_service.journey_1_stop_id);
+        }
+
+        Self { data, stop_ids }
+    }
+
+    // Functions
+
+    /// Returns true if a journey continues as another journey at the stop on some days.
+    pub fn has_stop(&self, stop_id: i32) -> bool {
+        self.stop_ids.contains(&stop_id)
+    }
+}
+
+/// Returns true if journey 1 continues as journey 2 at the stop, the passengers stay seated.
+/// The date and time is the arrival of journey 1 at the stop or the departure of journey 2,
+/// the bit field of the through service applies to the operating day of journey 1.
+pub fn is_through_service(
+    data_storage: &DataStorage,
+    index: &RoutingIndex,
+    stop_id: i32,
+    journey_id_1: i32,
+    journey_id_2: i32,
+    date_time: NaiveDateTime,
+) -> bool {
+    let Some(entries) = index
+        .through_services()
+        .data
+        .get(&(journey_id_1, journey_id_2))
+    else {
+        return false;
+    };
+
+    let Some(operating_date) = data_storage
+        .journeys()
+        .find(journey_id_1)
+        .and_then(|journey| operating_date(journey, stop_id, date_time))
+    else {
+        return false;
+    };
+
+    let Some(bit_fields) = data_storage.bit_fields_by_day().get(&operating_date) else {
+        return false;
+    };
+
+    entries
+        .iter()
+        .any(|&(through_service_stop_id, bit_field_id)| {
+            through_service_stop_id == stop_id && bit_fields.contains(&bit_field_id)
+        })
+}
+
+/// Returns the day on which the journey departs from its first stop, given a date and time at the stop not earlier than its arrival and less than a day later.
+fn operating_date(journey: &Journey, stop_id: i32, date_time: NaiveDateTime) -> Option<NaiveDate> {
+    let route = journey.route();
+    let first_departure_time = (*route.first()?.departure_time())?;
+    let arrival_time = route
+        .iter()
+        // The first route entry has no arrival time.
+        .skip(1)
+        .find(|route_entry| route_entry.stop_id() == stop_id)
+        .and_then(|route_entry| *route_entry.arrival_time())?;
+
+    let arrival_date = if arrival_time <= date_time.time() {
+        date_time.date()
+    } else {
+        sub_1_day(date_time.date())
+    };
+
+    // The arrival time is on the next day if it is earlier than the first departure time.
+    if arrival_time < first_departure_time {
+        Some(sub_1_day(arrival_date))
+    } else {
+        Some(arrival_date)
+    }
+}
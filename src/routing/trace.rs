@@ -0,0 +1,90 @@
+use std::{fmt::Debug, io::Write, sync::Mutex};
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// Explains why the level exploration did not explore a route or a journey further.
+/// The journey id of a route is the one of its last section, None if the route ends by walking.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SearchEvent {
+    /// The connections are not explored from a stop that cannot be used for exchange.
+    StopNotExchangePoint {
+        stop_id: i32,
+        journey_id: Option<i32>,
+        arrival_at: NaiveDateTime,
+    },
+    /// Another route reached the stop at the same time or earlier, the connections are not explored again.
+    RouteDominated {
+        stop_id: i32,
+        journey_id: Option<i32>,
+        arrival_at: NaiveDateTime,
+        earliest_arrival_at: NaiveDateTime,
+    },
+    /// The journey has already been explored at a lower connection level.
+    JourneyAlreadyExplored {
+        stop_id: i32,
+        journey_id: i32,
+        departure_at: NaiveDateTime,
+    },
+    /// Another journey departing earlier from the stop serves the same stops and arrives no later at each of them, or the route already served them.
+    JourneySameTerminus {
+        stop_id: i32,
+        journey_id: i32,
+        departure_at: NaiveDateTime,
+    },
+    /// The journey departs before the exchange time from the previous journey has elapsed.
+    ExchangeTimeNotMet {
+        stop_id: i32,
+        previous_journey_id: i32,
+        journey_id: i32,
+        arrival_at: NaiveDateTime,
+        departure_at: NaiveDateTime,
+        exchange_time: i16,
+    },
+    /// The journey departs before the minimum transfer slack has elapsed, once the exchange time is over.
+    TransferSlackNotMet {
+        stop_id: i32,
+        previous_journey_id: i32,
+        journey_id: i32,
+        arrival_at: NaiveDateTime,
+        departure_at: NaiveDateTime,
+        transfer_slack: i16,
+    },
+}
+
+/// Receives the events of the searches, see RoutingOptions::set_event_sink.
+pub trait SearchEventSink: Debug + Send + Sync {
+    fn record(&self, event: SearchEvent);
+}
+
+/// Keeps all the events in the order they were recorded, to be dumped as JSON once the search is done.
+#[derive(Debug, Default)]
+pub struct SearchTrace {
+    events: Mutex<Vec<SearchEvent>>,
+}
+
+impl SearchTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Getters/Setters
+
+    pub fn events(&self) -> Vec<SearchEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    // Functions
+
+    /// Writes the events as a JSON array.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(writer, &*self.events.lock().unwrap())
+    }
+}
+
+impl SearchEventSink for SearchTrace {
+    fn record(&self, event: SearchEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
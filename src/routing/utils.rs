@@ -1,18 +1,38 @@
-use hrdf_parser::{DataStorage, StopConnection};
-use rustc_hash::FxHashSet;
+use std::{
+    collections::BTreeSet,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{atomic::AtomicUsize, Arc},
+};
 
-use super::models::{Route, RouteSection};
+use hrdf_parser::{DataStorage, Journey, Stop, StopConnection};
+use rustc_hash::FxHashSet;
 
-pub fn clone_update_route<F>(route: &Route, f: F) -> Route
-where
-    F: FnOnce(&mut Vec<RouteSection>, &mut FxHashSet<i32>),
-{
-    let mut cloned_sections = route.sections().clone();
-    let mut cloned_visited_stops = route.visited_stops().clone();
+use super::models::{AbortReason, Route, RouteArena, RoutingOptions, SearchAborted, SearchResult};
 
-    f(&mut cloned_sections, &mut cloned_visited_stops);
+/// Looks the journey up in the timetable, then in the timetable overlay.
+pub fn find_journey<'a>(
+    data_storage: &'a DataStorage,
+    options: &'a RoutingOptions,
+    journey_id: i32,
+) -> Option<&'a Journey> {
+    data_storage.journeys().find(journey_id).or_else(|| {
+        options
+            .timetable_overlay()
+            .and_then(|overlay| overlay.find_journey(journey_id))
+    })
+}
 
-    Route::new(cloned_sections, cloned_visited_stops)
+/// Looks the stop up in the timetable, then in the timetable overlay.
+pub fn find_stop<'a>(
+    data_storage: &'a DataStorage,
+    options: &'a RoutingOptions,
+    stop_id: i32,
+) -> Option<&'a Stop> {
+    data_storage.stops().find(stop_id).or_else(|| {
+        options
+            .timetable_overlay()
+            .and_then(|overlay| overlay.find_stop(stop_id))
+    })
 }
 
 pub fn get_stop_connections(
@@ -30,26 +50,144 @@ pub fn get_stop_connections(
         })
 }
 
-pub fn get_routes_to_ignore(data_storage: &DataStorage, route: &Route) -> FxHashSet<u64> {
-    route
-        .sections()
+/// Counterpart of get_stop_connections, the stop connections of the timetable overlay are added.
+pub fn get_stop_connections_from<'a>(
+    data_storage: &'a DataStorage,
+    options: &'a RoutingOptions,
+    stop_id: i32,
+) -> Vec<&'a StopConnection> {
+    let mut stop_connections = get_stop_connections(data_storage, stop_id).unwrap_or_default();
+
+    if let Some(overlay) = options.timetable_overlay() {
+        stop_connections.extend(overlay.stop_connections(stop_id));
+    }
+    stop_connections
+}
+
+/// Returns the stop connections leading to the stop, including the ones of the timetable overlay.
+/// Only the nearby stops listed by the stop are considered, as the stop connections are indexed by their first stop.
+pub fn get_stop_connections_to<'a>(
+    data_storage: &'a DataStorage,
+    options: &'a RoutingOptions,
+    stop_id: i32,
+) -> Vec<&'a StopConnection> {
+    get_stop_connections_from(data_storage, options, stop_id)
         .into_iter()
+        // Sometimes certain stop identifiers don't exist for unknown reasons.
+        .filter(|stop_connection| {
+            find_stop(data_storage, options, stop_connection.stop_id_2()).is_some()
+        })
+        .filter_map(|stop_connection| {
+            get_stop_connections_from(data_storage, options, stop_connection.stop_id_2())
+                .into_iter()
+                .find(|reverse_stop_connection| reverse_stop_connection.stop_id_2() == stop_id)
+        })
+        .collect()
+}
+
+pub fn get_routes_to_ignore(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    arena: &RouteArena,
+    route: &Route,
+) -> FxHashSet<u64> {
+    route
+        .sections_rev(arena)
         .filter_map(|section| {
             section
-                .journey(data_storage)
+                .journey(data_storage, options)
                 .and_then(|journey| journey.hash_route(route.arrival_stop_id()))
         })
         .collect()
 }
 
-pub fn sort_routes(routes: &mut Vec<Route>) {
+/// The route must have been built backward, its last section being the earliest one.
+pub fn get_routes_to_ignore_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    arena: &RouteArena,
+    route: &Route,
+) -> FxHashSet<u64> {
+    route
+        .sections_rev(arena)
+        .filter_map(|section| {
+            section.journey(data_storage, options).and_then(|journey| {
+                hash_route_backward(journey, route.last_section().departure_stop_id())
+            })
+        })
+        .collect()
+}
+
+/// Counterpart of Journey::hash_route, the stops are taken from the start of the journey up to the arrival stop.
+pub fn hash_route_backward(journey: &Journey, arrival_stop_id: i32) -> Option<u64> {
+    let index = journey
+        .route()
+        .iter()
+        .rposition(|route_entry| route_entry.stop_id() == arrival_stop_id)?;
+
+    let mut hasher = DefaultHasher::new();
+    journey
+        .route()
+        .iter()
+        .take(index + 1)
+        .map(|route_entry| route_entry.stop_id())
+        .collect::<BTreeSet<_>>()
+        .hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+pub fn sort_routes(routes: &mut [Route]) {
     routes.sort_by_key(|route| route.arrival_at());
 }
 
-pub fn sorted_insert(routes: &mut Vec<Route>, route_to_insert: Route) {
-    let index = routes
-        .into_iter()
-        .position(|route| route_to_insert.arrival_at() < route.arrival_at())
-        .unwrap_or_else(|| routes.len());
-    routes.insert(index, route_to_insert);
+/// The routes must have been built backward, they are sorted by descending departure time.
+pub fn sort_routes_backward(
+    data_storage: &DataStorage,
+    options: &RoutingOptions,
+    routes: &mut [Route],
+) {
+    routes.sort_by_cached_key(|route| {
+        std::cmp::Reverse(route.last_section().departure_at(data_storage, options))
+    });
+}
+
+/// Applies the function to the result, whether the search completed or was aborted.
+pub fn map_search_result<T, U, F>(result: SearchResult<T>, f: F) -> SearchResult<U>
+where
+    F: FnOnce(T) -> U,
+{
+    match result {
+        Ok(result) => Ok(f(result)),
+        Err(aborted) => Err(aborted.map(f)),
+    }
+}
+
+/// Returns an error holding the result if the search was aborted.
+pub fn to_search_result<T>(result: T, abort_reason: Option<AbortReason>) -> SearchResult<T> {
+    match abort_reason {
+        Some(reason) => Err(SearchAborted::new(reason, result)),
+        None => Ok(result),
+    }
+}
+
+/// Returns the result, complete or partial, with the reason if the search was aborted.
+pub fn from_search_result<T>(result: SearchResult<T>) -> (T, Option<AbortReason>) {
+    match result {
+        Ok(result) => (result, None),
+        Err(aborted) => {
+            let reason = aborted.reason();
+            (aborted.into_partial_result(), Some(reason))
+        }
+    }
+}
+
+/// Returns a copy of the options whose searches share the maximum number of explored routes, for the functions running several searches.
+/// The deadline being an instant, it is already shared by the searches run with the options.
+pub fn with_shared_budget(options: &RoutingOptions) -> RoutingOptions {
+    let mut options = options.clone();
+
+    if options.explored_routes_counter().is_none() {
+        options.set_explored_routes_counter(Some(Arc::new(AtomicUsize::new(0))));
+    }
+    options
 }
@@ -1,20 +1,66 @@
-use std::{str::FromStr, sync::Arc};
-
-use axum::{extract::Query, http::StatusCode, routing::get, Json, Router};
+use std::{
+    env,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration as StdDuration, Instant},
+};
+
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderMap, Method, StatusCode},
+    routing::{get, post, put},
+    Json, Router,
+};
 use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use hrdf_parser::{timetable_end_date, timetable_start_date, Hrdf};
+use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowMethods, Any, CorsLayer};
+
+use crate::{
+    isochrone::{self, IsochroneDisplayMode, IsochroneMap},
+    routing::{LevelExploration, RealTimeFeed, RealTimeUpdates, RoutingOptions, Scenario},
+};
+
+/// The scenarios registered by the planners, indexed by their ID, with the time they were registered at.
+type Scenarios = RwLock<FxHashMap<String, (Arc<Scenario>, Instant)>>;
+
+/// A request whose search takes longer is answered with a 503 Service Unavailable.
+const SEARCH_DEADLINE_IN_SECONDS: u64 = 30;
 
-use crate::isochrone::{self, IsochroneDisplayMode, IsochroneMap};
+/// Registering a scenario beyond this number evicts the one registered the longest ago.
+const MAXIMUM_NUMBER_OF_SCENARIOS: usize = 100;
+
+/// The environment variable holding the token required by the endpoints that modify the state of the service.
+/// The endpoints are disabled if it is not set.
+const ADMIN_TOKEN_VARIABLE: &str = "HRDF_ADMIN_TOKEN";
 
 pub async fn run_service(hrdf: Hrdf) {
     log::info!("Starting the server...");
 
+    let admin_token = env::var(ADMIN_TOKEN_VARIABLE).ok().map(Arc::new);
+    let admin_token_1 = admin_token.clone();
+
+    if admin_token.is_none() {
+        log::warn!(
+            "{} is not set, the real-time updates and the scenarios cannot be modified.",
+            ADMIN_TOKEN_VARIABLE
+        );
+    }
+
+    let algorithm = Arc::new(LevelExploration::new(hrdf.data_storage()));
+    let real_time_feed = Arc::new(RealTimeFeed::new());
+    let real_time_feed_1 = Arc::clone(&real_time_feed);
+    let scenarios = Arc::new(Scenarios::default());
+    let scenarios_1 = Arc::clone(&scenarios);
     let hrdf = Arc::new(hrdf);
     let hrdf_1 = Arc::clone(&hrdf);
     let hrdf_2 = Arc::clone(&hrdf);
-    let cors = CorsLayer::new().allow_methods(Any).allow_origin(Any);
+    let hrdf_3 = Arc::clone(&hrdf);
+    // The browsers can only send the requests that do not modify the state of the service.
+    let cors = CorsLayer::new()
+        .allow_methods(AllowMethods::list([Method::GET]))
+        .allow_origin(Any);
 
     #[rustfmt::skip]
     let app = Router::new()
@@ -24,7 +70,15 @@ pub async fn run_service(hrdf: Hrdf) {
         )
         .route(
             "/isochrones",
-            get(move |params| compute_isochrones(Arc::clone(&hrdf_2), params)),
+            get(move |params| compute_isochrones(Arc::clone(&hrdf_2), Arc::clone(&algorithm), Arc::clone(&real_time_feed), Arc::clone(&scenarios), params)),
+        )
+        .route(
+            "/real_time_updates",
+            post(move |headers, body| update_real_time(Arc::clone(&hrdf_3), Arc::clone(&real_time_feed_1), admin_token.clone(), headers, body)),
+        )
+        .route(
+            "/scenarios/:scenario_id",
+            put(move |scenario_id, headers, body| register_scenario(Arc::clone(&scenarios_1), admin_token_1.clone(), scenario_id, headers, body)),
         )
         .layer(cors);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8100").await.unwrap();
@@ -56,10 +110,15 @@ struct ComputeIsochronesRequest {
     time_limit: u32,
     isochrone_interval: u32,
     display_mode: String,
+    // The baseline is computed without a scenario.
+    scenario_id: Option<String>,
 }
 
 async fn compute_isochrones(
     hrdf: Arc<Hrdf>,
+    algorithm: Arc<LevelExploration>,
+    real_time_feed: Arc<RealTimeFeed>,
+    scenarios: Arc<Scenarios>,
     Query(params): Query<ComputeIsochronesRequest>,
 ) -> Result<Json<IsochroneMap>, StatusCode> {
     // The coordinates are not checked but should be.
@@ -82,8 +141,26 @@ async fn compute_isochrones(
         return Err(StatusCode::BAD_REQUEST);
     }
 
+    let scenario = match &params.scenario_id {
+        Some(scenario_id) => match scenarios.read().unwrap().get(scenario_id) {
+            Some((scenario, _)) => Some(Arc::clone(scenario)),
+            // The scenario has not been registered.
+            None => return Err(StatusCode::NOT_FOUND),
+        },
+        None => None,
+    };
+
+    let mut options = RoutingOptions::default();
+    options.set_deadline(Some(
+        Instant::now() + StdDuration::from_secs(SEARCH_DEADLINE_IN_SECONDS),
+    ));
+    options.set_real_time_updates(Some(real_time_feed.updates()));
+    options.set_scenario(scenario);
+
     let result = isochrone::compute_isochrones(
         &hrdf,
+        algorithm.as_ref(),
+        &options,
         params.origin_point_latitude,
         params.origin_point_longitude,
         NaiveDateTime::new(params.departure_date, params.departure_time),
@@ -91,6 +168,78 @@ async fn compute_isochrones(
         Duration::minutes(params.isochrone_interval.into()),
         IsochroneDisplayMode::from_str(&params.display_mode).unwrap(),
         false,
-    );
+    )
+    // The partial isochrones are not returned, they would be mistaken for the complete ones.
+    .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
     Ok(Json(result))
 }
+
+/// Returns true if the request carries the admin token in its header "Authorization: Bearer <token>".
+fn is_authorized(admin_token: Option<&Arc<String>>, headers: &HeaderMap) -> bool {
+    let Some(admin_token) = admin_token else {
+        return false;
+    };
+
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token == admin_token.as_str())
+}
+
+/// Replaces the real-time updates used by the next requests, see RealTimeUpdates::from_json for the format of the body.
+/// The request must carry the admin token.
+async fn update_real_time(
+    hrdf: Arc<Hrdf>,
+    real_time_feed: Arc<RealTimeFeed>,
+    admin_token: Option<Arc<String>>,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    if !is_authorized(admin_token.as_ref(), &headers) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    match RealTimeUpdates::from_json(hrdf.data_storage(), body.as_bytes()) {
+        Ok(updates) => {
+            real_time_feed.set_updates(updates);
+            StatusCode::NO_CONTENT
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
+
+/// Registers the scenario under the ID, replacing the previous one, see Scenario::from_json for the format of the body.
+/// The request must carry the admin token.
+async fn register_scenario(
+    scenarios: Arc<Scenarios>,
+    admin_token: Option<Arc<String>>,
+    Path(scenario_id): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    if !is_authorized(admin_token.as_ref(), &headers) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    match Scenario::from_json(body.as_bytes()) {
+        Ok(scenario) => {
+            let mut scenarios = scenarios.write().unwrap();
+
+            if !scenarios.contains_key(&scenario_id)
+                && scenarios.len() >= MAXIMUM_NUMBER_OF_SCENARIOS
+            {
+                let oldest_scenario_id = scenarios
+                    .iter()
+                    .min_by_key(|(_, (_, registered_at))| *registered_at)
+                    .map(|(id, _)| id.clone())
+                    .unwrap();
+                scenarios.remove(&oldest_scenario_id);
+            }
+
+            scenarios.insert(scenario_id, (Arc::new(scenario), Instant::now()));
+            StatusCode::NO_CONTENT
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
@@ -0,0 +1,92 @@
+mod models;
+
+use chrono::{Duration, NaiveDateTime};
+use hrdf_parser::Hrdf;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+
+use crate::routing::{
+    find_reachable_stops_within_time_limit, from_search_result, to_search_result, AbortReason,
+    Route, RoutingAlgorithm, RoutingOptions, SearchResult,
+};
+
+pub use models::TravelTimeMatrix;
+
+/// The searches do not go beyond this duration after the departure.
+const TIME_LIMIT_IN_HOURS: i64 = 24;
+
+/// Computes the travel durations and numbers of transfers from each origin stop to each destination stop.
+/// One search is run per origin stop, the searches are run in parallel and share the routing algorithm (e.g. a Raptor built once for all of them).
+/// The duration is measured from the departure date and time, so it includes the waiting time at the origin stop.
+/// The departure date and time must be within the timetable period.
+/// If any search is aborted, the error holds the matrix with the routes found until then.
+pub fn compute_travel_time_matrix(
+    hrdf: &Hrdf,
+    algorithm: &(dyn RoutingAlgorithm + Sync),
+    origins: &[i32],
+    destinations: &[i32],
+    departure_at: NaiveDateTime,
+    options: &RoutingOptions,
+) -> SearchResult<TravelTimeMatrix> {
+    let rows: Vec<(Vec<_>, Option<AbortReason>)> = origins
+        .par_iter()
+        .map(|&origin| {
+            let (routes, abort_reason) =
+                from_search_result(find_reachable_stops_within_time_limit(
+                    hrdf,
+                    algorithm,
+                    options,
+                    origin,
+                    departure_at,
+                    Duration::hours(TIME_LIMIT_IN_HOURS),
+                    false,
+                ));
+
+            let routes: FxHashMap<i32, Route> = routes
+                .into_iter()
+                .map(|route| (route.sections().last().unwrap().arrival_stop_id(), route))
+                .collect();
+
+            let row = destinations
+                .iter()
+                .map(|&destination| {
+                    if destination == origin {
+                        return (Some(0), Some(0));
+                    }
+
+                    let Some(route) = routes.get(&destination) else {
+                        return (None, None);
+                    };
+
+                    let duration = (route.arrival_at() - departure_at).num_minutes();
+                    // A journey continuing as another journey (through service) is not a transfer.
+                    let journey_count = route
+                        .sections()
+                        .iter()
+                        .filter(|section| {
+                            !section.is_walking_trip() && !section.is_through_service()
+                        })
+                        .count();
+
+                    (
+                        Some(u32::try_from(duration).unwrap()),
+                        Some(u32::try_from(journey_count.saturating_sub(1)).unwrap()),
+                    )
+                })
+                .collect();
+
+            (row, abort_reason)
+        })
+        .collect();
+
+    let abort_reason = rows.iter().find_map(|(_, abort_reason)| *abort_reason);
+    let (durations, transfers) = rows.into_iter().flat_map(|(row, _)| row).unzip();
+
+    let matrix = TravelTimeMatrix::new(
+        origins.to_vec(),
+        destinations.to_vec(),
+        durations,
+        transfers,
+    );
+    to_search_result(matrix, abort_reason)
+}
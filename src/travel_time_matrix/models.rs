@@ -0,0 +1,86 @@
+use std::io::{self, Write};
+
+use chrono::Duration;
+use serde::Serialize;
+
+/// The travel durations and numbers of transfers between each origin and each destination.
+/// The values are stored in dense row-major arrays, one row per origin.
+#[derive(Debug, Serialize)]
+pub struct TravelTimeMatrix {
+    origins: Vec<i32>,
+    destinations: Vec<i32>,
+    durations: Vec<Option<u32>>, // In minutes.
+    transfers: Vec<Option<u32>>,
+}
+
+impl TravelTimeMatrix {
+    pub fn new(
+        origins: Vec<i32>,
+        destinations: Vec<i32>,
+        durations: Vec<Option<u32>>,
+        transfers: Vec<Option<u32>>,
+    ) -> Self {
+        assert_eq!(durations.len(), origins.len() * destinations.len());
+        assert_eq!(transfers.len(), origins.len() * destinations.len());
+
+        Self {
+            origins,
+            destinations,
+            durations,
+            transfers,
+        }
+    }
+
+    // Getters/Setters
+
+    pub fn origins(&self) -> &Vec<i32> {
+        &self.origins
+    }
+
+    pub fn destinations(&self) -> &Vec<i32> {
+        &self.destinations
+    }
+
+    // Functions
+
+    /// Returns None if the destination cannot be reached from the origin.
+    pub fn duration(&self, origin_index: usize, destination_index: usize) -> Option<Duration> {
+        self.durations[self.index(origin_index, destination_index)]
+            .map(|duration| Duration::minutes(duration.into()))
+    }
+
+    /// Returns None if the destination cannot be reached from the origin.
+    pub fn transfers(&self, origin_index: usize, destination_index: usize) -> Option<u32> {
+        self.transfers[self.index(origin_index, destination_index)]
+    }
+
+    /// Writes one line per (origin, destination) pair, the duration is in minutes and the values are empty if the destination cannot be reached.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "origin_stop_id,destination_stop_id,duration,transfers"
+        )?;
+
+        for (i, origin) in self.origins.iter().enumerate() {
+            for (j, destination) in self.destinations.iter().enumerate() {
+                let index = self.index(i, j);
+                let to_string = |value: Option<u32>| value.map_or(String::new(), |v| v.to_string());
+
+                writeln!(
+                    writer,
+                    "{},{},{},{}",
+                    origin,
+                    destination,
+                    to_string(self.durations[index]),
+                    to_string(self.transfers[index]),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn index(&self, origin_index: usize, destination_index: usize) -> usize {
+        origin_index * self.destinations.len() + destination_index
+    }
+}
@@ -1,3 +1,5 @@
+use std::f64::consts::PI;
+
 use chrono::{Days, Duration, NaiveDate, NaiveDateTime, NaiveTime};
 
 // TODO: ...
@@ -6,6 +8,10 @@ pub fn add_1_day(date: NaiveDate) -> NaiveDate {
     date.checked_add_days(Days::new(1)).unwrap()
 }
 
+pub fn sub_1_day(date: NaiveDate) -> NaiveDate {
+    date.checked_sub_days(Days::new(1)).unwrap()
+}
+
 pub fn add_minutes_to_date_time(date_time: NaiveDateTime, minutes: i64) -> NaiveDateTime {
     date_time
         .checked_add_signed(Duration::minutes(minutes))
@@ -27,3 +33,26 @@ pub fn create_time(hour: u32, minute: u32) -> NaiveTime {
 pub fn create_date_time(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
     NaiveDateTime::new(create_date(year, month, day), create_time(hour, minute))
 }
+
+fn degrees_to_radians(degrees: f64) -> f64 {
+    degrees * PI / 180.0
+}
+
+/// Returns the distance between 2 WGS84 points, in kilometers.
+pub fn haversine_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let radius_of_earth_km = 6371.0;
+
+    let lat1_rad = degrees_to_radians(lat1);
+    let lon1_rad = degrees_to_radians(lon1);
+    let lat2_rad = degrees_to_radians(lat2);
+    let lon2_rad = degrees_to_radians(lon2);
+
+    let delta_lat = lat2_rad - lat1_rad;
+    let delta_lon = lon2_rad - lon1_rad;
+
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + lat1_rad.cos() * lat2_rad.cos() * (delta_lon / 2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+
+    radius_of_earth_km * c
+}